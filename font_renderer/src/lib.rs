@@ -2,25 +2,244 @@ use font_kit::font::Font;
 use font_kit::canvas::{Canvas, Format, RasterizationOptions};
 use font_kit::family_name::FamilyName;
 use font_kit::hinting::HintingOptions;
-use font_kit::properties::Properties;
+use font_kit::properties::{Properties, Style as FontStyle, Weight};
 use font_kit::source::SystemSource;
 use pathfinder_geometry::transform2d::Transform2F;
+use pathfinder_geometry::rect::RectF;
 use pathfinder_geometry::vector::{Vector2F, Vector2I};
 use nalgebra::Vector2;
 use image::{RgbaImage,RgbImage};
 use image::buffer::ConvertBuffer;
+use parser::{Alignment, Color, Label, Style, StyledText, VerticalAlignment};
 pub struct Renderer{
     font: Font,
+    /// Faces used for styled text, see `face_index`
+    faces: Vec<Font>,
+}
+/// Glyph placed by `layout_styled`
+struct PlacedGlyph{
+    face: usize,
+    glyph: Option<u32>,
+    /// Left edge of the glyph on its baseline
+    position: Vector2F,
+    advance: f32,
+    line: usize,
+    style: Style,
 }
 impl Renderer{
+    /// Largest font size labels are drawn at, figures can ask for any size
+    pub const MAX_LABEL_SIZE: f32 = 512.0;
     pub fn new()->Self{
         let font = SystemSource::new()
             .select_best_match(&[FamilyName::Monospace], &Properties::new())
             .unwrap()
             .load()
             .unwrap();
+        // every combination of monospace, bold and italic, in the order of `face_index`
+        let faces = (0..8).map(|index|{
+            let family = if index & 0b100 != 0 {FamilyName::Monospace} else {FamilyName::SansSerif};
+            let mut properties = Properties::new();
+            if index & 0b010 != 0 {
+                properties.weight(Weight::BOLD);
+            }
+            if index & 0b001 != 0 {
+                properties.style(FontStyle::Italic);
+            }
+            SystemSource::new()
+                .select_best_match(&[family], &properties)
+                .ok()
+                .and_then(|handle| handle.load().ok())
+                .unwrap_or_else(|| font.clone())
+        }).collect();
         Self{
-            font
+            font,
+            faces,
+        }
+    }
+    /// Height in pixels of one line of text
+    pub fn line_height(&self,point_size:f32)->f32{
+        let metrics = self.font.metrics();
+        (metrics.ascent - metrics.descent + metrics.line_gap) * point_size / metrics.units_per_em as f32
+    }
+    /// Height in pixels `text` takes up when wrapped to `width` pixels
+    pub fn styled_height(&self,text:&StyledText,width:u32,point_size:f32)->u32{
+        let (_,lines) = self.layout_styled(text,width,point_size);
+        (lines as f32 * self.line_height(point_size)).ceil() as u32
+    }
+    /// Width in pixels of the longest line of `text` when it is not wrapped
+    pub fn styled_width(&self,text:&StyledText,point_size:f32)->f32{
+        let (glyphs,_) = self.layout_styled(text,u32::MAX,point_size);
+        glyphs.iter()
+            .map(|placed| placed.position.x() + placed.advance)
+            .fold(0.0,f32::max)
+    }
+    /// Draws `text` on `image`, wrapping lines at the width of the image. Each span is drawn
+    /// with the face that matches its style, glyphs past the bottom of the image are clipped.
+    pub fn write_styled_to_image(&self,image: RgbaImage,text:&StyledText,point_size:f32)->RgbaImage{
+        let width = image.width();
+        self.write_styled_region(image,text,point_size,width,Vector2F::zero())
+    }
+    /// Draws the part of `text` wrapped at `wrap_width` that falls in `image` when the top left
+    /// of the image is at `origin` in the text
+    fn write_styled_region(&self,mut image: RgbaImage,text:&StyledText,point_size:f32,wrap_width:u32,origin:Vector2F)->RgbaImage{
+        let (glyphs,_) = self.layout_styled(text,wrap_width,point_size);
+        let line_height = self.line_height(point_size);
+        let metrics = self.font.metrics();
+        let ascent = metrics.ascent * point_size / metrics.units_per_em as f32;
+        let decoration_thickness = (point_size / 14.0).max(1.0);
+        // backgrounds go first so they do not cover parts of glyphs that overhang their advance
+        for placed in glyphs.iter(){
+            let position = placed.position - origin;
+            fill_rect(&mut image,position.x(),placed.line as f32 * line_height - origin.y(),placed.advance,line_height,placed.style.background);
+        }
+        for placed in glyphs.iter(){
+            let position = placed.position - origin;
+            let foreground = placed.style.foreground;
+            if let Some(glyph) = placed.glyph{
+                self.draw_glyph(&mut image,placed.face,glyph,position,point_size,foreground);
+            }
+            if placed.style.underline{
+                fill_rect(&mut image,position.x(),position.y() + decoration_thickness,placed.advance,decoration_thickness,foreground);
+            }
+            if placed.style.strikethrough{
+                fill_rect(&mut image,position.x(),position.y() - ascent * 0.3,placed.advance,decoration_thickness,foreground);
+            }
+        }
+        image
+    }
+    /// Draws a figure's text element with its anchor at `position`, rotated around the anchor.
+    /// Labels with a size that is not positive or a rotation or position that is not finite are
+    /// skipped, sizes above `MAX_LABEL_SIZE` are drawn at it.
+    pub fn draw_label(&self,image:&mut RgbaImage,label:&Label,position:Vector2<f32>){
+        // NaN is checked before clamping as `min` would replace it
+        if label.font_size.is_nan()
+            || label.font_size <= 0.0
+            || !label.rotation.is_finite()
+            || !position.x.is_finite()
+            || !position.y.is_finite(){
+            return;
+        }
+        let font_size = label.font_size.min(Self::MAX_LABEL_SIZE);
+        // drawn upright in white first so the alpha is the coverage, then turned while blending
+        let text = StyledText::new().span(&label.text,Style{foreground: Color::WHITE,..Style::default()});
+        let width = self.styled_width(&text,font_size);
+        let height = self.styled_height(&text,u32::MAX,font_size);
+        if width <= 0.0 || height == 0{
+            return;
+        }
+        let metrics = self.font.metrics();
+        let scale = font_size / metrics.units_per_em as f32;
+        let (ascent,descent) = (metrics.ascent * scale,metrics.descent * scale);
+        let x = match label.horizontal{
+            Alignment::Left => 0.0,
+            Alignment::Center => -width / 2.0,
+            Alignment::Right => -width,
+        };
+        // the first baseline of `upright` is an ascent below its top
+        let y = match label.vertical{
+            VerticalAlignment::Top => 0.0,
+            VerticalAlignment::Middle => -(ascent - descent) / 2.0,
+            VerticalAlignment::Baseline => -ascent,
+            VerticalAlignment::Bottom => descent - ascent,
+        };
+        let transform = Transform2F::from_translation(Vector2F::new(position.x,position.y))
+            * Transform2F::from_rotation(label.rotation.to_radians())
+            * Transform2F::from_translation(Vector2F::new(x,y));
+        let inverse = transform.inverse();
+        let upright_rect = RectF::new(Vector2F::zero(),Vector2F::new(width.ceil() + 1.0,height as f32));
+        let canvas = RectF::new(Vector2F::zero(),Vector2F::new(image.width() as f32,image.height() as f32));
+        // only the part of the label that lands on the canvas is rendered
+        let bounds = match (transform * upright_rect).intersection(canvas){
+            Some(bounds) => bounds.round_out(),
+            None => return,
+        };
+        // a pixel of margin so sampling at the edge of the part interpolates with its neighbors
+        let margin = Vector2F::splat(1.0);
+        let source_bounds = inverse * bounds;
+        let source_bounds = RectF::from_points(source_bounds.origin() - margin,source_bounds.lower_right() + margin);
+        let visible = match source_bounds.intersection(upright_rect){
+            Some(visible) => visible.round_out(),
+            None => return,
+        };
+        let upright = self.write_styled_region(
+            RgbaImage::new(visible.width() as u32,visible.height() as u32),
+            &text,
+            font_size,
+            u32::MAX,
+            visible.origin(),
+        );
+        let bounds = bounds.to_i32();
+        for py in bounds.min_y()..bounds.max_y(){
+            for px in bounds.min_x()..bounds.max_x(){
+                let source = inverse * Vector2F::new(px as f32 + 0.5,py as f32 + 0.5) - visible.origin();
+                blend(image,px,py,label.color,sample_alpha(&upright,source));
+            }
+        }
+    }
+    /// Index into `faces` for a style
+    fn face_index(style:&Style)->usize{
+        (style.monospace as usize) << 2 | (style.bold as usize) << 1 | style.italic as usize
+    }
+    /// Places every character of `text`, returns the glyphs and the number of lines used
+    fn layout_styled(&self,text:&StyledText,width:u32,point_size:f32)->(Vec<PlacedGlyph>,usize){
+        let line_height = self.line_height(point_size);
+        let metrics = self.font.metrics();
+        let ascent = metrics.ascent * point_size / metrics.units_per_em as f32;
+        let mut glyphs = vec![];
+        let mut x = 0.0;
+        let mut line = 0;
+        for span in text.spans.iter(){
+            let face = Self::face_index(&span.style);
+            let font = &self.faces[face];
+            let units_per_em = font.metrics().units_per_em as f32;
+            for c in span.text.chars(){
+                if c == '\n'{
+                    x = 0.0;
+                    line += 1;
+                    continue;
+                }
+                let glyph = font.glyph_for_char(c);
+                let advance = glyph
+                    .and_then(|glyph| font.advance(glyph).ok())
+                    .map(|advance| advance.x() * point_size / units_per_em)
+                    .unwrap_or(0.0);
+                if x + advance > width as f32 && x > 0.0{
+                    x = 0.0;
+                    line += 1;
+                }
+                glyphs.push(PlacedGlyph{
+                    face,
+                    // whitespace has nothing to draw
+                    glyph: glyph.filter(|_| !c.is_whitespace()),
+                    position: Vector2F::new(x,line as f32 * line_height + ascent),
+                    advance,
+                    line,
+                    style: span.style,
+                });
+                x += advance;
+            }
+        }
+        let lines = if glyphs.is_empty() && line == 0 {0} else {line + 1};
+        (glyphs,lines)
+    }
+    /// Rasterizes a glyph on its own and blends it onto `image` in `color`
+    fn draw_glyph(&self,image:&mut RgbaImage,face:usize,glyph:u32,position:Vector2F,point_size:f32,color:Color){
+        let font = &self.faces[face];
+        let transform = Transform2F::from_translation(position);
+        let bounds = match font.raster_bounds(glyph,point_size,transform,HintingOptions::None,RasterizationOptions::GrayscaleAa){
+            Ok(bounds) if bounds.width() > 0 && bounds.height() > 0 => bounds,
+            _ => return,
+        };
+        let mut canvas = Canvas::new(bounds.size(),Format::A8);
+        let glyph_transform = Transform2F::from_translation(position - bounds.origin().to_f32());
+        if font.rasterize_glyph(&mut canvas,glyph,point_size,glyph_transform,HintingOptions::None,RasterizationOptions::GrayscaleAa).is_err(){
+            return;
+        }
+        for y in 0..bounds.height(){
+            for x in 0..bounds.width(){
+                let coverage = canvas.pixels[y as usize * canvas.stride + x as usize] as f32 / 255.0;
+                blend(image,bounds.origin_x() + x,bounds.origin_y() + y,color,coverage);
+            }
         }
     }
     pub fn write_to_image(&self,image: RgbaImage,data:&String,point_size:f32)->RgbaImage{
@@ -38,6 +257,15 @@ impl Renderer{
         let img2 = img.convert();
         return img2
     }
+    /// Width of `character` including the space after it, in pixels
+    pub fn advance(&self, character: char, point_size: f32) -> f32 {
+        let units_per_em = self.font.metrics().units_per_em as f32;
+        self.font
+            .glyph_for_char(character)
+            .and_then(|glyph| self.font.advance(glyph).ok())
+            .map(|advance| advance.x() * point_size / units_per_em)
+            .unwrap_or(0.0)
+    }
     fn write_to_canvas(&self,canvas: &mut Canvas,data: &String,point_size:f32){
         
         for (glyph,position) in self.get_string_position(&data,Vector2::new(canvas.size.x(),canvas.size.y()),point_size){
@@ -76,6 +304,51 @@ impl Renderer{
         return Vector2::new(raster_bounds.width(),raster_bounds.height());
     }
 }
+/// Blends `color` onto the pixel at `x`,`y`, scaled by `coverage`. Pixels outside of the image
+/// are ignored.
+fn blend(image:&mut RgbaImage,x:i32,y:i32,color:Color,coverage:f32){
+    if x < 0 || y < 0 || x as u32 >= image.width() || y as u32 >= image.height(){
+        return;
+    }
+    let source = color.to_bytes();
+    let alpha = source[3] as f32 / 255.0 * coverage;
+    if alpha <= 0.0{
+        return;
+    }
+    let pixel = image.get_pixel_mut(x as u32,y as u32);
+    for channel in 0..3{
+        pixel[channel] = (source[channel] as f32 * alpha + pixel[channel] as f32 * (1.0 - alpha)).round() as u8;
+    }
+    pixel[3] = (255.0 * alpha + pixel[3] as f32 * (1.0 - alpha)).round() as u8;
+}
+/// Alpha of `image` at `point`, interpolated between the centers of the nearest pixels. Outside
+/// of the image is transparent.
+fn sample_alpha(image:&RgbaImage,point:Vector2F)->f32{
+    let (x,y) = (point.x() - 0.5,point.y() - 0.5);
+    let (left,top) = (x.floor(),y.floor());
+    let alpha = |px:f32,py:f32|{
+        if px < 0.0 || py < 0.0 || px >= image.width() as f32 || py >= image.height() as f32{
+            0.0
+        }else{
+            image.get_pixel(px as u32,py as u32)[3] as f32 / 255.0
+        }
+    };
+    let (fx,fy) = (x - left,y - top);
+    let upper = alpha(left,top) * (1.0 - fx) + alpha(left + 1.0,top) * fx;
+    let lower = alpha(left,top + 1.0) * (1.0 - fx) + alpha(left + 1.0,top + 1.0) * fx;
+    upper * (1.0 - fy) + lower * fy
+}
+/// Blends a rectangle of `color` onto `image`
+fn fill_rect(image:&mut RgbaImage,x:f32,y:f32,width:f32,height:f32,color:Color){
+    if color.is_transparent(){
+        return;
+    }
+    for py in y.round() as i32..(y + height).round() as i32{
+        for px in x.round() as i32..(x + width).round() as i32{
+            blend(image,px,py,color,1.0);
+        }
+    }
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,4 +358,50 @@ mod tests {
         let r = Renderer::new();
         r.write_to_canvas(&mut canvas, &String::from("Hello World"),12.0);
     }
+    #[test]
+    fn styled_text() {
+        let r = Renderer::new();
+        let text = StyledText::new()
+            .span("plain ",Style::default())
+            .span("bold\nred",Style{bold: true,foreground: Color::RED,background: Color::BLUE,underline: true,..Style::default()});
+        let height = r.styled_height(&text,200,12.0);
+        assert_eq!(height,(2.0 * r.line_height(12.0)).ceil() as u32);
+        assert!(r.styled_width(&text,12.0) > r.styled_width(&StyledText::new().span("bold",Style::default()),12.0));
+        let image = r.write_styled_to_image(RgbaImage::new(200,height),&text,12.0);
+        // the background of the second span is drawn under the first line
+        assert!(image.pixels().any(|pixel| pixel[0] == 0 && pixel[2] == 0xff));
+        assert!(image.pixels().any(|pixel| pixel[0] > 0));
+    }
+    #[test]
+    fn label() {
+        let r = Renderer::new();
+        let mut image = RgbaImage::new(100,100);
+        // a quarter turn clockwise makes the text run down from the anchor
+        let label = Label::new("label",12.0,Color::RED).align(Alignment::Left,VerticalAlignment::Middle).rotate(90.0);
+        r.draw_label(&mut image,&label,Vector2::new(50.0,10.0));
+        let drawn: Vec<(u32,u32)> = image.enumerate_pixels().filter(|(_,_,pixel)| pixel[0] > 0).map(|(x,y,_)| (x,y)).collect();
+        assert!(!drawn.is_empty());
+        assert!(drawn.iter().all(|(x,y)| *y >= 9 && (*x as i32 - 50).abs() <= 8));
+        assert!(drawn.iter().any(|(_,y)| *y > 30));
+    }
+    #[test]
+    fn label_sizes() {
+        let r = Renderer::new();
+        let mut image = RgbaImage::new(64,64);
+        for size in [f32::NAN,-12.0,0.0].iter(){
+            r.draw_label(&mut image,&Label::new("label",*size,Color::RED),Vector2::new(0.0,0.0));
+        }
+        assert!(image.pixels().all(|pixel| pixel[3] == 0));
+        // huge sizes are drawn at the largest size, only the part on the canvas is rendered
+        for size in [f32::MAX,f32::INFINITY].iter(){
+            let mut image = RgbaImage::new(1024,1024);
+            r.draw_label(&mut image,&Label::new("l",*size,Color::RED),Vector2::new(0.0,0.0));
+            let bottom = image.enumerate_pixels().filter(|(_,_,pixel)| pixel[0] > 0).map(|(_,y,_)| y).max();
+            assert!(bottom.unwrap() > 256 && bottom.unwrap() < 1023);
+        }
+        // a label far off the canvas draws nothing
+        let mut image = RgbaImage::new(64,64);
+        r.draw_label(&mut image,&Label::new("label",12.0,Color::RED),Vector2::new(1e30,-1e30));
+        assert!(image.pixels().all(|pixel| pixel[3] == 0));
+    }
 }
@@ -22,7 +22,7 @@ use wasm_bindgen::prelude::*;
 
 use winit::event::KeyboardInput;
 mod front_end;
-use front_end::{DrawCall, Event, Scene, SceneCtor};
+use front_end::{DrawCall, Event, MouseButton, Scene, SceneCtor, SpecialKey};
 pub use front_end::{Model, Terminal, Texture};
 mod gpu;
 use gfx_hal::{prelude::*, window};
@@ -77,6 +77,8 @@ impl<B: gfx_hal::Backend, E: Scene> Context<B, E> {
     }
     fn resize(&mut self, new_size: window::Extent2D) {
         self.gpu.change_resolution(new_size);
+        self.front_end
+            .resize(nalgebra::Vector2::new(new_size.width, new_size.height));
     }
     fn draw(&mut self) {
         let draw_calls = self.front_end.get_draw_calls();
@@ -151,6 +153,10 @@ fn to_event(keyboard: KeyboardInput) -> Event {
             VirtualKeyCode::X => Event::RegularKey('x'),
             VirtualKeyCode::Y => Event::RegularKey('y'),
             VirtualKeyCode::Z => Event::RegularKey('z'),
+            // only on press, so holding the key down does not save a figure twice
+            VirtualKeyCode::Snapshot if keyboard.state == winit::event::ElementState::Pressed => {
+                Event::SpecialKey(SpecialKey::Snapshot)
+            }
             _ => Event::Unknown,
         }
     } else {
@@ -247,6 +253,32 @@ pub fn build_vulkan_context() {
                 winit::event::WindowEvent::KeyboardInput { input, .. } => {
                     context.process_event(to_event(input));
                 }
+                winit::event::WindowEvent::CursorMoved { position, .. } => {
+                    context.process_event(Event::CursorMoved(nalgebra::Vector2::new(
+                        position.x as f32,
+                        position.y as f32,
+                    )));
+                }
+                winit::event::WindowEvent::MouseInput { state, button, .. } => {
+                    context.process_event(Event::MouseInput {
+                        button: match button {
+                            winit::event::MouseButton::Left => MouseButton::Left,
+                            winit::event::MouseButton::Right => MouseButton::Right,
+                            winit::event::MouseButton::Middle => MouseButton::Middle,
+                            winit::event::MouseButton::Other(_) => MouseButton::Other,
+                        },
+                        pressed: state == winit::event::ElementState::Pressed,
+                    });
+                }
+                winit::event::WindowEvent::MouseWheel { delta, .. } => {
+                    context.process_event(Event::MouseWheel(match delta {
+                        winit::event::MouseScrollDelta::LineDelta(_, lines) => lines,
+                        // touchpads scroll in pixels, about this many make a line
+                        winit::event::MouseScrollDelta::PixelDelta(position) => {
+                            position.y as f32 / 20.0
+                        }
+                    }));
+                }
 
                 winit::event::WindowEvent::Resized(dims) => {
                     println!("resized to {:?}", dims);
@@ -160,10 +160,25 @@ pub enum SpecialKey {
     Paste,
     Cut,
 }
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other,
+}
 pub enum Event {
     RegularKey(char),
     #[allow(dead_code)]
     SpecialKey(SpecialKey),
+    /// Cursor position in pixels from the top left of the window
+    CursorMoved(Vector2<f32>),
+    MouseInput {
+        button: MouseButton,
+        pressed: bool,
+    },
+    /// Lines scrolled, positive away from the user
+    MouseWheel(f32),
     Unknown,
 }
 pub type SceneCtor<S> = (
@@ -175,4 +190,6 @@ pub type SceneCtor<S> = (
 pub trait Scene {
     fn get_draw_calls(&mut self) -> Vec<DrawCall>;
     fn process_event(&mut self, event: Event);
+    /// Called when the window changes size, in pixels
+    fn resize(&mut self, size: Vector2<u32>);
 }
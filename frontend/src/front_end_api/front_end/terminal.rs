@@ -1,43 +1,95 @@
-use super::{Event, Model, ModelId, Scene, SceneCtor, Texture, TextureId};
+use super::{Event, Model, ModelId, MouseButton, Scene, SceneCtor, SpecialKey, Texture, TextureId};
+use super::super::gpu::DEFAULT_SIZE;
 pub use super::DrawCall;
-use image::Rgba;
+use image::{imageops, Rgba, RgbaImage};
 use nalgebra::{Vector2, Vector3};
 use font_renderer::Renderer;
-use parser::{Deserializer,serializer,ParsedAST};
+use parser::{serializer,capability_reply,Capabilities,Datatypes,ElementTypes,Figure,Hello,Palette,PaletteIndex,ParsedAST,Style,StyledText};
 mod io;
 mod render_surfaces;
-use render_surfaces::{Font,TextRenderer,TextureRenderer,LineRenderer};
+use render_surfaces::{ChartRenderer,FigureRenderer,ProgressRenderer,RenderSUrface,TableRenderer,TextRenderer};
+use copypasta::{ClipboardContext, ClipboardProvider};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 pub struct Terminal {
     terminal_mesh: ModelId,
     texture: TextureId,
     input_buffer: String,
     front_end: io::FrontEnd,
-    deseralizer: Deserializer,
+    /// Output in the order it is shown. Output sent in an update keeps the `ParsedAST::Update`
+    /// so later updates can find it by id.
     rendering_buffer: Vec<(ParsedAST,DrawObject)>,
+    /// Handshake sent by the app, `None` if the app is a plain text program
+    app_hello: Option<Hello>,
     font: Renderer,
+    /// Set when output arrived since the terminal texture was last drawn
+    dirty: bool,
+    /// Top and height in pixels of each object in `rendering_buffer` when it was last drawn
+    layout: Vec<(i64,u32)>,
+    cursor: Vector2<f32>,
+    drag: Option<ColumnDrag>,
+    /// Chart the user is dragging to pan it
+    pan: Option<usize>,
+    /// Heatmap value or chart point shown next to the cursor when the terminal texture was last drawn
+    shown_tooltip: Option<String>,
+    /// Size of the window in pixels
+    size: Vector2<u32>,
+    /// Where exported figures are saved, see `EXPORT_DIRECTORY_VAR`
+    export_directory: PathBuf,
+}
+/// Table column border the user is dragging
+struct ColumnDrag{
+    object: usize,
+    column: usize,
+    start_x: f32,
+    start_width: f32,
 }
 enum DrawObject{
     Text(TextRenderer),
-    Texture(TextureRenderer),
-    Line(LineRenderer),
-}
-struct GlobalData{
-    font: Font
-
+    Table(TableRenderer),
+    Progress(ProgressRenderer),
+    Figure(FigureRenderer),
+    Chart(ChartRenderer),
 }
 impl DrawObject{
-    pub fn from_ast(ast: &ParsedAST,global: GlobalData)->Self{
+    /// Surface for output. Every output datatype needs an arm that draws it, as the terminal
+    /// advertises all of them in `Terminal::capabilities`.
+    pub fn from_ast(ast: &ParsedAST)->Self{
         match ast{
-            ParsedAST::String(s) => DrawObject::Text(TextRenderer::new(global.font,s)),
-            ParsedAST::Figure(_)=>todo!(),
+            ParsedAST::String(s) => DrawObject::Text(TextRenderer::new(
+                StyledText::new().span(s,Style::default()),
+            )),
+            ParsedAST::StyledText(text) => DrawObject::Text(TextRenderer::new(text.clone())),
+            ParsedAST::Table(table) => DrawObject::Table(TableRenderer::new(table.clone())),
+            ParsedAST::Progress(progress) => DrawObject::Progress(ProgressRenderer::new(progress.clone())),
+            ParsedAST::Update(_,ast) => Self::from_ast(ast),
+            ParsedAST::Figure(figure) => DrawObject::Figure(FigureRenderer::new(figure.clone())),
+            ParsedAST::Chart(chart) => DrawObject::Chart(ChartRenderer::new(chart.clone())),
+            ParsedAST::Hello(_)
+            | ParsedAST::CapabilityQuery
+            | ParsedAST::Capabilities(_)
+            | ParsedAST::Palette(_)
+            | ParsedAST::Delete(_)=>{
+                unreachable!("control packets are handled by the terminal")
+            }
         }
     }
-    pub fn get_draw_calls(&mut self)->Vec<DrawCall>{
-        todo!("get draw calls of sub objects")
-
+    fn surface(&self)->&dyn RenderSUrface{
+        match self{
+            DrawObject::Text(text)=>text,
+            DrawObject::Table(table)=>table,
+            DrawObject::Progress(progress)=>progress,
+            DrawObject::Figure(figure)=>figure,
+            DrawObject::Chart(chart)=>chart,
+        }
     }
 }
 impl Terminal {
+    /// Size text is drawn at
+    const POINT_SIZE: f32 = 12.0;
+    /// Variable naming the directory figures are exported to, the terminal's working directory
+    /// if it is not set
+    const EXPORT_DIRECTORY_VAR: &'static str = "GSH_EXPORT_DIR";
     pub fn new() -> SceneCtor<Self> {
         #[rustfmt::skip]
         let model = Model {
@@ -56,50 +108,374 @@ impl Terminal {
         (
             vec![model],
             vec![Texture { image }],
-            Box::new(|model, textures| Terminal {
-                terminal_mesh: model[0].clone(),
-                texture: textures[0].clone(),
-                input_buffer: "".to_string(),
-                font: Renderer::new(),
-                rendering_buffer: vec![],
-                deseralizer: Deserializer::new(),
-                front_end: io::FrontEnd::new(io::Settings{command: "../test_app/target/release/test_app".to_string()}),
+            Box::new(|model, textures| {
+                let font = Renderer::new();
+                let pixel_width = DEFAULT_SIZE.width;
+                // a font without a width for 'm' would make the column count saturate
+                let advance = font.advance('m',Self::POINT_SIZE).max(1.0);
+                let columns = ((pixel_width as f32/advance) as u32).max(1);
+                Terminal {
+                    terminal_mesh: model[0].clone(),
+                    texture: textures[0].clone(),
+                    input_buffer: "".to_string(),
+                    font,
+                    rendering_buffer: vec![],
+                    app_hello: None,
+                    dirty: true,
+                    layout: vec![],
+                    cursor: Vector2::new(0.0,0.0),
+                    drag: None,
+                    pan: None,
+                    shown_tooltip: None,
+                    size: Vector2::new(DEFAULT_SIZE.width,DEFAULT_SIZE.height),
+                    export_directory: std::env::var_os(Self::EXPORT_DIRECTORY_VAR)
+                        .map(PathBuf::from)
+                        .unwrap_or_else(|| PathBuf::from(".")),
+                    front_end: io::FrontEnd::new(io::Settings{
+                        command: "../test_app/target/release/test_app".to_string(),
+                        columns,
+                        pixel_width,
+                    }),
+                }
             }),
         )
     }
 }
 impl Terminal{
+    /// What the terminal can draw, sent to the app when it asks. The output datatypes are the
+    /// ones `DrawObject::from_ast` makes a surface for, the rest are control packets the
+    /// terminal handles itself.
+    fn capabilities()->Capabilities{
+        let output = Datatypes::ALL.iter()
+            .filter(|datatype|datatype.is_output())
+            .fold(0,|bits,datatype|bits | datatype.bit());
+        Capabilities{
+            datatypes: output
+                | Datatypes::Update.bit()
+                | Datatypes::Delete.bit()
+                | Datatypes::Palette.bit(),
+            figure_elements: ElementTypes::ALL.iter().fold(0,|bits,element| bits | element.bit()),
+            // bigger figures are scaled down to this when they are drawn
+            max_image_size: Vector2::new(Figure::MAX_SIZE,Figure::MAX_SIZE),
+            color_depth: 32,
+        }
+    }
+    /// Theme colors sent to the app along with the capabilities, the terminal draws with the
+    /// default palette
+    fn palette()->Palette{
+        Palette::default()
+    }
     /// reformats layout every frame. Will do things like merge text boxes
     fn process_layout(&self){
         // yes I know this looks bad but I need to check length every loop inorder to make
         // sure that the program does not walk off of the end of the array
     }
-
+    /// Draws the output stacked top to bottom, scrolled so the newest output is at the bottom
+    fn render(&mut self)->RgbaImage{
+        let width = self.size.x;
+        let height = self.size.y;
+        let mut canvas = RgbaImage::from_pixel(width, height, Self::palette().get(PaletteIndex::Background).into());
+        let heights:Vec<u32> = self.rendering_buffer.iter()
+            .map(|(_,draw_object)|draw_object.surface().height(&self.font,width))
+            .collect();
+        let total:u32 = heights.iter().sum();
+        let mut y = height as i64 - total.max(height) as i64;
+        self.layout.clear();
+        for ((_,draw_object),surface_height) in self.rendering_buffer.iter().zip(heights){
+            self.layout.push((y,surface_height));
+            if y + (surface_height as i64) > 0 && surface_height > 0{
+                let image = draw_object.surface().render(
+                    &self.font,
+                    RgbaImage::from_pixel(width, surface_height, Rgba([0, 0, 0, 0])),
+                );
+                // surfaces scrolled partly off the top only have their bottom drawn
+                let hidden = (-y).max(0) as u32;
+                let visible = imageops::crop_imm(&image,0,hidden,width,surface_height - hidden).to_image();
+                imageops::overlay(&mut canvas,&visible,0,(y + hidden as i64) as u32);
+            }
+            y += surface_height as i64;
+        }
+        self.shown_tooltip = self.hovered_tooltip();
+        if let Some(text) = &self.shown_tooltip{
+            self.draw_tooltip(&mut canvas,text);
+        }
+        canvas
+    }
+    /// Draws `text` in a box beside the cursor, moved inside of the canvas if it would stick out
+    fn draw_tooltip(&self,canvas:&mut RgbaImage,text:&str){
+        const PADDING:u32 = 4;
+        const CURSOR_OFFSET:i64 = 12;
+        let text = StyledText::new().span(text,Style::default());
+        // a pixel wider than the text so it never wraps
+        let text_width = self.font.styled_width(&text,Self::POINT_SIZE).ceil() as u32 + 1;
+        let text_height = self.font.line_height(Self::POINT_SIZE).ceil() as u32;
+        let mut tooltip = RgbaImage::from_pixel(
+            text_width + 2 * PADDING,
+            text_height + 2 * PADDING,
+            Self::palette().get(PaletteIndex::Muted).into(),
+        );
+        let label = self.font.write_styled_to_image(RgbaImage::new(text_width,text_height),&text,Self::POINT_SIZE);
+        imageops::overlay(&mut tooltip,&label,PADDING,PADDING);
+        let x = (self.cursor.x as i64 + CURSOR_OFFSET).min(canvas.width() as i64 - tooltip.width() as i64).max(0);
+        let y = (self.cursor.y as i64 + CURSOR_OFFSET).min(canvas.height() as i64 - tooltip.height() as i64).max(0);
+        imageops::overlay(canvas,&tooltip,x as u32,y as u32);
+    }
+    /// Id an app gave to output, `None` for output that was not sent in an update
+    fn output_id(ast:&ParsedAST)->Option<u32>{
+        match ast{
+            ParsedAST::Update(id,_)=>Some(*id),
+            _=>None,
+        }
+    }
+    /// Object under `position` along with `position` relative to the object's top left
+    fn object_at(&self,position:Vector2<f32>)->Option<(usize,Vector2<f32>)>{
+        self.layout.iter().enumerate().find_map(|(object,(top,height))|{
+            let y = position.y - *top as f32;
+            if y >= 0.0 && y < *height as f32{
+                Some((object,Vector2::new(position.x,y)))
+            }else{
+                None
+            }
+        })
+    }
+    /// Value of the heatmap cell or the chart point under the cursor
+    fn hovered_tooltip(&self)->Option<String>{
+        let (object,local) = self.object_at(self.cursor)?;
+        match &self.rendering_buffer[object].1{
+            DrawObject::Figure(figure)=>figure.value_at(local,self.size.x).map(|value|value.to_string()),
+            DrawObject::Chart(chart)=>chart.tooltip_at(local,self.size.x),
+            _=>None,
+        }
+    }
+    /// Chart under the cursor along with the cursor relative to its top left
+    fn hovered_chart(&mut self)->Option<(&mut ChartRenderer,Vector2<f32>)>{
+        let (object,local) = self.object_at(self.cursor)?;
+        match &mut self.rendering_buffer[object].1{
+            DrawObject::Chart(chart)=>Some((chart,local)),
+            _=>None,
+        }
+    }
+    /// Table under the cursor
+    fn hovered_table(&self)->Option<(usize,Vector2<f32>)>{
+        let (object,local) = self.object_at(self.cursor)?;
+        match self.rendering_buffer[object].1{
+            DrawObject::Table(_)=>Some((object,local)),
+            _=>None,
+        }
+    }
+    /// Clicking a table header sorts by that column, pressing on a border between headers
+    /// starts resizing the column to its left
+    fn press_table(&mut self,object:usize,local:Vector2<f32>){
+        if let DrawObject::Table(table) = &mut self.rendering_buffer[object].1{
+            if local.y >= TableRenderer::row_height(&self.font) as f32{
+                return;
+            }
+            if let Some(column) = table.border_at(&self.font,local.x){
+                self.drag = Some(ColumnDrag{
+                    object,
+                    column,
+                    start_x: local.x,
+                    start_width: table.column_widths(&self.font)[column],
+                });
+            }else if let Some(column) = table.column_at(&self.font,local.x){
+                table.sort(column);
+                self.dirty = true;
+            }
+        }
+    }
+    /// Copies the table under the cursor to the clipboard, as CSV or as TSV
+    fn copy_table(&mut self,csv:bool){
+        let (object,_) = match self.hovered_table(){
+            Some(hovered)=>hovered,
+            None=>return,
+        };
+        if let DrawObject::Table(table) = &self.rendering_buffer[object].1{
+            let text = if csv {table.table().to_csv()} else {table.table().to_tsv()};
+            let copied = ClipboardContext::new().and_then(|mut clipboard|clipboard.set_contents(text));
+            if let Err(error) = copied{
+                self.status(&format!("failed to copy table: {}",error),PaletteIndex::Error);
+            }
+        }
+    }
+    /// Saves the figure or chart under the cursor to `export_directory`, both as an SVG for
+    /// reports and as a PNG of what is on screen. The outcome is shown as a line of output.
+    fn export_figure(&mut self){
+        let (object,_) = match self.object_at(self.cursor){
+            Some(hovered)=>hovered,
+            None=>return,
+        };
+        let figure = match &self.rendering_buffer[object].1{
+            DrawObject::Figure(figure)=>figure.figure().clone(),
+            DrawObject::Chart(chart)=>chart.chart().to_figure(&chart.layout(self.size.x),&Self::palette()),
+            _=>return,
+        };
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|time|time.as_millis()).unwrap_or(0);
+        let path = self.export_directory.join(format!("figure-{}",millis));
+        let (svg,png) = (path.with_extension("svg"),path.with_extension("png"));
+        // drawn at the size it has on screen, which is never bigger than `Figure::MAX_SIZE`
+        let image = figure.rasterize_at_with(
+            figure.size_for(self.size.x),
+            |canvas,label,position|self.font.draw_label(canvas,label,position),
+        );
+        let saved = std::fs::write(&svg,figure.to_svg())
+            .map_err(|error|error.to_string())
+            .and_then(|()|image.save(&png).map_err(|error|error.to_string()));
+        match saved{
+            Ok(())=>self.status(&format!("saved figure as {} and {}",svg.display(),png.display()),PaletteIndex::Muted),
+            Err(error)=>self.status(
+                &format!("failed to save figure to {}: {}",self.export_directory.display(),error),
+                PaletteIndex::Error,
+            ),
+        }
+    }
+    /// Adds a line from the terminal itself to the output, like the result of an export
+    fn status(&mut self,message:&str,color:PaletteIndex){
+        let style = Style{foreground: Self::palette().get(color),..Style::default()};
+        let text = ParsedAST::StyledText(StyledText::new().span(message,style));
+        let draw_object = DrawObject::from_ast(&text);
+        self.rendering_buffer.push((text,draw_object));
+        self.dirty = true;
+    }
 }
 impl Scene for Terminal {
     fn get_draw_calls(&mut self) -> Vec<DrawCall> {
         self.front_end.send_input(serializer::build_text(self.input_buffer.chars().map(|c|c).collect()));
 
-        if let Some(new_ast) = self.deseralizer.parse(&mut self.front_end.poll_output()).ok(){
-            let mut ast = new_ast.iter().map(|a| (a.clone(),DrawObject::from_ast(a))).collect();
-            self.rendering_buffer.append(&mut ast);
-
+        for ast in self.front_end.poll_output(){
+            match ast{
+                ParsedAST::Hello(hello)=>self.app_hello = Some(hello),
+                ParsedAST::CapabilityQuery=>{
+                    for reply in capability_reply(self.app_hello.as_ref(),Self::capabilities(),Self::palette()){
+                        self.front_end.send_input(serializer::serialize(&reply));
+                    }
+                }
+                // capabilities describe what a frontend can draw, the terminal draws the same
+                // things whatever an app claims to support
+                ParsedAST::Capabilities(_)=>{}
+                // the terminal draws with its own theme
+                ParsedAST::Palette(_)=>{}
+                ParsedAST::Delete(id)=>{
+                    self.rendering_buffer.retain(|(shown,_)|Self::output_id(shown) != Some(id));
+                    // the dragged table or chart may have moved or be gone
+                    self.drag = None;
+                    self.pan = None;
+                    self.dirty = true;
+                }
+                ast=>{
+                    let draw_object = DrawObject::from_ast(&ast);
+                    let shown = Self::output_id(&ast).and_then(|id|{
+                        self.rendering_buffer.iter().position(|(shown,_)|Self::output_id(shown) == Some(id))
+                    });
+                    match shown{
+                        Some(index)=>{
+                            self.rendering_buffer[index] = (ast,draw_object);
+                            self.drag = None;
+                            self.pan = None;
+                        }
+                        None=>self.rendering_buffer.push((ast,draw_object)),
+                    }
+                    self.dirty = true;
+                }
+            }
         }
         self.process_layout();
+        let animated = self.rendering_buffer.iter().any(|(_,draw_object)|match draw_object{
+            DrawObject::Progress(progress)=>progress.animated(),
+            _=>false,
+        });
+        if animated{
+            self.dirty = true;
+        }
         let mut draw = vec![];
-        for (_,draw_object) in self.rendering_buffer.iter_mut(){
-            draw.append(&mut draw_object.get_draw_calls());
-
+        if self.dirty{
+            draw.push(DrawCall::UpdateTexture{
+                texture: self.texture.clone(),
+                new_texture: self.render(),
+            });
+            self.dirty = false;
         }
+        draw.push(DrawCall::DrawModel{
+            model: self.terminal_mesh.clone(),
+            texture: self.texture.clone(),
+            position: Vector3::new(0.0,0.0,0.0),
+        });
         return draw
     }
+    fn resize(&mut self, size: Vector2<u32>) {
+        self.size = size;
+        self.dirty = true;
+    }
     fn process_event(&mut self, event: Event) {
         match event {
             Event::RegularKey(c) => {
                 println!("pressed: {}", c);
                 self.input_buffer.push(c);
             }
+            // TSV pastes into spreadsheets as cells, right click copies CSV instead
+            Event::SpecialKey(SpecialKey::Copy) => self.copy_table(false),
+            Event::SpecialKey(SpecialKey::Snapshot) => self.export_figure(),
             Event::SpecialKey(k) => println!("special key {:?}", k),
+            Event::CursorMoved(position) => {
+                let previous = std::mem::replace(&mut self.cursor,position);
+                if let Some(object) = self.pan{
+                    if let DrawObject::Chart(chart) = &mut self.rendering_buffer[object].1{
+                        chart.pan(previous,position,self.size.x);
+                        self.dirty = true;
+                    }
+                }
+                // redraw to move the tooltip along with the cursor
+                if self.shown_tooltip.is_some() || self.hovered_tooltip().is_some(){
+                    self.dirty = true;
+                }
+                if let Some(drag) = &self.drag{
+                    if let DrawObject::Table(table) = &mut self.rendering_buffer[drag.object].1{
+                        table.resize(drag.column,drag.start_width + position.x - drag.start_x);
+                        self.dirty = true;
+                    }
+                }
+            }
+            Event::MouseInput{button: MouseButton::Left,pressed: true} => {
+                if let Some((object,local)) = self.hovered_table(){
+                    self.press_table(object,local);
+                }
+                self.pan = match self.object_at(self.cursor){
+                    Some((object,_)) => match self.rendering_buffer[object].1{
+                        DrawObject::Chart(_)=>Some(object),
+                        _=>None,
+                    },
+                    None=>None,
+                };
+            }
+            Event::MouseInput{button: MouseButton::Left,pressed: false} => {
+                self.drag = None;
+                self.pan = None;
+            }
+            // middle click resets the view of charts and changes the colormap of heatmaps
+            Event::MouseInput{button: MouseButton::Middle,pressed: true} => {
+                if let Some((object,_)) = self.object_at(self.cursor){
+                    match &mut self.rendering_buffer[object].1{
+                        DrawObject::Chart(chart)=>{
+                            chart.reset();
+                            self.dirty = true;
+                        }
+                        DrawObject::Figure(figure)=>{
+                            if figure.cycle_colormap(){
+                                self.dirty = true;
+                            }
+                        }
+                        _=>{}
+                    }
+                }
+            }
+            Event::MouseWheel(lines) => {
+                let width = self.size.x;
+                if let Some((chart,local)) = self.hovered_chart(){
+                    chart.zoom(local,width,lines);
+                    self.dirty = true;
+                }
+            }
+            // right click copies tables, print screen saves figures
+            Event::MouseInput{button: MouseButton::Right,pressed: true} => self.copy_table(true),
             _ => (),
         }
     }
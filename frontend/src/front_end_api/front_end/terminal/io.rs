@@ -1,44 +1,55 @@
+use parser::environment::Environment;
+use parser::{Deserializer, ParsedAST};
 use std::io::prelude::*;
 use std::process::{ChildStdin, ChildStdout, Command, Stdio};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 pub struct FrontEnd {
     stdin: ChildStdin,
-    stdout: Receiver<u8>,
+    stdout: Receiver<ParsedAST>,
 }
 pub struct ProcessStdout {
     stdout: ChildStdout,
-    stdout_return: Sender<u8>,
+    stdout_return: Sender<ParsedAST>,
 }
 impl ProcessStdout {
-    pub fn new(stdout: ChildStdout, stdout_return: Sender<u8>) -> Self {
+    pub fn new(stdout: ChildStdout, stdout_return: Sender<ParsedAST>) -> Self {
         Self {
             stdout,
             stdout_return,
         }
     }
     /// Starts the operation of second thread
-    pub fn start_loop(&mut self) {
-        let mut buffer: [u8; 1] = [0];
-        loop {
-            if let Some(_) = self.stdout.read_exact(&mut buffer).err() {
-                break;
-            }
-            if let Some(_) = self.stdout_return.send(buffer[0]).err() {
-                break;
+    pub fn start_loop(self) {
+        // programs that do not start with a hello are shown as plain text
+        for node in Deserializer::with_handshake().into_reader(self.stdout) {
+            match node {
+                Ok(node) => {
+                    if let Some(_) = self.stdout_return.send(node).err() {
+                        break;
+                    }
+                }
+                Err(error) => eprintln!("skipping packet: {}", error),
             }
         }
     }
 }
 impl FrontEnd {
     pub fn new(settings: Settings) -> Self {
+        let environment = Environment::new(
+            settings.columns,
+            settings.pixel_width,
+            Self::session_id(),
+        );
         let child = Command::new(settings.command)
+            .envs(environment.to_vars())
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .spawn()
             .expect("??");
         let (sender, stdout) = channel();
-        let mut other_thread = ProcessStdout::new(child.stdout.unwrap(), sender);
+        let other_thread = ProcessStdout::new(child.stdout.unwrap(), sender);
         thread::spawn(move || {
             other_thread.start_loop();
         });
@@ -48,17 +59,28 @@ impl FrontEnd {
             stdin: child.stdin.unwrap(),
         }
     }
+    /// Id that is unique to this terminal session
+    fn session_id() -> String {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos())
+            .unwrap_or(0);
+        format!("{}-{:x}", std::process::id(), started)
+    }
     pub fn send_input(&mut self, input: Vec<u8>) {
         self.stdin.write(&input);
     }
-    pub fn poll_output(&self) -> Vec<u8> {
-        let mut output = vec![];
-        if let Some(byte) = self.stdout.try_recv().ok() {
-            output.push(byte);
-        }
-        return output;
+    /// Gets the packets the app has written since the last call
+    pub fn poll_output(&self) -> Vec<ParsedAST> {
+        self.stdout.try_iter().collect()
     }
 }
+/// Settings the app is started with. The sizes are only passed to the app at startup, see
+/// `parser::environment`.
 pub struct Settings {
     pub command: String,
+    /// Width of the terminal in monospace characters
+    pub columns: u32,
+    /// Width of the terminal in pixels
+    pub pixel_width: u32,
 }
@@ -1,17 +1,369 @@
-use super::super::{DrawCall, ModelId, TextureId};
-pub trait RenderSUrface {}
-pub struct Font {}
-pub struct TextRenderer {}
+use super::Terminal;
+use font_renderer::Renderer;
+use image::{imageops, Rgba, RgbaImage};
+use nalgebra::{Point2, Vector2};
+use parser::{Alignment, Chart, ChartLayout, ChartRange, Figure, Progress, Span, Style, StyledText, Table};
+use std::cell::RefCell;
+use std::time::Instant;
+/// Output drawn on the cpu into its own strip of the terminal texture
+pub trait RenderSUrface {
+    /// Height in pixels of the surface when it is `width` pixels wide
+    fn height(&self, font: &Renderer, width: u32) -> u32;
+    /// Draws the surface onto `image`, which is sized by `height`
+    fn render(&self, font: &Renderer, image: RgbaImage) -> RgbaImage;
+}
+pub struct TextRenderer {
+    text: StyledText,
+}
 impl TextRenderer {
-    pub fn new(font: &Font, string: String) -> Self {
-        unimplemented!()
+    pub fn new(text: StyledText) -> Self {
+        Self { text }
+    }
+    /// Appends unstyled text
+    pub fn add_text(&mut self, data: String) {
+        self.text.spans.push(Span {
+            text: data,
+            style: Style::default(),
+        });
+    }
+}
+impl RenderSUrface for TextRenderer {
+    fn height(&self, font: &Renderer, width: u32) -> u32 {
+        font.styled_height(&self.text, width, Terminal::POINT_SIZE)
+    }
+    fn render(&self, font: &Renderer, image: RgbaImage) -> RgbaImage {
+        font.write_styled_to_image(image, &self.text, Terminal::POINT_SIZE)
+    }
+}
+/// Table with columns the user can resize and sort by
+pub struct TableRenderer {
+    table: Table,
+    /// Widths set by dragging a column border, `None` for columns sized to their contents
+    widths: Vec<Option<f32>>,
+    /// Column the rows are sorted by and whether they are ascending
+    sorted: Option<(usize, bool)>,
+    /// Result of `column_widths`, cleared when a column is resized or sorted. The terminal draws
+    /// everything in one font so the widths do not depend on which one is passed in.
+    cached_widths: RefCell<Option<Vec<f32>>>,
+}
+impl TableRenderer {
+    /// Space between the text of a cell and its borders
+    const PADDING: f32 = 6.0;
+    /// Columns can not be dragged narrower than this
+    pub const MIN_WIDTH: f32 = 2.0 * Self::PADDING + 8.0;
+    const HEADER_BACKGROUND: Rgba<u8> = Rgba([40, 40, 60, 255]);
+    const BORDER: Rgba<u8> = Rgba([90, 90, 110, 255]);
+    pub fn new(table: Table) -> Self {
+        Self {
+            widths: vec![None; table.columns().len()],
+            table,
+            sorted: None,
+            cached_widths: RefCell::new(None),
+        }
+    }
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+    /// Height in pixels of the header and of every row
+    pub fn row_height(font: &Renderer) -> u32 {
+        (font.line_height(Terminal::POINT_SIZE) + Self::PADDING).ceil() as u32
+    }
+    /// Width in pixels of every column
+    pub fn column_widths(&self, font: &Renderer) -> Vec<f32> {
+        if let Some(widths) = &*self.cached_widths.borrow() {
+            return widths.clone();
+        }
+        let widths: Vec<f32> = self
+            .widths
+            .iter()
+            .enumerate()
+            .map(|(column, width)| {
+                width.unwrap_or_else(|| {
+                    let header = font.styled_width(&self.header_text(column), Terminal::POINT_SIZE);
+                    self.table
+                        .rows()
+                        .iter()
+                        .map(|row| font.styled_width(&Self::cell_text(&row[column].to_string()), Terminal::POINT_SIZE))
+                        .fold(header, f32::max)
+                        .ceil()
+                        + 2.0 * Self::PADDING
+                })
+            })
+            .collect();
+        *self.cached_widths.borrow_mut() = Some(widths.clone());
+        widths
+    }
+    /// Column whose right border is within a few pixels of `x`
+    pub fn border_at(&self, font: &Renderer, x: f32) -> Option<usize> {
+        let mut right = 0.0;
+        for (column, width) in self.column_widths(font).into_iter().enumerate() {
+            right += width;
+            if (x - right).abs() <= 3.0 {
+                return Some(column);
+            }
+        }
+        None
+    }
+    pub fn column_at(&self, font: &Renderer, x: f32) -> Option<usize> {
+        let mut right = 0.0;
+        for (column, width) in self.column_widths(font).into_iter().enumerate() {
+            right += width;
+            if x < right {
+                return Some(column);
+            }
+        }
+        None
+    }
+    pub fn resize(&mut self, column: usize, width: f32) {
+        self.widths[column] = Some(width.max(Self::MIN_WIDTH));
+        *self.cached_widths.get_mut() = None;
+    }
+    /// Sorts by `column`, ascending the first time and flipping the order on every call after
+    pub fn sort(&mut self, column: usize) {
+        let ascending = match self.sorted {
+            Some((sorted, ascending)) if sorted == column => !ascending,
+            _ => true,
+        };
+        self.table.sort_by(column, ascending);
+        self.sorted = Some((column, ascending));
+        // the header of the sorted column gains an arrow
+        *self.cached_widths.get_mut() = None;
+    }
+    fn header_text(&self, column: usize) -> StyledText {
+        let name = &self.table.columns()[column].name;
+        let text = match self.sorted {
+            Some((sorted, true)) if sorted == column => format!("{} ^", name),
+            Some((sorted, false)) if sorted == column => format!("{} v", name),
+            _ => name.clone(),
+        };
+        StyledText::new().span(&text, Style { bold: true, ..Style::default() })
+    }
+    fn cell_text(text: &str) -> StyledText {
+        StyledText::new().span(text, Style::default())
+    }
+    /// Draws `text` inside of the cell at `x`,`y` that is `width` pixels wide
+    fn draw_cell(font: &Renderer, image: &mut RgbaImage, text: &StyledText, alignment: Alignment, x: f32, y: u32, width: f32) {
+        let inner = (width - 2.0 * Self::PADDING).max(0.0);
+        let text_width = font.styled_width(text, Terminal::POINT_SIZE).ceil();
+        if inner < 1.0 || text_width < 1.0 {
+            return;
+        }
+        let offset = match alignment {
+            Alignment::Left => 0.0,
+            Alignment::Right => (inner - text_width).max(0.0),
+            Alignment::Center => ((inner - text_width) / 2.0).max(0.0),
+        };
+        // wide enough that the text never wraps, then cut down to the cell
+        let rendered = font.write_styled_to_image(
+            RgbaImage::new(text_width as u32 + 1, Self::row_height(font)),
+            text,
+            Terminal::POINT_SIZE,
+        );
+        let visible = imageops::crop_imm(&rendered, 0, 0, inner.min(text_width + 1.0) as u32, rendered.height()).to_image();
+        imageops::overlay(image, &visible, (x + Self::PADDING + offset) as u32, y + Self::PADDING as u32 / 2);
+    }
+}
+impl RenderSUrface for TableRenderer {
+    fn height(&self, font: &Renderer, _width: u32) -> u32 {
+        Self::row_height(font) * (self.table.rows().len() as u32 + 1) + 1
+    }
+    fn render(&self, font: &Renderer, mut image: RgbaImage) -> RgbaImage {
+        let row_height = Self::row_height(font);
+        let widths = self.column_widths(font);
+        let total_width: f32 = widths.iter().sum();
+        fill(&mut image, 0, 0, total_width as u32, row_height, Self::HEADER_BACKGROUND);
+        let mut x = 0.0;
+        for (column, width) in widths.iter().enumerate() {
+            let alignment = self.table.columns()[column].alignment;
+            Self::draw_cell(font, &mut image, &self.header_text(column), alignment, x, 0, *width);
+            for (row, cells) in self.table.rows().iter().enumerate() {
+                let text = Self::cell_text(&cells[column].to_string());
+                let y = row_height * (row as u32 + 1);
+                Self::draw_cell(font, &mut image, &text, alignment, x, y, *width);
+            }
+            x += width;
+            fill(&mut image, x as u32, 0, 1, image.height(), Self::BORDER);
+        }
+        fill(&mut image, 0, row_height, total_width as u32, 1, Self::BORDER);
+        image
+    }
+}
+/// Progress bar as wide as the terminal, with the label and status above it
+pub struct ProgressRenderer {
+    progress: Progress,
+    /// When the spinner of indeterminate progress started moving
+    started: Instant,
+}
+impl ProgressRenderer {
+    const PADDING: u32 = 6;
+    const BAR_HEIGHT: u32 = 10;
+    const TRACK: Rgba<u8> = Rgba([50, 50, 70, 255]);
+    const FILL: Rgba<u8> = Rgba([80, 160, 255, 255]);
+    /// Seconds the spinner takes to go across the bar and back
+    const SPINNER_PERIOD: f32 = 2.0;
+    pub fn new(progress: Progress) -> Self {
+        Self {
+            progress,
+            started: Instant::now(),
+        }
+    }
+    /// Whether the bar moves on its own and has to be redrawn every frame
+    pub fn animated(&self) -> bool {
+        self.progress.total.is_none()
     }
-    pub fn add_text(&self, data: String) {
-        unimplemented!()
+    fn text(&self) -> StyledText {
+        StyledText::new()
+            .span(&self.progress.label, Style { bold: true, ..Style::default() })
+            .span(&format!("  {}", self.progress.status()), Style::default())
     }
-    pub fn get_draw_call(&self) -> DrawCall {
-        unimplemented!()
+}
+impl RenderSUrface for ProgressRenderer {
+    fn height(&self, font: &Renderer, _width: u32) -> u32 {
+        font.line_height(Terminal::POINT_SIZE).ceil() as u32 + Self::BAR_HEIGHT + 3 * Self::PADDING
+    }
+    fn render(&self, font: &Renderer, mut image: RgbaImage) -> RgbaImage {
+        let line_height = font.line_height(Terminal::POINT_SIZE).ceil() as u32;
+        let width = image.width().saturating_sub(2 * Self::PADDING);
+        if width == 0 {
+            return image;
+        }
+        let text = font.write_styled_to_image(RgbaImage::new(width, line_height), &self.text(), Terminal::POINT_SIZE);
+        imageops::overlay(&mut image, &text, Self::PADDING, Self::PADDING);
+        let bar_y = 2 * Self::PADDING + line_height;
+        fill(&mut image, Self::PADDING, bar_y, width, Self::BAR_HEIGHT, Self::TRACK);
+        match self.progress.fraction() {
+            Some(fraction) => {
+                let filled = (fraction * width as f32) as u32;
+                fill(&mut image, Self::PADDING, bar_y, filled, Self::BAR_HEIGHT, Self::FILL);
+            }
+            None => {
+                // a block bouncing between the ends of the bar
+                let block = width / 5;
+                let phase = self.started.elapsed().as_secs_f32() % Self::SPINNER_PERIOD / Self::SPINNER_PERIOD;
+                let position = 1.0 - (2.0 * phase - 1.0).abs();
+                let x = Self::PADDING + (position * (width - block) as f32) as u32;
+                fill(&mut image, x, bar_y, block, Self::BAR_HEIGHT, Self::FILL);
+            }
+        }
+        image
+    }
+}
+/// Figure drawn in the top left of its strip, at its own size or sized to the strip's width if it
+/// has a view box. The strip is redrawn every frame so figures follow the width of the terminal.
+pub struct FigureRenderer {
+    figure: Figure,
+}
+impl FigureRenderer {
+    pub fn new(figure: Figure) -> Self {
+        Self { figure }
+    }
+    pub fn figure(&self) -> &Figure {
+        &self.figure
+    }
+    /// Recolors the heatmaps with the colormap after the one they have now, returns false if the
+    /// figure has no heatmaps
+    pub fn cycle_colormap(&mut self) -> bool {
+        match self.figure.colormap() {
+            Some(colormap) => {
+                self.figure.set_colormap(colormap.next());
+                true
+            }
+            None => false,
+        }
+    }
+    /// Value of the heatmap cell at `position`, in pixels from the top left of the figure when it
+    /// is drawn `width` pixels wide
+    pub fn value_at(&self, position: Vector2<f32>, width: u32) -> Option<f32> {
+        let size = self.figure.size_for(width);
+        // heatmaps are cut off at the edge of the figure
+        if position.x < 0.0 || position.x >= size.x as f32 || position.y >= size.y as f32 {
+            return None;
+        }
+        let transform = self.figure.transform_for(size);
+        let point = transform.try_inverse()?.transform_point(&Point2::from(position));
+        self.figure.heatmap_value_at(point.coords)
+    }
+}
+impl RenderSUrface for FigureRenderer {
+    fn height(&self, _font: &Renderer, width: u32) -> u32 {
+        self.figure.size_for(width).y
+    }
+    fn render(&self, font: &Renderer, mut image: RgbaImage) -> RgbaImage {
+        let size = self.figure.size_for(image.width());
+        let figure = self
+            .figure
+            .rasterize_at_with(size, |canvas, label, position| font.draw_label(canvas, label, position));
+        imageops::overlay(&mut image, &figure, 0, 0);
+        image
+    }
+}
+/// Chart as wide as the terminal. The user zooms and pans it without the app, so the range it shows
+/// is kept here rather than in the chart.
+pub struct ChartRenderer {
+    chart: Chart,
+    range: ChartRange,
+}
+impl ChartRenderer {
+    const MIN_HEIGHT: u32 = 120;
+    const MAX_HEIGHT: u32 = 400;
+    /// How much one line of the mouse wheel zooms in
+    const ZOOM_STEP: f64 = 1.1;
+    pub fn new(chart: Chart) -> Self {
+        Self {
+            range: chart.auto_range(),
+            chart,
+        }
+    }
+    pub fn chart(&self) -> &Chart {
+        &self.chart
+    }
+    /// Size in pixels of the chart when the terminal is `width` pixels wide
+    fn size(width: u32) -> Vector2<u32> {
+        Vector2::new(width, (width / 2).max(Self::MIN_HEIGHT).min(Self::MAX_HEIGHT))
+    }
+    pub fn layout(&self, width: u32) -> ChartLayout {
+        self.chart.layout(Self::size(width), self.range)
+    }
+    /// Text for the point under `position`, in pixels from the top left of the chart
+    pub fn tooltip_at(&self, position: Vector2<f32>, width: u32) -> Option<String> {
+        let (index, point) = self.chart.point_at(&self.layout(width), position)?;
+        Some(self.chart.describe(index, point))
+    }
+    /// Zooms in by `lines` of the mouse wheel around `position`, keeping the data under it in place
+    pub fn zoom(&mut self, position: Vector2<f32>, width: u32, lines: f32) {
+        let layout = self.layout(width);
+        if !layout.contains(position) {
+            return;
+        }
+        let center = layout.to_data(position);
+        self.range = self.range.zoom(center, Self::ZOOM_STEP.powf(lines as f64));
+    }
+    /// Moves the data along with the cursor going from `from` to `to`
+    pub fn pan(&mut self, from: Vector2<f32>, to: Vector2<f32>, width: u32) {
+        let layout = self.layout(width);
+        self.range = self.range.pan(layout.to_data(from) - layout.to_data(to));
+    }
+    /// Goes back to the range the chart was sent with
+    pub fn reset(&mut self) {
+        self.range = self.chart.auto_range();
+    }
+}
+impl RenderSUrface for ChartRenderer {
+    fn height(&self, _font: &Renderer, width: u32) -> u32 {
+        Self::size(width).y
+    }
+    fn render(&self, font: &Renderer, mut image: RgbaImage) -> RgbaImage {
+        let figure = self.chart.to_figure(&self.layout(image.width()), &Terminal::palette());
+        let chart = figure.rasterize_with(|canvas, label, position| font.draw_label(canvas, label, position));
+        imageops::overlay(&mut image, &chart, 0, 0);
+        image
+    }
+}
+/// Fills a rectangle of `image`, parts outside of the image are skipped
+fn fill(image: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, color: Rgba<u8>) {
+    for py in y..(y + height).min(image.height()) {
+        for px in x..(x + width).min(image.width()) {
+            image.put_pixel(px, py, color);
+        }
     }
 }
-pub struct TextureRenderer {}
-pub struct LineRenderer {}
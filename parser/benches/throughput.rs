@@ -0,0 +1,45 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use image::RgbaImage;
+use nalgebra::Vector2;
+use parser::{serializer, Deserializer, Figure, ParsedAST};
+
+/// Stream of many short text packets, like an app printing a log
+fn text_stream() -> Vec<u8> {
+    let mut stream = vec![];
+    for i in 0..10_000 {
+        stream.append(&mut serializer::build_text(format!("line {} of the log output", i)));
+    }
+    stream
+}
+/// Stream of a few figures that each hold one 512x512 picture
+fn image_stream() -> Vec<u8> {
+    let pixels = (0..512 * 512 * 4).map(|i| i as u8).collect();
+    let image = RgbaImage::from_raw(512, 512, pixels).unwrap();
+    let figure = ParsedAST::Figure(Figure::new(512, 512).image_at(Vector2::new(0, 0), image));
+    let mut stream = vec![];
+    for _ in 0..8 {
+        stream.append(&mut serializer::serialize(&figure));
+    }
+    stream
+}
+/// Feeds `stream` to a new deserializer in chunks the size of a pipe read
+fn parse_chunked(stream: &[u8], chunk_size: usize) -> usize {
+    let mut p = Deserializer::new();
+    let mut parsed = 0;
+    for chunk in stream.chunks(chunk_size) {
+        parsed += p.parse(&mut chunk.to_vec()).unwrap().len();
+    }
+    parsed
+}
+fn throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deserialize");
+    let text = text_stream();
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("text", |b| b.iter(|| parse_chunked(&text, 4096)));
+    let images = image_stream();
+    group.throughput(Throughput::Bytes(images.len() as u64));
+    group.bench_function("images", |b| b.iter(|| parse_chunked(&images, 65536)));
+    group.finish();
+}
+criterion_group!(benches, throughput);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use parser::Deserializer;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks where the stream is split so packets that arrive in pieces get
+    // exercised as well as whole ones.
+    if let Some((split, stream)) = data.split_first() {
+        let (first, second) = stream.split_at(*split as usize % (stream.len() + 1));
+        let mut p = Deserializer::new();
+        for mut chunk in vec![first.to_vec(), second.to_vec()] {
+            // skip broken packets the same way the terminal does
+            while p.parse(&mut chunk).is_err() {
+                p.skip_packet();
+            }
+        }
+    }
+});
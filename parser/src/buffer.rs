@@ -0,0 +1,109 @@
+/// Byte queue that is written to at the back and read from the front.
+///
+/// Reading does not move any bytes, it only advances `start`. The unread bytes are moved back
+/// to the start of the allocation only when new data would not fit otherwise and more bytes
+/// have been read than are left unread, so moving them costs less than the bytes already parsed
+/// and the total amount of copying stays linear in the size of the stream.
+pub(crate) struct StreamBuffer {
+    data: Vec<u8>,
+    /// Index of the first unread byte in `data`
+    start: usize,
+}
+impl StreamBuffer {
+    /// Largest amount of memory reserved up front for a packet that has not fully arrived. The
+    /// length comes from the app so it can not be trusted with an unbounded allocation.
+    const MAX_RESERVE: usize = 1 << 26;
+    pub fn new() -> Self {
+        Self {
+            data: vec![],
+            start: 0,
+        }
+    }
+    /// Appends the contents of `bytes` to the back of the queue, leaving `bytes` empty.
+    pub fn append(&mut self, bytes: &mut Vec<u8>) {
+        if bytes.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            // Nothing is buffered so the incoming allocation can be used as is, the caller gets
+            // the old allocation back to fill next time
+            self.start = 0;
+            self.data.clear();
+            std::mem::swap(&mut self.data, bytes);
+            return;
+        }
+        if self.data.len() + bytes.len() > self.data.capacity() && self.start >= self.len() {
+            self.compact();
+        }
+        self.data.append(bytes);
+    }
+    /// Makes room for a packet of `size` bytes that is at the front of the queue so it is not
+    /// grown one chunk at a time while the packet streams in.
+    pub fn reserve_packet(&mut self, size: usize) {
+        let size = size.min(Self::MAX_RESERVE);
+        if size > self.len() && self.start + size > self.data.capacity() {
+            self.compact();
+            self.data.reserve(size - self.len());
+        }
+    }
+    /// Unread bytes
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[self.start..]
+    }
+    pub fn len(&self) -> usize {
+        self.data.len() - self.start
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Marks `length` bytes at the front as read
+    pub fn consume(&mut self, length: usize) {
+        self.start += length.min(self.len());
+        if self.is_empty() {
+            self.data.clear();
+            self.start = 0;
+        }
+    }
+    /// Moves the unread bytes to the start of the allocation
+    fn compact(&mut self) {
+        self.data.drain(..self.start);
+        self.start = 0;
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn reuses_incoming_allocation() {
+        let mut buffer = StreamBuffer::new();
+        let mut bytes = Vec::with_capacity(64);
+        bytes.extend_from_slice(&[1, 2, 3]);
+        let pointer = bytes.as_ptr();
+        buffer.append(&mut bytes);
+        assert!(bytes.is_empty());
+        assert_eq!(buffer.as_slice().as_ptr(), pointer);
+    }
+    #[test]
+    fn consume_and_append() {
+        let mut buffer = StreamBuffer::new();
+        buffer.append(&mut vec![1, 2, 3, 4]);
+        buffer.consume(3);
+        assert_eq!(buffer.as_slice(), &[4]);
+        buffer.append(&mut vec![5, 6, 7, 8, 9]);
+        assert_eq!(buffer.as_slice(), &[4, 5, 6, 7, 8, 9]);
+        buffer.consume(100);
+        assert!(buffer.is_empty());
+    }
+    #[test]
+    fn reserve_keeps_contents() {
+        let mut buffer = StreamBuffer::new();
+        buffer.append(&mut vec![1, 2, 3, 4]);
+        buffer.consume(2);
+        buffer.reserve_packet(1024);
+        assert_eq!(buffer.as_slice(), &[3, 4]);
+        let pointer = buffer.as_slice().as_ptr();
+        buffer.append(&mut vec![0; 1000]);
+        assert_eq!(buffer.as_slice().as_ptr(), pointer);
+        assert_eq!(buffer.len(), 1002);
+    }
+}
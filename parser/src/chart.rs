@@ -0,0 +1,696 @@
+use super::{
+    Alignment, Color, Figure, Group, Label, Palette, PaletteIndex, ShapeStyle, VerticalAlignment,
+};
+use nalgebra::Vector2;
+/// Line, scatter or bar plot of one or more series with axes, ticks and a legend. The frontend
+/// lays it out and draws it, and since it has the data it can zoom, pan and show the values
+/// under the cursor without asking the app.
+///
+/// ```
+/// # use parser::{Axis, Chart, ChartKind, Series};
+/// # use nalgebra::Vector2;
+/// let chart = Chart::new(ChartKind::Line)
+///     .title("Loss")
+///     .x_axis(Axis::new("epoch"))
+///     .y_axis(Axis::new("loss").range(0.0, 1.0))
+///     .series(Series::new(
+///         "train",
+///         vec![Vector2::new(0.0, 0.9), Vector2::new(1.0, 0.5)],
+///     ));
+/// let range = chart.auto_range();
+/// assert_eq!((range.min.y, range.max.y), (0.0, 1.0));
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Chart {
+    pub kind: ChartKind,
+    pub title: String,
+    pub x_axis: Axis,
+    pub y_axis: Axis,
+    pub series: Vec<Series>,
+    /// Whether a legend naming the series is drawn in the top right corner of the plot
+    pub legend: bool,
+}
+/// How every series of a chart is drawn
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChartKind {
+    /// The points of each series joined in the order they were sent
+    Line = 0x0,
+    /// A dot at each point
+    Scatter = 0x1,
+    /// A bar from 0 to each point, centered on its x. Bars of different series at the same x
+    /// stand side by side.
+    Bar = 0x2,
+}
+impl ChartKind {
+    pub const ALL: [ChartKind; 3] = [ChartKind::Line, ChartKind::Scatter, ChartKind::Bar];
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| *kind as u32 == value)
+    }
+}
+#[derive(Debug, PartialEq, Clone)]
+pub struct Axis {
+    pub label: String,
+    /// Values at the start and end of the axis, `None` to fit the axis to the data
+    pub range: Option<(f64, f64)>,
+}
+impl Axis {
+    /// Axis fit to the data
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            range: None,
+        }
+    }
+    pub fn range(mut self, low: f64, high: f64) -> Self {
+        self.range = Some((low, high));
+        self
+    }
+}
+#[derive(Debug, PartialEq, Clone)]
+pub struct Series {
+    pub name: String,
+    /// `None` to use the color of the series' place in `Chart::SERIES_COLORS`
+    pub color: Option<Color>,
+    /// Points that are not finite are not drawn
+    pub points: Vec<Vector2<f64>>,
+}
+impl Series {
+    pub(crate) const HAS_COLOR: u32 = 1 << 0;
+    pub fn new(name: &str, points: Vec<Vector2<f64>>) -> Self {
+        Self {
+            name: name.to_string(),
+            color: None,
+            points,
+        }
+    }
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+}
+/// Part of the data a chart shows, from `min` at the bottom left of the plot to `max` at its top
+/// right
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ChartRange {
+    pub min: Vector2<f64>,
+    pub max: Vector2<f64>,
+}
+impl ChartRange {
+    /// Magnifies the range `factor` times around `center`, factors below 1 zoom out
+    pub fn zoom(self, center: Vector2<f64>, factor: f64) -> Self {
+        if !(factor.is_finite() && factor > 0.0) {
+            return self;
+        }
+        Self {
+            min: center + (self.min - center) / factor,
+            max: center + (self.max - center) / factor,
+        }
+    }
+    pub fn pan(self, offset: Vector2<f64>) -> Self {
+        Self {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+}
+/// Where the plot area of a chart drawn at `size` pixels lands and which data it shows. Maps
+/// between data and pixels for drawing, hovering and zooming alike.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ChartLayout {
+    pub size: Vector2<u32>,
+    /// Top left corner of the plot area in pixels
+    pub plot_position: Vector2<f32>,
+    pub plot_size: Vector2<f32>,
+    pub range: ChartRange,
+}
+impl ChartLayout {
+    /// Pixel a point of data is drawn at, y grows up in data and down in pixels
+    pub fn to_pixel(&self, point: Vector2<f64>) -> Vector2<f32> {
+        let span = self.range.max - self.range.min;
+        let t = (point - self.range.min).component_div(&span);
+        Vector2::new(
+            self.plot_position.x + (t.x * self.plot_size.x as f64) as f32,
+            self.plot_position.y + ((1.0 - t.y) * self.plot_size.y as f64) as f32,
+        )
+    }
+    pub fn to_data(&self, pixel: Vector2<f32>) -> Vector2<f64> {
+        let t = (pixel - self.plot_position).component_div(&self.plot_size);
+        let span = self.range.max - self.range.min;
+        Vector2::new(
+            self.range.min.x + t.x as f64 * span.x,
+            self.range.min.y + (1.0 - t.y as f64) * span.y,
+        )
+    }
+    /// Whether `pixel` is inside of the plot area
+    pub fn contains(&self, pixel: Vector2<f32>) -> bool {
+        let inside = pixel - self.plot_position;
+        inside.x >= 0.0
+            && inside.y >= 0.0
+            && inside.x < self.plot_size.x
+            && inside.y < self.plot_size.y
+    }
+}
+impl Chart {
+    /// Colors of series without their own, in the order of the series
+    pub const SERIES_COLORS: [Color; 8] = [
+        Color::rgb(0x4e, 0x79, 0xa7),
+        Color::rgb(0xf2, 0x8e, 0x2b),
+        Color::rgb(0xe1, 0x57, 0x59),
+        Color::rgb(0x76, 0xb7, 0xb2),
+        Color::rgb(0x59, 0xa1, 0x4f),
+        Color::rgb(0xed, 0xc9, 0x48),
+        Color::rgb(0xb0, 0x7a, 0xa1),
+        Color::rgb(0xff, 0x9d, 0xa7),
+    ];
+    /// Empty space around everything, in pixels
+    const MARGIN: f32 = 12.0;
+    /// Room left of the plot for the labels of the y ticks
+    const Y_TICK_WIDTH: f32 = 48.0;
+    const TICK_FONT_SIZE: f32 = 10.0;
+    const LABEL_FONT_SIZE: f32 = 12.0;
+    const TITLE_FONT_SIZE: f32 = 14.0;
+    /// Rough distance between ticks in pixels, the ticks themselves fall on round values
+    const TICK_SPACING: f32 = 80.0;
+    const LINE_WIDTH: f32 = 2.0;
+    const MARKER_RADIUS: f32 = 3.0;
+    /// How far from a point in pixels the cursor may be to hover it
+    const HOVER_RADIUS: f32 = 6.0;
+    /// Part of the gap between neighboring x values that bars fill
+    const BAR_FILL: f64 = 0.8;
+    /// Part of the data's extent left empty on each side of axes fit to the data
+    const PADDING: f64 = 0.05;
+    pub(crate) const LEGEND: u32 = 1 << 0;
+    pub(crate) const X_RANGE: u32 = 1 << 1;
+    pub(crate) const Y_RANGE: u32 = 1 << 2;
+    /// Chart without series, with unlabeled axes fit to the data and a legend
+    pub fn new(kind: ChartKind) -> Self {
+        Self {
+            kind,
+            title: String::new(),
+            x_axis: Axis::new(""),
+            y_axis: Axis::new(""),
+            series: vec![],
+            legend: true,
+        }
+    }
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+    pub fn x_axis(mut self, axis: Axis) -> Self {
+        self.x_axis = axis;
+        self
+    }
+    pub fn y_axis(mut self, axis: Axis) -> Self {
+        self.y_axis = axis;
+        self
+    }
+    pub fn series(mut self, series: Series) -> Self {
+        self.series.push(series);
+        self
+    }
+    pub fn legend(mut self, legend: bool) -> Self {
+        self.legend = legend;
+        self
+    }
+    /// Packs whether there is a legend and which axes have a range the way they are sent
+    pub(crate) fn flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.legend {
+            flags |= Self::LEGEND;
+        }
+        if self.x_axis.range.is_some() {
+            flags |= Self::X_RANGE;
+        }
+        if self.y_axis.range.is_some() {
+            flags |= Self::Y_RANGE;
+        }
+        flags
+    }
+    /// Color the series at `index` is drawn in
+    pub fn series_color(&self, index: usize) -> Color {
+        self.series[index]
+            .color
+            .unwrap_or(Self::SERIES_COLORS[index % Self::SERIES_COLORS.len()])
+    }
+    fn finite_points(&self) -> impl Iterator<Item = &Vector2<f64>> {
+        self.series
+            .iter()
+            .flat_map(|series| series.points.iter())
+            .filter(|point| point.x.is_finite() && point.y.is_finite())
+    }
+    /// Range with the axes the app gave a range kept as they are and the others fit to the
+    /// data with some room around it. Bar charts always show 0 on the y axis.
+    pub fn auto_range(&self) -> ChartRange {
+        let extent = self
+            .finite_points()
+            .fold(None, |extent, point| match extent {
+                Some((low, high)) => Some((point.inf(&low), point.sup(&high))),
+                None => Some((*point, *point)),
+            });
+        let (low, high) = extent.unwrap_or((Vector2::new(0.0, 0.0), Vector2::new(1.0, 1.0)));
+        let bar = self.kind == ChartKind::Bar;
+        // a range with both ends at the same value would divide by zero when drawn
+        let spread = |(low, high): (f64, f64)| {
+            if low == high {
+                (low - 0.5, high + 0.5)
+            } else {
+                (low, high)
+            }
+        };
+        let x = self.x_axis.range.map(spread).unwrap_or_else(|| {
+            if bar {
+                // room for the outer halves of the first and last bars
+                let spacing = self.bar_spacing();
+                padded(low.x - spacing / 2.0, high.x + spacing / 2.0, 0.0, false)
+            } else {
+                padded(low.x, high.x, Self::PADDING, false)
+            }
+        });
+        let y = self.y_axis.range.map(spread).unwrap_or_else(|| {
+            if bar {
+                padded(low.y.min(0.0), high.y.max(0.0), Self::PADDING, true)
+            } else {
+                padded(low.y, high.y, Self::PADDING, false)
+            }
+        });
+        ChartRange {
+            min: Vector2::new(x.0, y.0),
+            max: Vector2::new(x.1, y.1),
+        }
+    }
+    /// Places the plot area in a chart of `size` pixels showing `range`
+    pub fn layout(&self, size: Vector2<u32>, range: ChartRange) -> ChartLayout {
+        let line = |font_size: f32, text: &str| {
+            if text.is_empty() {
+                0.0
+            } else {
+                font_size * 1.5
+            }
+        };
+        let top = Self::MARGIN + line(Self::TITLE_FONT_SIZE, &self.title);
+        let left =
+            Self::MARGIN + Self::Y_TICK_WIDTH + line(Self::LABEL_FONT_SIZE, &self.y_axis.label);
+        let bottom = Self::MARGIN
+            + Self::TICK_FONT_SIZE * 1.5
+            + line(Self::LABEL_FONT_SIZE, &self.x_axis.label);
+        ChartLayout {
+            size,
+            plot_position: Vector2::new(left, top),
+            plot_size: Vector2::new(
+                (size.x as f32 - left - Self::MARGIN).max(1.0),
+                (size.y as f32 - top - bottom).max(1.0),
+            ),
+            range,
+        }
+    }
+    /// The chart drawn as a figure, with text and grid lines in the colors of `palette`
+    pub fn to_figure(&self, layout: &ChartLayout, palette: &Palette) -> Figure {
+        let foreground = palette.get(PaletteIndex::Foreground);
+        let muted = palette.get(PaletteIndex::Muted);
+        let (near, size) = (layout.plot_position, layout.plot_size);
+        let far = near + size;
+        let range = layout.range;
+        let mut figure = Figure::new(layout.size.x, layout.size.y);
+        let x_ticks = ticks(
+            range.min.x,
+            range.max.x,
+            (size.x / Self::TICK_SPACING) as usize,
+        );
+        for (x, text) in tick_labels(&x_ticks) {
+            let pixel = layout.to_pixel(Vector2::new(x, range.min.y)).x;
+            let label = Label::new(&text, Self::TICK_FONT_SIZE, foreground)
+                .align(Alignment::Center, VerticalAlignment::Top);
+            figure = figure
+                .polyline(
+                    muted,
+                    1.0,
+                    vec![Vector2::new(pixel, near.y), Vector2::new(pixel, far.y)],
+                )
+                .label(
+                    Vector2::new(pixel.round() as i32, (far.y + 4.0) as i32),
+                    label,
+                );
+        }
+        let y_ticks = ticks(
+            range.min.y,
+            range.max.y,
+            (size.y / Self::TICK_SPACING) as usize,
+        );
+        for (y, text) in tick_labels(&y_ticks) {
+            let pixel = layout.to_pixel(Vector2::new(range.min.x, y)).y;
+            let label = Label::new(&text, Self::TICK_FONT_SIZE, foreground)
+                .align(Alignment::Right, VerticalAlignment::Middle);
+            figure = figure
+                .polyline(
+                    muted,
+                    1.0,
+                    vec![Vector2::new(near.x, pixel), Vector2::new(far.x, pixel)],
+                )
+                .label(
+                    Vector2::new((near.x - 6.0) as i32, pixel.round() as i32),
+                    label,
+                );
+        }
+        figure = figure.rectangle(
+            Vector2::new(near.x as i32, near.y as i32),
+            size,
+            0.0,
+            ShapeStyle::stroked(muted, 1.0),
+        );
+        let plot = Group::from_figure(self.plot(layout)).clip(near, size);
+        figure = figure.group(Vector2::new(0, 0), plot);
+        if !self.title.is_empty() {
+            let title = Label::new(&self.title, Self::TITLE_FONT_SIZE, foreground)
+                .align(Alignment::Center, VerticalAlignment::Top);
+            let center = (layout.size.x / 2) as i32;
+            figure = figure.label(Vector2::new(center, Self::MARGIN as i32), title);
+        }
+        if !self.x_axis.label.is_empty() {
+            let label = Label::new(&self.x_axis.label, Self::LABEL_FONT_SIZE, foreground)
+                .align(Alignment::Center, VerticalAlignment::Bottom);
+            let anchor = Vector2::new(
+                (near.x + size.x / 2.0) as i32,
+                layout.size.y as i32 - Self::MARGIN as i32,
+            );
+            figure = figure.label(anchor, label);
+        }
+        if !self.y_axis.label.is_empty() {
+            // turned to read bottom to top, the top of the text faces the left edge
+            let label = Label::new(&self.y_axis.label, Self::LABEL_FONT_SIZE, foreground)
+                .align(Alignment::Center, VerticalAlignment::Top)
+                .rotate(-90.0);
+            let anchor = Vector2::new(Self::MARGIN as i32, (near.y + size.y / 2.0) as i32);
+            figure = figure.label(anchor, label);
+        }
+        if self.legend && !self.series.is_empty() {
+            figure = self.draw_legend(figure, layout, palette);
+        }
+        figure
+    }
+    /// The series, in pixels of the whole chart
+    fn plot(&self, layout: &ChartLayout) -> Figure {
+        let mut plot = Figure::new(0, 0);
+        let spacing = self.bar_spacing();
+        for (index, series) in self.series.iter().enumerate() {
+            let color = self.series_color(index);
+            let points = series
+                .points
+                .iter()
+                .filter(|point| point.x.is_finite() && point.y.is_finite());
+            match self.kind {
+                ChartKind::Line => {
+                    let pixels = points.map(|point| layout.to_pixel(*point)).collect();
+                    plot = plot.polyline(color, Self::LINE_WIDTH, pixels);
+                }
+                ChartKind::Scatter => {
+                    for point in points {
+                        let pixel = layout.to_pixel(*point);
+                        plot = plot.ellipse(
+                            Vector2::new(pixel.x.round() as i32, pixel.y.round() as i32),
+                            Vector2::new(Self::MARKER_RADIUS, Self::MARKER_RADIUS),
+                            ShapeStyle::filled(color),
+                        );
+                    }
+                }
+                ChartKind::Bar => {
+                    for point in points {
+                        let (low, high) = self.bar(layout, spacing, index, *point);
+                        let corners = vec![
+                            low,
+                            Vector2::new(high.x, low.y),
+                            high,
+                            Vector2::new(low.x, high.y),
+                        ];
+                        plot = plot.polygon(corners, ShapeStyle::filled(color));
+                    }
+                }
+            }
+        }
+        plot
+    }
+    /// Box in the top right of the plot with a swatch and the name of each series. The frontend
+    /// measures text, so the width is a guess from the longest name.
+    fn draw_legend(&self, figure: Figure, layout: &ChartLayout, palette: &Palette) -> Figure {
+        const SWATCH: f32 = 10.0;
+        const INSET: f32 = 8.0;
+        let row = Self::LABEL_FONT_SIZE * 1.5;
+        let longest = self
+            .series
+            .iter()
+            .map(|series| series.name.chars().count())
+            .max()
+            .unwrap_or(0);
+        let width = 3.0 * INSET + SWATCH + longest as f32 * Self::LABEL_FONT_SIZE * 0.6;
+        let height = 2.0 * INSET + row * self.series.len() as f32 - (row - SWATCH);
+        let corner = Vector2::new(
+            layout.plot_position.x + layout.plot_size.x - width - INSET,
+            layout.plot_position.y + INSET,
+        );
+        let background = ShapeStyle {
+            fill: palette.get(PaletteIndex::Background).with_alpha(220),
+            stroke: palette.get(PaletteIndex::Muted),
+            stroke_width: 1.0,
+        };
+        let mut figure = figure.rectangle(
+            Vector2::new(corner.x as i32, corner.y as i32),
+            Vector2::new(width, height),
+            3.0,
+            background,
+        );
+        for (index, series) in self.series.iter().enumerate() {
+            let top = corner + Vector2::new(INSET, INSET + row * index as f32);
+            let label = Label::new(
+                &series.name,
+                Self::LABEL_FONT_SIZE,
+                palette.get(PaletteIndex::Foreground),
+            )
+            .align(Alignment::Left, VerticalAlignment::Middle);
+            figure = figure
+                .rectangle(
+                    Vector2::new(top.x as i32, top.y as i32),
+                    Vector2::new(SWATCH, SWATCH),
+                    0.0,
+                    ShapeStyle::filled(self.series_color(index)),
+                )
+                .label(
+                    Vector2::new(
+                        (top.x + SWATCH + INSET) as i32,
+                        (top.y + SWATCH / 2.0) as i32,
+                    ),
+                    label,
+                );
+        }
+        figure
+    }
+    /// Series and point drawn at `pixel`, the bar under it or the nearest point within a few
+    /// pixels. Points of later series win, as they are drawn on top.
+    pub fn point_at(
+        &self,
+        layout: &ChartLayout,
+        pixel: Vector2<f32>,
+    ) -> Option<(usize, Vector2<f64>)> {
+        if !layout.contains(pixel) {
+            return None;
+        }
+        let spacing = self.bar_spacing();
+        let mut nearest = None;
+        let mut nearest_distance = Self::HOVER_RADIUS;
+        for (index, series) in self.series.iter().enumerate() {
+            let points = series
+                .points
+                .iter()
+                .filter(|point| point.x.is_finite() && point.y.is_finite());
+            for point in points {
+                let distance = if self.kind == ChartKind::Bar {
+                    let (low, high) = self.bar(layout, spacing, index, *point);
+                    if pixel.x >= low.x && pixel.x < high.x && pixel.y >= low.y && pixel.y < high.y
+                    {
+                        0.0
+                    } else {
+                        continue;
+                    }
+                } else {
+                    (layout.to_pixel(*point) - pixel).norm()
+                };
+                if distance <= nearest_distance {
+                    nearest = Some((index, *point));
+                    nearest_distance = distance;
+                }
+            }
+        }
+        nearest
+    }
+    /// Text shown for a point of the series at `index` when it is hovered, such as "train: 2, 0.5"
+    pub fn describe(&self, index: usize, point: Vector2<f64>) -> String {
+        let name = &self.series[index].name;
+        if name.is_empty() {
+            format!("{}, {}", point.x, point.y)
+        } else {
+            format!("{}: {}, {}", name, point.x, point.y)
+        }
+    }
+    /// Smallest gap between the x values of the points, which is the room each group of bars
+    /// has. 1 if there are fewer than two different x values.
+    fn bar_spacing(&self) -> f64 {
+        let mut xs: Vec<f64> = self.finite_points().map(|point| point.x).collect();
+        xs.sort_by(|a, b| a.partial_cmp(b).expect("points are finite"));
+        xs.dedup();
+        let spacing = xs
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .fold(f64::INFINITY, f64::min);
+        if spacing.is_finite() && spacing > 0.0 {
+            spacing
+        } else {
+            1.0
+        }
+    }
+    /// Top left and bottom right corners in pixels of the bar for `point` of the series at
+    /// `index`
+    fn bar(
+        &self,
+        layout: &ChartLayout,
+        spacing: f64,
+        index: usize,
+        point: Vector2<f64>,
+    ) -> (Vector2<f32>, Vector2<f32>) {
+        let width = spacing * Self::BAR_FILL / self.series.len() as f64;
+        let left = point.x - spacing * Self::BAR_FILL / 2.0 + width * index as f64;
+        let base = layout.to_pixel(Vector2::new(left, 0.0));
+        let end = layout.to_pixel(Vector2::new(left + width, point.y));
+        (base.inf(&end), base.sup(&end))
+    }
+}
+/// `low` and `high` of the data moved apart by `padding` of their distance, ends at 0 stay there
+/// if `keep_zero` is set. Equal ends are spread so the range is never empty.
+fn padded(low: f64, high: f64, padding: f64, keep_zero: bool) -> (f64, f64) {
+    if high - low <= 0.0 {
+        return (low - 0.5, high + 0.5);
+    }
+    let margin = (high - low) * padding;
+    let low = if keep_zero && low == 0.0 {
+        low
+    } else {
+        low - margin
+    };
+    let high = if keep_zero && high == 0.0 {
+        high
+    } else {
+        high + margin
+    };
+    (low, high)
+}
+/// About `count` round values between `low` and `high` to label an axis with, in increasing
+/// order. Ticks are whole multiples of 1, 2 or 5 times a power of ten.
+pub fn ticks(low: f64, high: f64, count: usize) -> Vec<f64> {
+    /// Ticks of axes zoomed so far that the values do not fit between them are left out
+    const MAX_TICKS: f64 = 1000.0;
+    let (low, high) = (low.min(high), low.max(high));
+    let span = high - low;
+    if !(span.is_finite() && span > 0.0) {
+        return vec![];
+    }
+    let rough = span / count.max(2) as f64;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = magnitude
+        * match rough / magnitude {
+            fraction if fraction <= 1.0 => 1.0,
+            fraction if fraction <= 2.0 => 2.0,
+            fraction if fraction <= 5.0 => 5.0,
+            _ => 10.0,
+        };
+    let (first, last) = ((low / step).ceil(), (high / step).floor());
+    if last < first || last - first >= MAX_TICKS {
+        return vec![];
+    }
+    (0..=(last - first) as usize)
+        .map(|index| (first + index as f64) * step)
+        .collect()
+}
+/// `ticks` with their text, given as many decimals as the distance between them needs
+fn tick_labels(ticks: &[f64]) -> Vec<(f64, String)> {
+    let step = match ticks {
+        [first, second, ..] => second - first,
+        _ => 1.0,
+    };
+    let decimals = (-step.log10().floor()).clamp(0.0, 15.0) as usize;
+    ticks
+        .iter()
+        .map(|tick| {
+            let text = format!("{:.*}", decimals, tick);
+            // rounding small values leaves a negative zero
+            let text = match text.strip_prefix('-') {
+                Some(rest) if rest.chars().all(|c| c == '0' || c == '.') => rest.to_string(),
+                _ => text,
+            };
+            (*tick, text)
+        })
+        .collect()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn tick_values() {
+        assert_eq!(ticks(0.0, 10.0, 5), vec![0.0, 2.0, 4.0, 6.0, 8.0, 10.0]);
+        assert_eq!(ticks(10.0, 0.0, 2), vec![0.0, 5.0, 10.0]);
+        assert_eq!(ticks(-0.13, 0.13, 3), vec![-0.1, 0.0, 0.1]);
+        assert_eq!(ticks(1.0, 1.0, 4), Vec::<f64>::new());
+        assert_eq!(ticks(0.0, f64::INFINITY, 4), Vec::<f64>::new());
+        let labels: Vec<String> = tick_labels(&[-0.5, 0.0, 0.5])
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect();
+        assert_eq!(labels, vec!["-0.5", "0.0", "0.5"]);
+    }
+    #[test]
+    fn auto_range() {
+        let bars = Chart::new(ChartKind::Bar).series(Series::new(
+            "",
+            vec![Vector2::new(1.0, 2.0), Vector2::new(3.0, 4.0)],
+        ));
+        let range = bars.auto_range();
+        assert_eq!(range.min, Vector2::new(0.0, 0.0));
+        assert_eq!(range.max.x, 4.0);
+        assert!((range.max.y - 4.2).abs() < 1e-9);
+        let flat = Chart::new(ChartKind::Line)
+            .x_axis(Axis::new("").range(-1.0, 1.0))
+            .series(Series::new("", vec![Vector2::new(0.0, 3.0)]));
+        let range = flat.auto_range();
+        assert_eq!(range.min, Vector2::new(-1.0, 2.5));
+        assert_eq!(range.max, Vector2::new(1.0, 3.5));
+        let zoomed = range.zoom(Vector2::new(0.0, 3.0), 2.0);
+        assert_eq!(zoomed.min, Vector2::new(-0.5, 2.75));
+        assert_eq!(
+            zoomed.pan(Vector2::new(0.5, 0.0)).max,
+            Vector2::new(1.0, 3.25)
+        );
+    }
+    #[test]
+    fn layout_and_hover() {
+        let chart = Chart::new(ChartKind::Scatter)
+            .series(Series::new("a", vec![Vector2::new(0.0, 0.0)]))
+            .series(Series::new("b", vec![Vector2::new(1.0, 1.0)]).color(Color::RED));
+        let range = ChartRange {
+            min: Vector2::new(0.0, 0.0),
+            max: Vector2::new(1.0, 1.0),
+        };
+        let layout = chart.layout(Vector2::new(200, 100), range);
+        let corner = layout.plot_position + layout.plot_size;
+        assert_eq!(layout.to_pixel(Vector2::new(1.0, 0.0)), corner);
+        assert_eq!(layout.to_data(corner), Vector2::new(1.0, 0.0));
+        let top_right = Vector2::new(corner.x - 2.0, layout.plot_position.y + 2.0);
+        let hovered = chart.point_at(&layout, top_right);
+        assert_eq!(hovered, Some((1, Vector2::new(1.0, 1.0))));
+        assert_eq!(chart.describe(1, Vector2::new(1.0, 1.0)), "b: 1, 1");
+        assert_eq!(
+            chart.point_at(&layout, layout.plot_position + layout.plot_size / 2.0),
+            None
+        );
+        assert_eq!(chart.series_color(1), Color::RED);
+        let image = chart.to_figure(&layout, &Palette::default()).rasterize();
+        let marker = Vector2::new(corner.x as u32 - 1, layout.plot_position.y as u32 + 1);
+        assert_eq!(image.get_pixel(marker.x, marker.y)[0], 255);
+    }
+}
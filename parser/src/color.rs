@@ -0,0 +1,294 @@
+use image::Rgba;
+use std::fmt;
+use std::str::FromStr;
+/// A color with straight (not premultiplied) alpha. Sent as four bytes in the order red, green,
+/// blue, alpha. Anything with an alpha of 0 is not drawn.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+impl Color {
+    pub const TRANSPARENT: Color = Color::rgba(0, 0, 0, 0);
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    pub const GRAY: Color = Color::rgb(128, 128, 128);
+    pub const RED: Color = Color::rgb(255, 0, 0);
+    pub const GREEN: Color = Color::rgb(0, 255, 0);
+    pub const BLUE: Color = Color::rgb(0, 0, 255);
+    pub const YELLOW: Color = Color::rgb(255, 255, 0);
+    pub const CYAN: Color = Color::rgb(0, 255, 255);
+    pub const MAGENTA: Color = Color::rgb(255, 0, 255);
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+    /// Opaque color
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self::rgba(r, g, b, 255)
+    }
+    /// The same color with alpha set to `a`
+    pub const fn with_alpha(self, a: u8) -> Self {
+        Self { a, ..self }
+    }
+    /// The bytes sent for the color
+    pub const fn to_bytes(self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+    pub const fn from_bytes(bytes: [u8; 4]) -> Self {
+        Self::rgba(bytes[0], bytes[1], bytes[2], bytes[3])
+    }
+    pub fn is_transparent(self) -> bool {
+        self.a == 0
+    }
+}
+/// Parses CSS style hex colors, `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`. The `#` is optional
+/// and colors without an alpha are opaque.
+impl FromStr for Color {
+    type Err = ParseColorError;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let digits = text.strip_prefix('#').unwrap_or(text);
+        let values = digits
+            .chars()
+            .map(|digit| {
+                digit
+                    .to_digit(16)
+                    .map(|value| value as u8)
+                    .ok_or(ParseColorError::InvalidDigit(digit))
+            })
+            .collect::<Result<Vec<u8>, _>>()?;
+        let channels: Vec<u8> = match values.len() {
+            3 | 4 => values.iter().map(|value| value * 0x11).collect(),
+            6 | 8 => values
+                .chunks(2)
+                .map(|pair| pair[0] << 4 | pair[1])
+                .collect(),
+            length => return Err(ParseColorError::InvalidLength(length)),
+        };
+        Ok(Self::rgba(
+            channels[0],
+            channels[1],
+            channels[2],
+            channels.get(3).copied().unwrap_or(255),
+        ))
+    }
+}
+/// Formats as `#rrggbb`, or `#rrggbbaa` if the color is not opaque
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if self.a != 255 {
+            write!(f, "{:02x}", self.a)?;
+        }
+        Ok(())
+    }
+}
+impl From<Rgba<u8>> for Color {
+    fn from(pixel: Rgba<u8>) -> Self {
+        Self::from_bytes(pixel.0)
+    }
+}
+impl From<Color> for Rgba<u8> {
+    fn from(color: Color) -> Self {
+        Rgba(color.to_bytes())
+    }
+}
+/// Error from parsing a hex color
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParseColorError {
+    /// Number of hex digits, which has to be 3, 4, 6 or 8
+    InvalidLength(usize),
+    InvalidDigit(char),
+}
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseColorError::InvalidLength(length) => {
+                write!(f, "hex color has {} digits instead of 3, 4, 6 or 8", length)
+            }
+            ParseColorError::InvalidDigit(digit) => {
+                write!(f, "'{}' is not a hex digit", digit)
+            }
+        }
+    }
+}
+impl std::error::Error for ParseColorError {}
+/// Named scale from low to high values, used to color heatmaps
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Colormap {
+    /// Dark purple through blue and green to yellow, readable by most color blind people
+    Viridis = 0x0,
+    /// Black through purple and red to pale yellow
+    Magma = 0x1,
+    /// Black to white
+    Grayscale = 0x2,
+}
+impl Colormap {
+    pub const ALL: [Colormap; 3] = [Colormap::Viridis, Colormap::Magma, Colormap::Grayscale];
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|colormap| *colormap as u32 == value)
+    }
+    /// The colormap after this one in `ALL`, wrapping around, for viewers that cycle through them
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+    /// Evenly spaced colors of the scale, the colors between them are interpolated
+    fn stops(self) -> &'static [Color] {
+        const VIRIDIS: [Color; 8] = [
+            Color::rgb(0x44, 0x01, 0x54),
+            Color::rgb(0x46, 0x33, 0x7e),
+            Color::rgb(0x36, 0x5c, 0x8d),
+            Color::rgb(0x27, 0x7f, 0x8e),
+            Color::rgb(0x1f, 0xa1, 0x87),
+            Color::rgb(0x4a, 0xc1, 0x6d),
+            Color::rgb(0x9f, 0xda, 0x3a),
+            Color::rgb(0xfd, 0xe7, 0x25),
+        ];
+        const MAGMA: [Color; 8] = [
+            Color::rgb(0x00, 0x00, 0x04),
+            Color::rgb(0x1d, 0x11, 0x47),
+            Color::rgb(0x51, 0x12, 0x7c),
+            Color::rgb(0x82, 0x26, 0x81),
+            Color::rgb(0xb6, 0x36, 0x79),
+            Color::rgb(0xe6, 0x51, 0x64),
+            Color::rgb(0xfb, 0x88, 0x61),
+            Color::rgb(0xfc, 0xfd, 0xbf),
+        ];
+        const GRAYSCALE: [Color; 2] = [Color::BLACK, Color::WHITE];
+        match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Magma => &MAGMA,
+            Colormap::Grayscale => &GRAYSCALE,
+        }
+    }
+    /// Color at `t` along the scale, from 0 for the lowest value to 1 for the highest. Values
+    /// outside of that are clamped and NaN is transparent.
+    pub fn color(self, t: f32) -> Color {
+        if t.is_nan() {
+            return Color::TRANSPARENT;
+        }
+        let stops = self.stops();
+        let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let index = (position as usize).min(stops.len() - 2);
+        let fraction = position - index as f32;
+        let (low, high) = (stops[index], stops[index + 1]);
+        let mix =
+            |low: u8, high: u8| (low as f32 + (high as f32 - low as f32) * fraction).round() as u8;
+        Color::rgb(mix(low.r, high.r), mix(low.g, high.g), mix(low.b, high.b))
+    }
+}
+/// Meaning of each entry of a `Palette`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PaletteIndex {
+    /// Plain text
+    Foreground = 0x0,
+    Background = 0x1,
+    /// Highlights, selections and links
+    Accent = 0x2,
+    Error = 0x3,
+    Warning = 0x4,
+    Success = 0x5,
+    /// Secondary text, borders and grid lines
+    Muted = 0x6,
+}
+impl PaletteIndex {
+    pub const ALL: [PaletteIndex; 7] = [
+        PaletteIndex::Foreground,
+        PaletteIndex::Background,
+        PaletteIndex::Accent,
+        PaletteIndex::Error,
+        PaletteIndex::Warning,
+        PaletteIndex::Success,
+        PaletteIndex::Muted,
+    ];
+}
+/// Colors of the frontend's theme, indexed by `PaletteIndex`. A frontend with a theme sends it
+/// in reply to a `CapabilityQuery` so apps can color semantic output like errors the way the
+/// user's theme does instead of picking their own colors.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Palette {
+    /// Colors in `PaletteIndex` order. Palettes from newer frontends may have more entries than
+    /// this version knows about, and ones from older frontends fewer.
+    pub colors: Vec<Color>,
+}
+impl Palette {
+    /// Color at `index`, or the default palette's color if the sender did not include it
+    pub fn get(&self, index: PaletteIndex) -> Color {
+        self.colors
+            .get(index as usize)
+            .copied()
+            .unwrap_or_else(|| Self::default().colors[index as usize])
+    }
+    /// Sets the color at `index`, filling in any entries before it from the default palette
+    pub fn set(mut self, index: PaletteIndex, color: Color) -> Self {
+        let defaults = Self::default();
+        while self.colors.len() <= index as usize {
+            self.colors.push(defaults.colors[self.colors.len()]);
+        }
+        self.colors[index as usize] = color;
+        self
+    }
+}
+impl Default for Palette {
+    /// Light text on a dark blue background, what apps should assume without a palette
+    fn default() -> Self {
+        Self {
+            colors: vec![
+                Color::WHITE,
+                Color::rgb(0, 0, 25),
+                Color::rgb(80, 160, 255),
+                Color::rgb(230, 70, 70),
+                Color::rgb(230, 180, 60),
+                Color::rgb(90, 200, 110),
+                Color::rgb(90, 90, 110),
+            ],
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn hex() {
+        assert_eq!("#ff8000".parse(), Ok(Color::rgb(255, 128, 0)));
+        assert_eq!("f80".parse(), Ok(Color::rgb(255, 136, 0)));
+        assert_eq!("#0000ff80".parse(), Ok(Color::BLUE.with_alpha(128)));
+        assert_eq!("#1234".parse(), Ok(Color::rgba(0x11, 0x22, 0x33, 0x44)));
+        assert_eq!(
+            "#12345".parse::<Color>(),
+            Err(ParseColorError::InvalidLength(5))
+        );
+        assert_eq!(
+            "#12345g".parse::<Color>(),
+            Err(ParseColorError::InvalidDigit('g'))
+        );
+        assert_eq!(Color::rgb(255, 128, 0).to_string(), "#ff8000");
+        assert_eq!(Color::TRANSPARENT.to_string(), "#00000000");
+    }
+    #[test]
+    fn colormap() {
+        assert_eq!(Colormap::Viridis.color(0.0), Color::rgb(0x44, 0x01, 0x54));
+        assert_eq!(Colormap::Viridis.color(1.0), Color::rgb(0xfd, 0xe7, 0x25));
+        assert_eq!(Colormap::Magma.color(2.0), Colormap::Magma.color(1.0));
+        assert_eq!(Colormap::Grayscale.color(0.5), Color::rgb(128, 128, 128));
+        assert_eq!(Colormap::Grayscale.color(-1.0), Color::BLACK);
+        assert!(Colormap::Viridis.color(f32::NAN).is_transparent());
+        assert_eq!(Colormap::Viridis.next(), Colormap::Magma);
+        assert_eq!(Colormap::Grayscale.next(), Colormap::Viridis);
+    }
+    #[test]
+    fn palette() {
+        let palette = Palette { colors: vec![] }.set(PaletteIndex::Error, Color::MAGENTA);
+        assert_eq!(palette.colors.len(), 4);
+        assert_eq!(palette.get(PaletteIndex::Error), Color::MAGENTA);
+        assert_eq!(palette.get(PaletteIndex::Foreground), Color::WHITE);
+        assert_eq!(
+            palette.get(PaletteIndex::Muted),
+            Palette::default().get(PaletteIndex::Muted)
+        );
+    }
+}
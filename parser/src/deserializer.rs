@@ -1,203 +1,700 @@
-use super::{ParsedAST,ParseError,Datatypes,FigureContents,Figure, FigureContentsData, Line};
-use image::RgbaImage;
-use nalgebra::Vector2;
+use super::{ParsedAST,ParseError,ParseErrorKind,PacketHeader,Datatypes,ElementTypes,Hello,Capabilities,Span,Style,StyledText,Table,Column,ColumnType,Alignment,Cell,Progress,MAGIC,FigureContents,Figure, FigureContentsData, Line, Rectangle, Ellipse, Polygon, ShapeStyle, Label, VerticalAlignment, Path, PathCommand, LineJoin, LineCap, ImageEncoding, Color, Palette, Group, ClipRectangle, ViewBox, Fit, Heatmap, Colormap, Chart, ChartKind, Axis, Series};
+use super::picture;
+use nalgebra::{Affine2, Matrix3, Vector2};
+use super::buffer::StreamBuffer;
+use super::reader::ReadDeserializer;
+use std::io::Read;
+use std::convert::TryInto;
+/// Turns the byte stream written by an app into `ParsedAST`s.
+///
+/// # Recovering from errors
+/// When a packet is malformed `parse` returns an error and leaves the broken packet at the front
+/// of the buffer, so calling `parse` again reports the same error. To drop the packet and keep
+/// going call `skip_packet`, every packet carries its length so the next one can always be found.
+/// Packets that were completed before the broken one in the same call are returned first, the
+/// error is returned by the following call.
+///
+/// Bytes are buffered in a `StreamBuffer` and parsed in place, so each byte is copied once on
+/// its way in and once more into the `ParsedAST` that owns it.
+///
+/// # Handshake
+/// A deserializer made with `with_handshake` checks whether the stream starts with a `Hello`
+/// packet. If it does the stream is parsed as packets, if not the program is not a GSH app and
+/// everything it writes is passed through as `ParsedAST::String`.
 pub struct Deserializer {
-    buffer: Vec<u8>,
+    buffer: StreamBuffer,
+    mode: Mode,
+    /// Offset from the start of the stream of the first byte in `buffer`
+    offset: u64,
+    /// Bytes of a skipped packet that have not arrived yet
+    skip: usize,
+}
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Mode {
+    /// Waiting for enough bytes to tell if the stream starts with a `Hello`
+    Handshake,
+    Packets,
+    RawText,
+}
+impl Default for Deserializer {
+    /// Parser for a stream of packets without a handshake, see `new`
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl  Deserializer {
     const HEADER_SIZE: usize = 8;
-    const FIGURE_HEADER_SIZW: usize = 8;
+    const HELLO_SIZE: usize = 12;
+    /// Dimensions and flags of a figure
+    const FIGURE_HEADER_SIZE: usize = 12;
+    /// Origin, size and fit of a figure's view box
+    const VIEW_BOX_SIZE: usize = 20;
+    /// Flag set when a figure has a view box
+    const FIGURE_VIEW_BOX: u32 = 0x1;
+    const ELEMENT_HEADER_SIZE: usize = 16;
+    /// Dimensions and encoding of a picture
+    const PICTURE_HEADER_SIZE: usize = 12;
+    const LINE_HEADER_SIZE: usize = 8;
+    const POINT_SIZE: usize = 8;
+    const SHAPE_STYLE_SIZE: usize = 12;
+    const RECTANGLE_SIZE: usize = 12;
+    const ELLIPSE_SIZE: usize = 8;
+    const LABEL_HEADER_SIZE: usize = 20;
+    /// Join, cap, dash offset and dash count of a path
+    const PATH_HEADER_SIZE: usize = 16;
+    const SPAN_HEADER_SIZE: usize = 16;
+    const TABLE_HEADER_SIZE: usize = 8;
+    /// Type and alignment of a column, its name follows
+    const COLUMN_HEADER_SIZE: usize = 8;
+    const ID_SIZE: usize = 4;
+    const PROGRESS_HEADER_SIZE: usize = 24;
+    const COLOR_SIZE: usize = 4;
+    /// Transform, opacity, z index, flags and clip rectangle of a group
+    const GROUP_HEADER_SIZE: usize = 52;
+    /// Flag set when a group has a clip rectangle
+    const GROUP_CLIP: u32 = 0x1;
+    /// Groups nested deeper than this are rejected so a packet can not run the parser or a
+    /// rasterizer out of stack
+    const MAX_GROUP_DEPTH: usize = 32;
+    /// Grid, size, range and colormap of a heatmap
+    const HEATMAP_HEADER_SIZE: usize = 28;
+    /// Kind, flags, axis ranges and series count of a chart
+    const CHART_HEADER_SIZE: usize = 44;
+    /// Flags, color and point count of a chart series
+    const SERIES_HEADER_SIZE: usize = 12;
+    /// An x and a y f64
+    const CHART_POINT_SIZE: usize = 16;
     ///generates new parser
     pub fn new() ->  Deserializer{
-       Deserializer  { buffer: vec![] }
+       Deserializer  {
+           buffer: StreamBuffer::new(),
+           mode: Mode::Packets,
+           offset: 0,
+           skip: 0,
+       }
+    }
+    /// Parser that falls back to treating the stream as raw text if it does not start with a
+    /// `Hello` packet
+    pub fn with_handshake() -> Deserializer {
+        Deserializer {
+            mode: Mode::Handshake,
+            ..Self::new()
+        }
     }
-    ///Takes in buffer if a payload is completed it is outputted in the parsed ast
-    pub fn parse(&mut self, mut buffer: &mut Vec<u8>) -> Result<Vec<ParsedAST>, ParseError> {
-        self.buffer.append(&mut buffer);
+    /// Builds a deserializer that pulls its bytes out of `reader`. The returned value is an
+    /// iterator over the packets in the stream.
+    pub fn from_reader<R: Read>(reader: R) -> ReadDeserializer<R> {
+        Self::new().into_reader(reader)
+    }
+    /// Makes this deserializer pull its bytes out of `reader`
+    pub fn into_reader<R: Read>(self, reader: R) -> ReadDeserializer<R> {
+        ReadDeserializer::new(self, reader)
+    }
+    /// Gets whether the stream is being passed through as raw text because it did not start
+    /// with a `Hello`
+    pub fn is_raw_text(&self) -> bool {
+        self.mode == Mode::RawText
+    }
+    ///Takes in buffer if a payload is completed it is outputted in the parsed ast.
+    ///Every length field is checked against the bytes that actually arrived, so a malformed
+    ///packet is reported as a `ParseError` instead of panicking.
+    pub fn parse(&mut self, buffer: &mut Vec<u8>) -> Result<Vec<ParsedAST>, ParseError> {
+        self.buffer.append(buffer);
+        if self.mode == Mode::Handshake {
+            self.detect_handshake();
+        }
+        match self.mode {
+            Mode::Handshake => return Ok(vec![]),
+            Mode::RawText => return Ok(self.parse_raw_text(false)),
+            Mode::Packets => (),
+        }
+        if self.skip > 0 {
+            let skipped = self.skip.min(self.buffer.len());
+            self.consume(skipped);
+            self.skip -= skipped;
+        }
 
         let mut parsed = vec![];
         loop {
-            if self.buffer.len() >= Self::HEADER_SIZE {
-                let data_type = u32::from_le_bytes([
-                    self.buffer[0],
-                    self.buffer[1],
-                    self.buffer[2],
-                    self.buffer[3],
-                ]);
-
-                const TEXT_TYPE: u32 = Datatypes::Text as u32;
-                const FIGURE_TYPE: u32 = Datatypes::Figure as u32;
-                let node = match data_type {
-                    TEXT_TYPE => self.parse_text(),
-                    FIGURE_TYPE => self.parse_figure(),
-                    _ => Some(Err(ParseError::InvalidDatatype(data_type))),
-                };
-                if let Some(node) = node {
-                    if node.is_ok() {
-                        parsed.push(node.ok().unwrap())
-                    } else {
-                        return Err(node.err().unwrap());
-                    }
-                } else {
+            let header = match self.header() {
+                Some(header) => header,
+                None => return Ok(parsed),
+            };
+            match self.parse_packet(header) {
+                Some(Ok(node)) => {
+                    self.consume(Self::HEADER_SIZE + header.length as usize);
+                    parsed.push(node);
+                }
+                Some(Err(error)) if parsed.is_empty() => return Err(error),
+                Some(Err(_)) => return Ok(parsed),
+                None => {
+                    self.buffer
+                        .reserve_packet(Self::HEADER_SIZE.saturating_add(header.length as usize));
                     return Ok(parsed);
                 }
-            } else {
-                return Ok(parsed);
             }
         }
     }
-
-    //parses contents of text
-    fn parse_text(&mut self) -> Option<Result<ParsedAST, ParseError>> {
-        let length = u32::from_le_bytes([
-            self.buffer[4],
-            self.buffer[5],
-            self.buffer[6],
-            self.buffer[7],
-        ]) as usize;
-        if length + Self::HEADER_SIZE > self.buffer.len() {
-            None
-        } else {
-            let (data_and_header, remaining) = self.buffer.split_at_mut(length + Self::HEADER_SIZE);
-            let (_, data) = data_and_header.split_at_mut(Self::HEADER_SIZE);
-            let string_result = String::from_utf8(data.to_vec());
-            if let Some(string) = string_result.ok() {
-                self.buffer = remaining.to_vec();
-                return Some(Ok(ParsedAST::String(string)));
-            } else {
-                return Some(Err(ParseError::StringNotUTF8));
+    /// Discards the packet at the front of the buffer, used to move past a packet that `parse`
+    /// returned an error for. If the rest of the packet has not arrived yet it is dropped as it
+    /// comes in. Returns the header of the skipped packet or `None` if no header has arrived.
+    pub fn skip_packet(&mut self) -> Option<PacketHeader> {
+        let header = self.header()?;
+        let size = Self::HEADER_SIZE.saturating_add(header.length as usize);
+        let skipped = size.min(self.buffer.len());
+        self.consume(skipped);
+        self.skip = size - skipped;
+        Some(header)
+    }
+    /// Call once the app has closed its end of the stream. Returns raw text that was held back
+    /// waiting for the rest of a character or the handshake, errors if the stream stopped in the
+    /// middle of a packet.
+    pub fn finish(&mut self) -> Result<Vec<ParsedAST>, ParseError> {
+        if self.mode == Mode::Handshake {
+            self.mode = Mode::RawText;
+        }
+        if self.mode == Mode::RawText {
+            return Ok(self.parse_raw_text(true));
+        }
+        if self.buffer.is_empty() {
+            return Ok(vec![]);
+        }
+        Err(ParseError {
+            offset: self.offset,
+            packet: self.header(),
+            ..ParseErrorKind::UnexpectedEof.into()
+        })
+    }
+    /// Compares the start of the stream against a `Hello` header, only the datatype and magic are
+    /// checked so a longer `Hello` from a future version still matches.
+    fn detect_handshake(&mut self) {
+        let mut expected = [0; Self::HEADER_SIZE + 4];
+        expected[..4].copy_from_slice(&(Datatypes::Hello as u32).to_le_bytes());
+        expected[Self::HEADER_SIZE..].copy_from_slice(&MAGIC);
+        let data = self.buffer.as_slice();
+        let matches = |range: std::ops::Range<usize>| {
+            let end = range.end.min(data.len());
+            let start = range.start.min(end);
+            data[start..end] == expected[start..end]
+        };
+        if !matches(0..4) || !matches(Self::HEADER_SIZE..expected.len()) {
+            self.mode = Mode::RawText;
+        } else if data.len() >= expected.len() {
+            self.mode = Mode::Packets;
+        }
+    }
+    /// Turns everything buffered into text. Invalid utf-8 is replaced, an incomplete character at
+    /// the end is kept for the next call unless `flush` is set.
+    fn parse_raw_text(&mut self, flush: bool) -> Vec<ParsedAST> {
+        let data = self.buffer.as_slice();
+        let mut length = data.len();
+        if !flush {
+            let mut start = 0;
+            loop {
+                match std::str::from_utf8(&data[start..]) {
+                    Ok(_) => break,
+                    Err(error) => match error.error_len() {
+                        Some(invalid) => start += error.valid_up_to() + invalid,
+                        None => {
+                            length = start + error.valid_up_to();
+                            break;
+                        }
+                    },
+                }
             }
         }
+        if length == 0 {
+            return vec![];
+        }
+        let text = String::from_utf8_lossy(&data[..length]).into_owned();
+        self.consume(length);
+        vec![ParsedAST::String(text)]
     }
-    /// Parses figure
-    fn parse_figure(&mut self) -> Option<Result<ParsedAST, ParseError>> {
-        if self.is_complete() != true {
-            return None;
+    /// Reads the header of the packet at the front of the buffer
+    fn header(&self) -> Option<PacketHeader> {
+        Some(PacketHeader {
+            data_type: read_u32(self.buffer.as_slice(), 0)?,
+            length: read_u32(self.buffer.as_slice(), 4)?,
+        })
+    }
+    /// Parses the packet at the front of the buffer. Returns `None` if the packet has not been
+    /// fully received yet.
+    fn parse_packet(&self, header: PacketHeader) -> Option<Result<ParsedAST, ParseError>> {
+        let node = match Datatypes::from_u32(header.data_type) {
+            Some(datatype) => Self::parse_payload(datatype, self.payload(header)?),
+            None => Err(ParseErrorKind::InvalidDatatype(header.data_type).into()),
+        };
+        Some(node.map_err(|error| error.in_packet(header, self.offset)))
+    }
+    /// Parses the payload of a packet of type `datatype`
+    fn parse_payload(datatype: Datatypes, data: &[u8]) -> Result<ParsedAST, ParseError> {
+        match datatype {
+            Datatypes::Text => Self::parse_text(data),
+            Datatypes::Figure => Self::parse_figure(data),
+            Datatypes::Hello => Self::parse_hello(data),
+            Datatypes::CapabilityQuery => Ok(ParsedAST::CapabilityQuery),
+            Datatypes::Capabilities => Self::parse_capabilities(data),
+            Datatypes::StyledText => Self::parse_styled_text(data),
+            Datatypes::Table => Self::parse_table(data),
+            Datatypes::Update => Self::parse_update(data),
+            Datatypes::Delete => Self::parse_delete(data),
+            Datatypes::Progress => Self::parse_progress(data),
+            Datatypes::Palette => Self::parse_palette(data),
+            Datatypes::Chart => Self::parse_chart(data),
         }
-        let packet_length = u32::from_le_bytes([
-            self.buffer[4],
-            self.buffer[5],
-            self.buffer[6],
-            self.buffer[7],
-        ]) as usize;
-        let dimensions = Vector2::new(
-            u32::from_le_bytes([
-                self.buffer[8],
-                self.buffer[9],
-                self.buffer[10],
-                self.buffer[11],
-            ]),
-            u32::from_le_bytes([
-                self.buffer[12],
-                self.buffer[13],
-                self.buffer[14],
-                self.buffer[15],
-            ]),
-        );
+    }
+    /// Gets the payload of the packet at the front of the buffer. Returns `None` if the
+    /// packet has not been fully received yet.
+    fn payload(&self, header: PacketHeader) -> Option<&[u8]> {
+        let end = Self::HEADER_SIZE.checked_add(header.length as usize)?;
+        self.buffer.as_slice().get(Self::HEADER_SIZE..end)
+    }
+    /// Removes `length` bytes from the front of the buffer
+    fn consume(&mut self, length: usize) {
+        self.buffer.consume(length);
+        self.offset += length as u64;
+    }
 
-        //figure-buffer
-        let figure_contents =
-            self.buffer[8 + 8..8 + 8 + packet_length - Self::FIGURE_HEADER_SIZW].to_vec();
+    //parses contents of text
+    fn parse_text(data: &[u8]) -> Result<ParsedAST, ParseError> {
+        match String::from_utf8(data.to_vec()) {
+            Ok(string) => Ok(ParsedAST::String(string)),
+            Err(error) => Err(ParseError {
+                offset: (Self::HEADER_SIZE + error.utf8_error().valid_up_to()) as u64,
+                ..ParseErrorKind::StringNotUTF8.into()
+            }),
+        }
+    }
+    /// Parses the handshake, fields added by later versions of the protocol are ignored
+    fn parse_hello(data: &[u8]) -> Result<ParsedAST, ParseError> {
+        if data.len() < Self::HELLO_SIZE || data[..4] != MAGIC {
+            return Err(ParseError {
+                offset: Self::HEADER_SIZE as u64,
+                ..ParseErrorKind::InvalidHello.into()
+            });
+        }
+        let field = |offset| read_u32(data, offset).ok_or(ParseErrorKind::InvalidHello);
+        Ok(ParsedAST::Hello(Hello {
+            version: field(4)?,
+            capabilities: field(8)?,
+        }))
+    }
+    /// Parses a list of spans, each is a header followed by its text
+    fn parse_styled_text(data: &[u8]) -> Result<ParsedAST, ParseError> {
+        let mut spans = vec![];
         let mut i = 0;
-        let mut figure_data = vec![];
-        loop {
-            if i >= figure_contents.len() {
-                break;
-            }
-            let size = u32::from_le_bytes([
-                figure_contents[i + 4],
-                figure_contents[i + 5],
-                figure_contents[i + 6],
-                figure_contents[i + 7],
-            ]);
-            figure_data.push(figure_contents[i..i + size as usize + 16].to_vec());
-            i += size as usize + 16;
-        }
-        let figures: Vec<Result<FigureContents, ParseError>> = figure_data
-            .iter()
-            .map(|data| {
-                let data_type = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
-                match data_type {
-                    0 => Self::parse_picture_element(data),
-                    1 => Self::parse_line_element(data),
-                    _ => Err(ParseError::InvalidDatatype(data_type)),
-                }
-            })
-            .collect();
-        let mut data = vec![];
-
-        for fig in figures.iter() {
-            if fig.is_err() {
-                return Some(Err(fig.clone().err().unwrap()));
+        while i < data.len() {
+            let error = |kind: ParseErrorKind| ParseError {
+                offset: (Self::HEADER_SIZE + i) as u64,
+                element: Some(spans.len()),
+                ..kind.into()
+            };
+            let field = |offset| {
+                read_u32(data, i + offset).ok_or_else(|| error(ParseErrorKind::InvalidSpan))
+            };
+            let color = |offset| {
+                read_color(data, i + offset).ok_or_else(|| error(ParseErrorKind::InvalidSpan))
+            };
+            let (foreground, background, flags) = (color(0)?, color(4)?, field(8)?);
+            let length = field(12)? as usize;
+            let start = i + Self::SPAN_HEADER_SIZE;
+            let text = start
+                .checked_add(length)
+                .and_then(|end| data.get(start..end))
+                .ok_or_else(|| error(ParseErrorKind::InvalidSpan))?;
+            let text = String::from_utf8(text.to_vec())
+                .map_err(|_| error(ParseErrorKind::StringNotUTF8))?;
+            spans.push(Span {
+                text,
+                style: Style::from_flags(foreground, background, flags),
+            });
+            i = start + length;
+        }
+        Ok(ParsedAST::StyledText(StyledText { spans }))
+    }
+    /// Parses a table, the cells of each row are read by the types of the columns
+    fn parse_table(data: &[u8]) -> Result<ParsedAST, ParseError> {
+        let error = |kind: ParseErrorKind, offset: usize, row: Option<usize>| ParseError {
+            offset: (Self::HEADER_SIZE + offset) as u64,
+            element: row,
+            ..kind.into()
+        };
+        let header_field =
+            |offset| read_u32(data, offset).ok_or_else(|| error(ParseErrorKind::InvalidTable, 0, None));
+        let (column_count, row_count) = (header_field(0)?, header_field(4)?);
+        let mut i = Self::TABLE_HEADER_SIZE;
+        let mut columns = vec![];
+        for _ in 0..column_count {
+            let field =
+                |offset| read_u32(data, i + offset).ok_or_else(|| error(ParseErrorKind::InvalidTable, i, None));
+            let column_type = field(0)?;
+            let column_type = ColumnType::from_u32(column_type)
+                .ok_or_else(|| error(ParseErrorKind::InvalidColumnType(column_type), i, None))?;
+            let alignment = Alignment::from_u32(field(4)?)
+                .ok_or_else(|| error(ParseErrorKind::InvalidTable, i, None))?;
+            let (name, end) = parse_string(data, i + Self::COLUMN_HEADER_SIZE, ParseErrorKind::InvalidTable)
+                .map_err(|kind| error(kind, i, None))?;
+            columns.push(Column {
+                name,
+                column_type,
+                alignment,
+            });
+            i = end;
+        }
+        if columns.is_empty() && row_count > 0 {
+            return Err(error(ParseErrorKind::InvalidTable, 0, None));
+        }
+        let mut rows = vec![];
+        for row in 0..row_count as usize {
+            let mut cells = Vec::with_capacity(columns.len());
+            for column in columns.iter() {
+                let (cell, end) = parse_cell(data, i, column.column_type)
+                    .map_err(|kind| error(kind, i, Some(row)))?;
+                cells.push(cell);
+                i = end;
             }
-            if let Some(f) = fig.clone().ok() {
-                data.push(f);
+            rows.push(cells);
+        }
+        if i != data.len() {
+            return Err(error(ParseErrorKind::InvalidTable, i, None));
+        }
+        Ok(ParsedAST::Table(Table { columns, rows }))
+    }
+    /// Parses an update, the packet it holds is parsed the same way as one in the stream
+    fn parse_update(data: &[u8]) -> Result<ParsedAST, ParseError> {
+        let invalid = || ParseError {
+            offset: Self::HEADER_SIZE as u64,
+            ..ParseErrorKind::InvalidUpdate.into()
+        };
+        let id = read_u32(data, 0).ok_or_else(invalid)?;
+        let packet = &data[Self::ID_SIZE..];
+        let datatype = read_u32(packet, 0)
+            .and_then(Datatypes::from_u32)
+            .filter(|datatype| datatype.is_output())
+            .ok_or_else(invalid)?;
+        let length = read_u32(packet, 4).ok_or_else(invalid)?;
+        if length as usize != packet.len() - Self::HEADER_SIZE {
+            return Err(invalid());
+        }
+        let node = Self::parse_payload(datatype, &packet[Self::HEADER_SIZE..]).map_err(|error| {
+            ParseError {
+                offset: error.offset + (Self::HEADER_SIZE + Self::ID_SIZE) as u64,
+                ..error
             }
+        })?;
+        Ok(ParsedAST::Update(id, Box::new(node)))
+    }
+    fn parse_progress(data: &[u8]) -> Result<ParsedAST, ParseError> {
+        let error = |kind: ParseErrorKind| ParseError {
+            offset: Self::HEADER_SIZE as u64,
+            ..kind.into()
+        };
+        let invalid = || error(ParseErrorKind::InvalidProgress);
+        let flags = read_u32(data, 0).ok_or_else(invalid)?;
+        let eta = read_u32(data, 4).ok_or_else(invalid)?;
+        let current = read_u64(data, 8).ok_or_else(invalid)?;
+        let total = read_u64(data, 16).ok_or_else(invalid)?;
+        let (label, end) = parse_string(data, Self::PROGRESS_HEADER_SIZE, ParseErrorKind::InvalidProgress)
+            .map_err(error)?;
+        let (units, _) = parse_string(data, end, ParseErrorKind::InvalidProgress).map_err(error)?;
+        Ok(ParsedAST::Progress(Progress::from_fields(
+            flags, label, current, total, units, eta,
+        )))
+    }
+    /// Parses a chart, errors in a series carry the index of the series
+    fn parse_chart(data: &[u8]) -> Result<ParsedAST, ParseError> {
+        let error = |offset: usize, series: Option<usize>| ParseError {
+            offset: (Self::HEADER_SIZE + offset) as u64,
+            element: series,
+            ..ParseErrorKind::InvalidChart.into()
+        };
+        let field = |offset| read_f64(data, offset).ok_or_else(|| error(0, None));
+        let kind = read_u32(data, 0)
+            .and_then(ChartKind::from_u32)
+            .ok_or_else(|| error(0, None))?;
+        let flags = read_u32(data, 4).ok_or_else(|| error(0, None))?;
+        if flags & !(Chart::LEGEND | Chart::X_RANGE | Chart::Y_RANGE) != 0 {
+            return Err(error(0, None));
         }
-        self.buffer = self.buffer[packet_length + Self::HEADER_SIZE..self.buffer.len()].to_vec();
-        let figure = Figure {
-            contents: data,
-            dimensions,
+        let x_range = (field(8)?, field(16)?);
+        let y_range = (field(24)?, field(32)?);
+        let series_count = read_u32(data, 40).ok_or_else(|| error(0, None))?;
+        let mut i = Self::CHART_HEADER_SIZE;
+        let mut text = || {
+            let (string, end) = parse_string(data, i, ParseErrorKind::InvalidChart).map_err(|kind| ParseError {
+                kind,
+                ..error(i, None)
+            })?;
+            i = end;
+            Ok::<_, ParseError>(string)
         };
-        return Some(Ok(ParsedAST::Figure(figure)));
+        let (title, x_label, y_label) = (text()?, text()?, text()?);
+        let mut series = vec![];
+        for index in 0..series_count as usize {
+            let field = |offset| read_u32(data, i + offset).ok_or_else(|| error(i, Some(index)));
+            let series_flags = field(0)?;
+            if series_flags & !Series::HAS_COLOR != 0 {
+                return Err(error(i, Some(index)));
+            }
+            let color = read_color(data, i + 4).ok_or_else(|| error(i, Some(index)))?;
+            let point_count = field(8)? as usize;
+            let (name, end) = parse_string(data, i + Self::SERIES_HEADER_SIZE, ParseErrorKind::InvalidChart)
+                .map_err(|kind| ParseError {
+                    kind,
+                    ..error(i, Some(index))
+                })?;
+            // the length is checked before reading so a huge count can not allocate
+            let points = point_count
+                .checked_mul(Self::CHART_POINT_SIZE)
+                .and_then(|length| data.get(end..end.checked_add(length)?))
+                .ok_or_else(|| error(i, Some(index)))?;
+            let points = points
+                .chunks_exact(Self::CHART_POINT_SIZE)
+                .map(|point| Some(Vector2::new(read_f64(point, 0)?, read_f64(point, 8)?)))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| error(i, Some(index)))?;
+            i = end + points.len() * Self::CHART_POINT_SIZE;
+            series.push(Series {
+                name,
+                color: Some(color).filter(|_| series_flags & Series::HAS_COLOR != 0),
+                points,
+            });
+        }
+        if i != data.len() {
+            return Err(error(i, None));
+        }
+        Ok(ParsedAST::Chart(Chart {
+            kind,
+            title,
+            x_axis: Axis {
+                label: x_label,
+                range: Some(x_range).filter(|_| flags & Chart::X_RANGE != 0),
+            },
+            y_axis: Axis {
+                label: y_label,
+                range: Some(y_range).filter(|_| flags & Chart::Y_RANGE != 0),
+            },
+            series,
+            legend: flags & Chart::LEGEND != 0,
+        }))
+    }
+    fn parse_delete(data: &[u8]) -> Result<ParsedAST, ParseError> {
+        let id = read_u32(data, 0).ok_or_else(|| ParseError {
+            offset: Self::HEADER_SIZE as u64,
+            ..ParseErrorKind::InvalidUpdate.into()
+        })?;
+        Ok(ParsedAST::Delete(id))
     }
-    fn parse_picture_element(data: &Vec<u8>) -> Result<FigureContents, ParseError> {
-        if data.len() < 6 * 4 {
-            return Err(ParseError::InvalidImage);
+    fn parse_palette(data: &[u8]) -> Result<ParsedAST, ParseError> {
+        if !data.len().is_multiple_of(Self::COLOR_SIZE) {
+            return Err(ParseError {
+                offset: (Self::HEADER_SIZE + data.len() / Self::COLOR_SIZE * Self::COLOR_SIZE) as u64,
+                ..ParseErrorKind::InvalidPalette.into()
+            });
         }
-        let position = Vector2::new(
-            i32::from_le_bytes([data[8 + 0], data[8 + 1], data[8 + 2], data[8 + 3]]),
-            i32::from_le_bytes([data[8 + 4], data[8 + 5], data[8 + 6], data[8 + 7]]),
-        );
-
+        let colors = data
+            .chunks_exact(Self::COLOR_SIZE)
+            .filter_map(|color| read_color(color, 0))
+            .collect();
+        Ok(ParsedAST::Palette(Palette { colors }))
+    }
+    /// Parses the reply to a capability query, fields added by later versions are ignored
+    fn parse_capabilities(data: &[u8]) -> Result<ParsedAST, ParseError> {
+        let field = |offset| {
+            read_u32(data, offset).ok_or_else(|| ParseError {
+                offset: Self::HEADER_SIZE as u64,
+                ..ParseErrorKind::InvalidCapabilities.into()
+            })
+        };
+        Ok(ParsedAST::Capabilities(Capabilities {
+            datatypes: field(0)?,
+            figure_elements: field(4)?,
+            max_image_size: Vector2::new(field(8)?, field(12)?),
+            color_depth: field(16)?,
+        }))
+    }
+    /// Parses figure
+    fn parse_figure(data: &[u8]) -> Result<ParsedAST, ParseError> {
+        let invalid_figure = |offset: usize, element: Option<usize>| ParseError {
+            offset: (Self::HEADER_SIZE + offset) as u64,
+            element,
+            ..ParseErrorKind::InvalidFigure.into()
+        };
         let dimensions = Vector2::new(
-            u32::from_le_bytes([data[16 + 0], data[16 + 1], data[16 + 2], data[16 + 3]]),
-            u32::from_le_bytes([data[16 + 4], data[16 + 5], data[16 + 6], data[16 + 7]]),
+            read_u32(data, 0).ok_or_else(|| invalid_figure(0, None))?,
+            read_u32(data, 4).ok_or_else(|| invalid_figure(0, None))?,
         );
-        if let Some(image) =
-            RgbaImage::from_raw(dimensions.x, dimensions.y, data[8..data.len()].to_vec())
-        {
-            return Ok(FigureContents {
-                data: FigureContentsData::Image(image),
-                position,
+        let flags = read_u32(data, 8).ok_or_else(|| invalid_figure(0, None))?;
+        if flags & !Self::FIGURE_VIEW_BOX != 0 {
+            return Err(invalid_figure(0, None));
+        }
+        let mut start = Self::FIGURE_HEADER_SIZE;
+        let mut view_box = None;
+        if flags & Self::FIGURE_VIEW_BOX != 0 {
+            let field = |offset| read_f32(data, start + offset).ok_or_else(|| invalid_figure(0, None));
+            let fit = read_u32(data, start + 16)
+                .and_then(Fit::from_u32)
+                .ok_or_else(|| invalid_figure(0, None))?;
+            view_box = Some(ViewBox {
+                origin: Vector2::new(field(0)?, field(4)?),
+                size: Vector2::new(field(8)?, field(12)?),
+                fit,
             });
+            start += Self::VIEW_BOX_SIZE;
+        }
+        let contents = Self::parse_elements(data, start, 0).map_err(|error| ParseError {
+            offset: error.offset + Self::HEADER_SIZE as u64,
+            ..error
+        })?;
+        Ok(ParsedAST::Figure(Figure {
+            contents,
+            dimensions,
+            view_box,
+        }))
+    }
+    /// Parses the figure elements from `start` to the end of `data`, which are nested in `depth`
+    /// groups. Errors are placed at the element that failed, relative to the start of `data`.
+    fn parse_elements(data: &[u8], start: usize, depth: usize) -> Result<Vec<FigureContents>, ParseError> {
+        let invalid_figure = |offset: usize, element: Option<usize>| ParseError {
+            offset: offset as u64,
+            element,
+            ..ParseErrorKind::InvalidFigure.into()
+        };
+        let mut contents = vec![];
+        let mut i = start;
+        while i < data.len() {
+            let index = contents.len();
+            let element_type = read_u32(data, i).ok_or_else(|| invalid_figure(i, Some(index)))?;
+            let size = read_u32(data, i + 4).ok_or_else(|| invalid_figure(i, Some(index)))? as usize;
+            let end = size
+                .checked_add(Self::ELEMENT_HEADER_SIZE)
+                .and_then(|size| size.checked_add(i))
+                .ok_or_else(|| invalid_figure(i, Some(index)))?;
+            let element = data.get(i..end).ok_or_else(|| invalid_figure(i, Some(index)))?;
+            const IMAGE_TYPE: u32 = ElementTypes::Image as u32;
+            const LINE_TYPE: u32 = ElementTypes::Line as u32;
+            const RECTANGLE_TYPE: u32 = ElementTypes::Rectangle as u32;
+            const ELLIPSE_TYPE: u32 = ElementTypes::Ellipse as u32;
+            const POLYGON_TYPE: u32 = ElementTypes::Polygon as u32;
+            const TEXT_ELEMENT_TYPE: u32 = ElementTypes::Text as u32;
+            const PATH_TYPE: u32 = ElementTypes::Path as u32;
+            const GROUP_TYPE: u32 = ElementTypes::Group as u32;
+            const HEATMAP_TYPE: u32 = ElementTypes::Heatmap as u32;
+            if element_type == GROUP_TYPE {
+                // errors in the children are placed at the child but keep the index of the
+                // element at this level
+                contents.push(Self::parse_group_element(element, depth).map_err(|error| ParseError {
+                    offset: error.offset + i as u64,
+                    element: Some(index),
+                    ..error
+                })?);
+                i = end;
+                continue;
+            }
+            let parsed = match element_type {
+                IMAGE_TYPE => Self::parse_picture_element(element),
+                LINE_TYPE => Self::parse_line_element(element),
+                RECTANGLE_TYPE => Self::parse_rectangle_element(element),
+                ELLIPSE_TYPE => Self::parse_ellipse_element(element),
+                POLYGON_TYPE => Self::parse_polygon_element(element),
+                TEXT_ELEMENT_TYPE => Self::parse_label_element(element),
+                PATH_TYPE => Self::parse_path_element(element),
+                HEATMAP_TYPE => Self::parse_heatmap_element(element),
+                _ => Err(ParseErrorKind::InvalidElementType(element_type)),
+            };
+            contents.push(parsed.map_err(|kind| ParseError {
+                offset: i as u64,
+                element: Some(index),
+                ..kind.into()
+            })?);
+            i = end;
         }
-        return Err(ParseError::InvalidImage);
+        Ok(contents)
     }
-    fn parse_line_element(data: &Vec<u8>) -> Result<FigureContents, ParseError> {
-        if data.len()%4!=0{
-            return Err(ParseError::InvalidLine)
-
+    fn parse_group_element(data: &[u8], depth: usize) -> Result<FigureContents, ParseError> {
+        let fields = Self::ELEMENT_HEADER_SIZE;
+        let field = |offset| read_f32(data, fields + offset).ok_or(ParseErrorKind::InvalidGroup);
+        if depth >= Self::MAX_GROUP_DEPTH {
+            return Err(ParseErrorKind::InvalidGroup.into());
         }
-        if data.len() < 6 * 4 {
-            return Err(ParseError::InvalidLine);
+        let position = Self::parse_position(data).ok_or(ParseErrorKind::InvalidGroup)?;
+        // sent in the order of an SVG matrix(a b c d e f)
+        let (a, b, c, d, e, f) = (field(0)?, field(4)?, field(8)?, field(12)?, field(16)?, field(20)?);
+        let transform = Affine2::from_matrix_unchecked(Matrix3::new(a, c, e, b, d, f, 0.0, 0.0, 1.0));
+        let opacity = field(24)?;
+        let z_index = read_i32(data, fields + 28).ok_or(ParseErrorKind::InvalidGroup)?;
+        let flags = read_u32(data, fields + 32).ok_or(ParseErrorKind::InvalidGroup)?;
+        if flags & !Self::GROUP_CLIP != 0 {
+            return Err(ParseErrorKind::InvalidGroup.into());
         }
-        let position = Vector2::new(
-            i32::from_le_bytes([data[8 + 0], data[8 + 1], data[8 + 2], data[8 + 3]]),
-            i32::from_le_bytes([data[8 + 4], data[8 + 5], data[8 + 6], data[8 + 7]]),
+        let clip = ClipRectangle {
+            position: Vector2::new(field(36)?, field(40)?),
+            size: Vector2::new(field(44)?, field(48)?),
+        };
+        let children = Self::parse_elements(data, fields + Self::GROUP_HEADER_SIZE, depth + 1)?;
+        Ok(FigureContents {
+            data: FigureContentsData::Group(Group {
+                transform,
+                opacity,
+                clip: if flags & Self::GROUP_CLIP != 0 { Some(clip) } else { None },
+                z_index,
+                children,
+            }),
+            position,
+        })
+    }
+    /// Reads the start position out of a figure element header
+    fn parse_position(data: &[u8]) -> Option<Vector2<i32>> {
+        Some(Vector2::new(read_i32(data, 8)?, read_i32(data, 12)?))
+    }
+    fn parse_picture_element(data: &[u8]) -> Result<FigureContents, ParseErrorKind> {
+        let position = Self::parse_position(data).ok_or(ParseErrorKind::InvalidImage)?;
+        let dimensions = Vector2::new(
+            read_u32(data, 16).ok_or(ParseErrorKind::InvalidImage)?,
+            read_u32(data, 20).ok_or(ParseErrorKind::InvalidImage)?,
         );
-        let color = u32::from_le_bytes([data[16 + 0], data[16 + 1], data[16 + 2], data[16 + 3]]);
-        let thickness =
-            f32::from_le_bytes([data[16 + 4], data[16 + 5], data[16 + 6], data[16 + 7]]);
-        let mut segments = vec![];
-        for i in (3)..(data.len() / 8) {
-            let index = i * 8;
-            segments.push(Vector2::new(
-                f32::from_le_bytes([
-                    data[index + 0],
-                    data[index + 1],
-                    data[index + 2],
-                    data[index + 3],
-                ]),
-                f32::from_le_bytes([
-                    data[index + 4],
-                    data[index + 5],
-                    data[index + 6],
-                    data[index + 7],
-                ]),
-            ));
-        }
-        return Ok(FigureContents {
+        let encoding = read_u32(data, 24).ok_or(ParseErrorKind::InvalidImage)?;
+        let encoding = ImageEncoding::from_u32(encoding)
+            .ok_or(ParseErrorKind::InvalidImageEncoding(encoding))?;
+        let pixels = data
+            .get(Self::ELEMENT_HEADER_SIZE + Self::PICTURE_HEADER_SIZE..)
+            .ok_or(ParseErrorKind::InvalidImage)?;
+        let image = picture::decode(encoding, dimensions.x, dimensions.y, pixels)
+            .ok_or(ParseErrorKind::InvalidImage)?;
+        Ok(FigureContents {
+            data: FigureContentsData::Image(image),
+            position,
+        })
+    }
+    fn parse_line_element(data: &[u8]) -> Result<FigureContents, ParseErrorKind> {
+        let position = Self::parse_position(data).ok_or(ParseErrorKind::InvalidLine)?;
+        let color = read_color(data, 16).ok_or(ParseErrorKind::InvalidLine)?;
+        let thickness = read_f32(data, 20).ok_or(ParseErrorKind::InvalidLine)?;
+        let segments = data
+            .get(Self::ELEMENT_HEADER_SIZE + Self::LINE_HEADER_SIZE..)
+            .and_then(Self::parse_points)
+            .ok_or(ParseErrorKind::InvalidLine)?;
+        Ok(FigureContents {
             data: FigureContentsData::Line(Line {
                 color,
                 thickness,
@@ -205,29 +702,278 @@ impl  Deserializer {
             }),
 
             position,
-        });
+        })
     }
-    /// Gets if current packet is complete
-    fn is_complete(&self) -> bool {
-        if self.buffer.len() < 8 {
-            return false;
+    fn parse_label_element(data: &[u8]) -> Result<FigureContents, ParseErrorKind> {
+        let field = |offset| {
+            read_u32(data, Self::ELEMENT_HEADER_SIZE + offset).ok_or(ParseErrorKind::InvalidLabel)
+        };
+        let color = read_color(data, Self::ELEMENT_HEADER_SIZE).ok_or(ParseErrorKind::InvalidLabel)?;
+        let font_size = f32::from_bits(field(4)?);
+        let rotation = f32::from_bits(field(8)?);
+        let horizontal = Alignment::from_u32(field(12)?).ok_or(ParseErrorKind::InvalidLabel)?;
+        let vertical = VerticalAlignment::from_u32(field(16)?).ok_or(ParseErrorKind::InvalidLabel)?;
+        let (text, end) = parse_string(
+            data,
+            Self::ELEMENT_HEADER_SIZE + Self::LABEL_HEADER_SIZE,
+            ParseErrorKind::InvalidLabel,
+        )?;
+        if end != data.len() {
+            return Err(ParseErrorKind::InvalidLabel);
         }
-        let data_size = u32::from_le_bytes([
-            self.buffer[4],
-            self.buffer[5],
-            self.buffer[6],
-            self.buffer[7],
-        ]);
-        if self.buffer.len() >= data_size as usize + 8 {
-            return true;
-        } else {
-            return false;
+        Ok(FigureContents {
+            data: FigureContentsData::Text(Label {
+                text,
+                font_size,
+                color,
+                rotation,
+                horizontal,
+                vertical,
+            }),
+            position: Self::parse_position(data).ok_or(ParseErrorKind::InvalidLabel)?,
+        })
+    }
+    fn parse_path_element(data: &[u8]) -> Result<FigureContents, ParseErrorKind> {
+        let fields = Self::ELEMENT_HEADER_SIZE + Self::SHAPE_STYLE_SIZE;
+        let field = |offset| read_u32(data, fields + offset).ok_or(ParseErrorKind::InvalidPath);
+        let style = Self::parse_shape_style(data).ok_or(ParseErrorKind::InvalidPath)?;
+        let join = LineJoin::from_u32(field(0)?).ok_or(ParseErrorKind::InvalidPath)?;
+        let cap = LineCap::from_u32(field(4)?).ok_or(ParseErrorKind::InvalidPath)?;
+        let dash_offset = f32::from_bits(field(8)?);
+        let dash_count = field(12)? as usize;
+        let dashes_start = fields + Self::PATH_HEADER_SIZE;
+        let dashes_end = dash_count
+            .checked_mul(4)
+            .and_then(|length| dashes_start.checked_add(length))
+            .filter(|end| *end <= data.len())
+            .ok_or(ParseErrorKind::InvalidPath)?;
+        let dashes = data[dashes_start..dashes_end]
+            .chunks_exact(4)
+            .map(|dash| read_f32(dash, 0))
+            .collect::<Option<Vec<f32>>>()
+            .ok_or(ParseErrorKind::InvalidPath)?;
+        let mut commands = vec![];
+        let mut offset = dashes_end;
+        while offset < data.len() {
+            let (command, end) =
+                Self::parse_path_command(data, offset).ok_or(ParseErrorKind::InvalidPath)?;
+            commands.push(command);
+            offset = end;
+        }
+        Ok(FigureContents {
+            data: FigureContentsData::Path(Path {
+                commands,
+                style,
+                join,
+                cap,
+                dashes,
+                dash_offset,
+            }),
+            position: Self::parse_position(data).ok_or(ParseErrorKind::InvalidPath)?,
+        })
+    }
+    /// Reads the path command at `offset`, returns it along with the offset of the byte after it
+    fn parse_path_command(data: &[u8], offset: usize) -> Option<(PathCommand, usize)> {
+        // fields of every command start after its tag and are mostly points
+        let point = |field: usize| {
+            Some(Vector2::new(
+                read_f32(data, offset + 4 + field)?,
+                read_f32(data, offset + 8 + field)?,
+            ))
+        };
+        let (command, fields_size) = match read_u32(data, offset)? {
+            PathCommand::MOVE_TO => (PathCommand::MoveTo(point(0)?), 8),
+            PathCommand::LINE_TO => (PathCommand::LineTo(point(0)?), 8),
+            PathCommand::QUADRATIC_TO => (
+                PathCommand::QuadraticTo {
+                    control: point(0)?,
+                    end: point(8)?,
+                },
+                16,
+            ),
+            PathCommand::CUBIC_TO => (
+                PathCommand::CubicTo {
+                    control_1: point(0)?,
+                    control_2: point(8)?,
+                    end: point(16)?,
+                },
+                24,
+            ),
+            PathCommand::ARC_TO => {
+                let flags = read_u32(data, offset + 16)?;
+                (
+                    PathCommand::ArcTo {
+                        radii: point(0)?,
+                        rotation: read_f32(data, offset + 12)?,
+                        large_arc: flags & 1 != 0,
+                        sweep: flags & 2 != 0,
+                        end: point(16)?,
+                    },
+                    24,
+                )
+            }
+            PathCommand::CLOSE => (PathCommand::Close, 0),
+            _ => return None,
+        };
+        Some((command, offset + 4 + fields_size))
+    }
+    /// Parses a heatmap, failing on an unknown colormap or values that do not fill the grid
+    fn parse_heatmap_element(data: &[u8]) -> Result<FigureContents, ParseErrorKind> {
+        let fields = Self::ELEMENT_HEADER_SIZE;
+        let field = |offset| read_f32(data, fields + offset).ok_or(ParseErrorKind::InvalidHeatmap);
+        let grid = Vector2::new(
+            read_u32(data, fields).ok_or(ParseErrorKind::InvalidHeatmap)?,
+            read_u32(data, fields + 4).ok_or(ParseErrorKind::InvalidHeatmap)?,
+        );
+        let size = Vector2::new(field(8)?, field(12)?);
+        let range = (field(16)?, field(20)?);
+        let colormap = read_u32(data, fields + 24)
+            .and_then(Colormap::from_u32)
+            .ok_or(ParseErrorKind::InvalidHeatmap)?;
+        let values = data
+            .get(fields + Self::HEATMAP_HEADER_SIZE..)
+            .ok_or(ParseErrorKind::InvalidHeatmap)?;
+        let cells = grid.x as u64 * grid.y as u64;
+        if cells.checked_mul(4) != Some(values.len() as u64) {
+            return Err(ParseErrorKind::InvalidHeatmap);
+        }
+        let values = values
+            .chunks_exact(4)
+            .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+            .collect();
+        Ok(FigureContents {
+            data: FigureContentsData::Heatmap(Heatmap {
+                grid,
+                size,
+                values,
+                range,
+                colormap,
+            }),
+            position: Self::parse_position(data).ok_or(ParseErrorKind::InvalidHeatmap)?,
+        })
+    }
+    /// Reads a list of points, returns `None` if it ends in a partial point
+    fn parse_points(data: &[u8]) -> Option<Vec<Vector2<f32>>> {
+        if !data.len().is_multiple_of(Self::POINT_SIZE) {
+            return None;
+        }
+        data.chunks_exact(Self::POINT_SIZE)
+            .map(|point| Some(Vector2::new(read_f32(point, 0)?, read_f32(point, 4)?)))
+            .collect()
+    }
+    /// Reads the fill and stroke that start the payload of every shape element
+    fn parse_shape_style(data: &[u8]) -> Option<ShapeStyle> {
+        Some(ShapeStyle {
+            fill: read_color(data, 16)?,
+            stroke: read_color(data, 20)?,
+            stroke_width: read_f32(data, 24)?,
+        })
+    }
+    fn parse_rectangle_element(data: &[u8]) -> Result<FigureContents, ParseErrorKind> {
+        let fields = Self::ELEMENT_HEADER_SIZE + Self::SHAPE_STYLE_SIZE;
+        if data.len() != fields + Self::RECTANGLE_SIZE {
+            return Err(ParseErrorKind::InvalidShape);
+        }
+        let field = |offset| read_f32(data, fields + offset).ok_or(ParseErrorKind::InvalidShape);
+        let rectangle = Rectangle {
+            size: Vector2::new(field(0)?, field(4)?),
+            corner_radius: field(8)?,
+            style: Self::parse_shape_style(data).ok_or(ParseErrorKind::InvalidShape)?,
+        };
+        Ok(FigureContents {
+            data: FigureContentsData::Rectangle(rectangle),
+            position: Self::parse_position(data).ok_or(ParseErrorKind::InvalidShape)?,
+        })
+    }
+    fn parse_ellipse_element(data: &[u8]) -> Result<FigureContents, ParseErrorKind> {
+        let fields = Self::ELEMENT_HEADER_SIZE + Self::SHAPE_STYLE_SIZE;
+        if data.len() != fields + Self::ELLIPSE_SIZE {
+            return Err(ParseErrorKind::InvalidShape);
         }
+        let field = |offset| read_f32(data, fields + offset).ok_or(ParseErrorKind::InvalidShape);
+        let ellipse = Ellipse {
+            radii: Vector2::new(field(0)?, field(4)?),
+            style: Self::parse_shape_style(data).ok_or(ParseErrorKind::InvalidShape)?,
+        };
+        Ok(FigureContents {
+            data: FigureContentsData::Ellipse(ellipse),
+            position: Self::parse_position(data).ok_or(ParseErrorKind::InvalidShape)?,
+        })
+    }
+    fn parse_polygon_element(data: &[u8]) -> Result<FigureContents, ParseErrorKind> {
+        let style = Self::parse_shape_style(data).ok_or(ParseErrorKind::InvalidShape)?;
+        let points = data
+            .get(Self::ELEMENT_HEADER_SIZE + Self::SHAPE_STYLE_SIZE..)
+            .and_then(Self::parse_points)
+            .ok_or(ParseErrorKind::InvalidShape)?;
+        Ok(FigureContents {
+            data: FigureContentsData::Polygon(Polygon { points, style }),
+            position: Self::parse_position(data).ok_or(ParseErrorKind::InvalidShape)?,
+        })
     }
 }
+/// Reads four bytes starting at `offset`, returns `None` if they run past the end of `data`
+fn read_bytes(data: &[u8], offset: usize) -> Option<[u8; 4]> {
+    data.get(offset..offset.checked_add(4)?)?.try_into().ok()
+}
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    data.get(offset..offset.checked_add(8)?)?
+        .try_into()
+        .ok()
+        .map(u64::from_le_bytes)
+}
+fn read_f64(data: &[u8], offset: usize) -> Option<f64> {
+    read_u64(data, offset).map(f64::from_bits)
+}
+/// Reads a string prefixed by its length, returns it along with the offset of the byte after it.
+/// Fails with `invalid` if the string runs past the end of `data`.
+fn parse_string(
+    data: &[u8],
+    offset: usize,
+    invalid: ParseErrorKind,
+) -> Result<(String, usize), ParseErrorKind> {
+    let length = read_u32(data, offset).ok_or_else(|| invalid.clone())? as usize;
+    let start = offset + 4;
+    let bytes = start
+        .checked_add(length)
+        .and_then(|end| data.get(start..end))
+        .ok_or(invalid)?;
+    let string = String::from_utf8(bytes.to_vec()).map_err(|_| ParseErrorKind::StringNotUTF8)?;
+    Ok((string, start + length))
+}
+/// Reads a table cell of `column_type`, returns it along with the offset of the byte after it
+fn parse_cell(data: &[u8], offset: usize, column_type: ColumnType) -> Result<(Cell, usize), ParseErrorKind> {
+    if column_type == ColumnType::String {
+        let (string, end) = parse_string(data, offset, ParseErrorKind::InvalidTable)?;
+        return Ok((Cell::String(string), end));
+    }
+    let value = read_u64(data, offset).ok_or(ParseErrorKind::InvalidTable)?;
+    let cell = match column_type {
+        ColumnType::String => unreachable!(),
+        ColumnType::Integer => Cell::Integer(value as i64),
+        ColumnType::Float => Cell::Float(f64::from_bits(value)),
+        ColumnType::Bytes => Cell::Bytes(value),
+        ColumnType::Date => Cell::Date(value as i64),
+    };
+    Ok((cell, offset + 8))
+}
+fn read_color(data: &[u8], offset: usize) -> Option<Color> {
+    read_bytes(data, offset).map(Color::from_bytes)
+}
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    read_bytes(data, offset).map(u32::from_le_bytes)
+}
+fn read_i32(data: &[u8], offset: usize) -> Option<i32> {
+    read_bytes(data, offset).map(i32::from_le_bytes)
+}
+fn read_f32(data: &[u8], offset: usize) -> Option<f32> {
+    read_bytes(data, offset).map(f32::from_le_bytes)
+}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::serializer;
+    use proptest::prelude::*;
     #[test]
     fn test_empty() {
         let mut p = Deserializer::new();
@@ -236,7 +982,7 @@ mod tests {
     #[test]
     fn parse_text() {
         let mut p = Deserializer::new();
-        let parsed_res = p.parse(&mut vec![0, 0, 0, 0, 1, 0, 0, 0, 'a' as u8]);
+        let parsed_res = p.parse(&mut vec![0, 0, 0, 0, 1, 0, 0, 0, b'a']);
         if parsed_res.is_err() {
             panic!("{:?}", parsed_res.err().unwrap())
         }
@@ -248,7 +994,7 @@ mod tests {
     fn parse_text_partial() {
         let mut p = Deserializer::new();
         let parsed = p
-            .parse(&mut vec![0, 0, 0, 0, 3, 0, 0, 0, 'a' as u8])
+            .parse(&mut vec![0, 0, 0, 0, 3, 0, 0, 0, b'a'])
             .ok()
             .unwrap();
         assert_eq!(parsed.len(), 0);
@@ -257,10 +1003,12 @@ mod tests {
     fn parse_figure() {
         let mut p = Deserializer::new();
         #[rustfmt::skip]
-        let parsed_res = p.parse(&mut vec![1, 0, 0, 0, 
-            8, 0, 0, 0, 
+        let parsed_res = p.parse(&mut vec![1, 0, 0, 0,
+            12, 0, 0, 0,
             5, 0, 0, 0,
-            5, 0, 0, 0]);
+            5, 0, 0, 0,
+            //flags
+            0, 0, 0, 0]);
         if parsed_res.is_err() {
             panic!("{:?}", parsed_res.err().unwrap());
         }
@@ -268,6 +1016,7 @@ mod tests {
             parsed_res.ok().unwrap()[0],
             ParsedAST::Figure(Figure {
                 dimensions: Vector2::new(5, 5),
+                view_box: None,
                 contents: vec![]
             })
         )
@@ -278,17 +1027,19 @@ mod tests {
         let figure_element_size = 4 * 4;
         let line_size = 4 + 4 + 2 * (4 + 4);
     
-        let t_bytes = (1.0 as f32).to_le_bytes();
+        let t_bytes = 1.0f32.to_le_bytes();
         assert_eq!(t_bytes.len(),4);
-        let e_bytes = (1.0 as f32).to_le_bytes();
-        let s_bytes = (0.0 as f32).to_le_bytes();
-        let size_bytes = ((8 + line_size + figure_element_size) as u32).to_le_bytes();
+        let e_bytes = 1.0f32.to_le_bytes();
+        let s_bytes = 0.0f32.to_le_bytes();
+        let size_bytes = ((12 + line_size + figure_element_size) as u32).to_le_bytes();
         #[rustfmt::skip]
         let parsed_res = p.parse(&mut vec![
             1,0,0,0,
             size_bytes[0],size_bytes[1],size_bytes[2],size_bytes[3],
             5,0,0,0,
             5,0,0,0,
+            //flags
+            0,0,0,0,
             //Element Type
             1,0,0,0,
             //Payload Length
@@ -315,9 +1066,10 @@ mod tests {
             parsed_res.ok().unwrap()[0],
             ParsedAST::Figure(Figure {
                 dimensions: Vector2::new(5, 5),
+                view_box: None,
                 contents: vec![FigureContents {
                     data: FigureContentsData::Line(Line {
-                        color: 0x00_00_00_ff,
+                        color: Color::rgba(0xff, 0, 0, 0),
                         thickness: 1.0,
                         segments: vec![Vector2::new(0.0, 0.0), Vector2::new(1.0, 1.0)]
                     }),
@@ -326,4 +1078,660 @@ mod tests {
             })
         )
     }
+    #[test]
+    fn figure_shorter_than_header() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let parsed_res = p.parse(&mut vec![1, 0, 0, 0,
+            4, 0, 0, 0,
+            5, 0, 0, 0]);
+        assert_eq!(parsed_res.err().unwrap().kind, ParseErrorKind::InvalidFigure);
+    }
+    #[test]
+    fn element_longer_than_figure() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let parsed_res = p.parse(&mut vec![1, 0, 0, 0,
+            28, 0, 0, 0,
+            5, 0, 0, 0,
+            5, 0, 0, 0,
+            0, 0, 0, 0,
+            //Element Type
+            1, 0, 0, 0,
+            //Payload Length
+            0xff, 0xff, 0xff, 0xff,
+            0, 0, 0, 0,
+            0, 0, 0, 0]);
+        assert_eq!(parsed_res.err().unwrap().kind, ParseErrorKind::InvalidFigure);
+    }
+    #[test]
+    fn picture_dimensions_larger_than_payload() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let parsed_res = p.parse(&mut vec![1, 0, 0, 0,
+            40, 0, 0, 0,
+            5, 0, 0, 0,
+            5, 0, 0, 0,
+            0, 0, 0, 0,
+            //Element Type
+            0, 0, 0, 0,
+            //Payload Length
+            12, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            //x dim and y dim
+            0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff,
+            0, 0, 0, 0]);
+        assert_eq!(parsed_res.err().unwrap().kind, ParseErrorKind::InvalidImage);
+    }
+    #[test]
+    fn picture_unknown_encoding() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let parsed_res = p.parse(&mut vec![1, 0, 0, 0,
+            44, 0, 0, 0,
+            5, 0, 0, 0,
+            5, 0, 0, 0,
+            0, 0, 0, 0,
+            //Element Type
+            0, 0, 0, 0,
+            //Payload Length
+            16, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            //x dim and y dim
+            1, 0, 0, 0,
+            1, 0, 0, 0,
+            //encoding
+            9, 0, 0, 0,
+            0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(
+            parsed_res.err().unwrap().kind,
+            ParseErrorKind::InvalidImageEncoding(9)
+        );
+    }
+    #[test]
+    fn group_errors() {
+        let group = Group::new(vec![]).element(FigureContents {
+            data: FigureContentsData::Line(Line {
+                color: Color::WHITE,
+                thickness: 1.0,
+                segments: vec![],
+            }),
+            position: Vector2::new(0, 0),
+        });
+        let figure = Figure::new(5, 5)
+            .rectangle(
+                Vector2::new(0, 0),
+                Vector2::new(1.0, 1.0),
+                0.0,
+                ShapeStyle::filled(Color::RED),
+            )
+            .group(Vector2::new(1, 1), group);
+        let bytes = serializer::serialize(&ParsedAST::Figure(figure));
+        // the child's element type, after the packet and figure headers, the rectangle and the
+        // group's header
+        let child = 8 + 12 + 40 + 68;
+        let mut broken = bytes.clone();
+        broken[child] = 99;
+        let error = Deserializer::new().parse(&mut broken).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidElementType(99));
+        assert_eq!(error.offset, child as u64);
+        assert_eq!(error.element, Some(1));
+        // the group's flags
+        let mut broken = bytes;
+        broken[8 + 12 + 40 + 16 + 32] = 2;
+        let error = Deserializer::new().parse(&mut broken).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidGroup);
+        let nested = (0..Deserializer::MAX_GROUP_DEPTH + 1).fold(Group::new(vec![]), |group, _| {
+            let child = FigureContents::new(FigureContentsData::Group(group), Vector2::new(0, 0));
+            Group::new(vec![child])
+        });
+        let figure = Figure::new(5, 5).group(Vector2::new(0, 0), nested);
+        let error = Deserializer::new()
+            .parse(&mut serializer::serialize(&ParsedAST::Figure(figure)))
+            .err()
+            .unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidGroup);
+    }
+    #[test]
+    fn heatmap_errors() {
+        let heatmap = Heatmap::new(Vector2::new(2, 2), vec![1.0, 2.0, 3.0, 4.0], Colormap::Viridis);
+        let bytes = serializer::serialize(&ParsedAST::Figure(Figure::new(5, 5).heatmap(Vector2::new(0, 0), heatmap)));
+        // the colormap, after the packet, figure and element headers
+        let colormap = 8 + 12 + 16 + 24;
+        let mut broken = bytes.clone();
+        broken[colormap] = 3;
+        let error = Deserializer::new().parse(&mut broken).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidHeatmap);
+        assert_eq!(error.element, Some(0));
+        // a grid with more cells than values
+        let mut broken = bytes;
+        broken[8 + 12 + 16] = 3;
+        let error = Deserializer::new().parse(&mut broken).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidHeatmap);
+    }
+    #[test]
+    fn line_with_partial_point() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let parsed_res = p.parse(&mut vec![1, 0, 0, 0,
+            40, 0, 0, 0,
+            5, 0, 0, 0,
+            5, 0, 0, 0,
+            0, 0, 0, 0,
+            //Element Type
+            1, 0, 0, 0,
+            //Payload Length
+            12, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            0xff, 0, 0, 0,
+            0, 0, 0x80, 0x3f,
+            0, 0, 0, 0]);
+        assert_eq!(parsed_res.err().unwrap().kind, ParseErrorKind::InvalidLine);
+    }
+    #[test]
+    fn error_context() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let error = p.parse(&mut vec![0, 0, 0, 0,
+            1, 0, 0, 0,
+            b'a',
+            1, 0, 0, 0,
+            40, 0, 0, 0,
+            5, 0, 0, 0,
+            5, 0, 0, 0,
+            0, 0, 0, 0,
+            //Element Type
+            1, 0, 0, 0,
+            //Payload Length
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            //Element Type
+            0, 0, 0, 0,
+            //Payload Length
+            4, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0]);
+        //the text before the broken figure is still returned
+        assert_eq!(error, Ok(vec![ParsedAST::String("a".to_string())]));
+        let error = p.parse(&mut vec![]).err().unwrap();
+        assert_eq!(
+            error,
+            ParseError {
+                kind: ParseErrorKind::InvalidLine,
+                offset: 9 + 8 + 12,
+                packet: Some(PacketHeader {
+                    data_type: 1,
+                    length: 40
+                }),
+                element: Some(0),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "line is missing its header or ends in a partial point in element 0 of packet (type 0x1, 40 bytes) at byte 29"
+        );
+    }
+    #[test]
+    fn skip_invalid_packet() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let mut stream = vec![0x20, 0, 0, 0,
+            2, 0, 0, 0,
+            0, 0,
+            0, 0, 0, 0,
+            1, 0, 0, 0,
+            b'a'];
+        let error = p.parse(&mut stream).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidDatatype(0x20));
+        assert_eq!(p.parse(&mut vec![]).err().unwrap(), error);
+        assert_eq!(
+            p.skip_packet(),
+            Some(PacketHeader {
+                data_type: 0x20,
+                length: 2
+            })
+        );
+        assert_eq!(
+            p.parse(&mut vec![]),
+            Ok(vec![ParsedAST::String("a".to_string())])
+        );
+    }
+    #[test]
+    fn skip_partial_packet() {
+        let mut p = Deserializer::new();
+        let error = p.parse(&mut vec![0x20, 0, 0, 0, 4, 0, 0, 0, 0]).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidDatatype(0x20));
+        p.skip_packet();
+        assert_eq!(p.parse(&mut vec![0, 0]), Ok(vec![]));
+        assert_eq!(
+            p.parse(&mut vec![0, 0, 0, 0, 0, 1, 0, 0, 0, b'a']),
+            Ok(vec![ParsedAST::String("a".to_string())])
+        );
+    }
+    #[test]
+    fn handshake_then_packets() {
+        let mut p = Deserializer::with_handshake();
+        let mut stream = crate::serializer::build_hello();
+        stream.append(&mut crate::serializer::build_text("a".to_string()));
+        let (first, second) = stream.split_at(5);
+        assert_eq!(p.parse(&mut first.to_vec()), Ok(vec![]));
+        assert_eq!(
+            p.parse(&mut second.to_vec()),
+            Ok(vec![
+                ParsedAST::Hello(Hello::new()),
+                ParsedAST::String("a".to_string())
+            ])
+        );
+        assert!(!p.is_raw_text());
+    }
+    #[test]
+    fn missing_handshake_is_raw_text() {
+        let mut p = Deserializer::with_handshake();
+        // starts like a hello header but the magic does not match
+        assert_eq!(p.parse(&mut vec![2, 0, 0]), Ok(vec![]));
+        assert_eq!(
+            p.parse(&mut vec![0, 1, 0, 0, 0, b'h', 0xc3]),
+            Ok(vec![ParsedAST::String("\u{2}\0\0\0\u{1}\0\0\0h".to_string())])
+        );
+        assert!(p.is_raw_text());
+        assert_eq!(
+            p.parse(&mut vec![0xa9]),
+            Ok(vec![ParsedAST::String("é".to_string())])
+        );
+        assert_eq!(
+            p.parse(&mut vec![0xff, 0xc3]),
+            Ok(vec![ParsedAST::String("\u{fffd}".to_string())])
+        );
+        assert_eq!(p.finish(), Ok(vec![ParsedAST::String("\u{fffd}".to_string())]));
+    }
+    #[test]
+    fn short_raw_stream() {
+        let parsed: Result<Vec<_>, _> = Deserializer::with_handshake()
+            .into_reader(std::io::Cursor::new(b"hi".to_vec()))
+            .collect();
+        assert_eq!(parsed, Ok(vec![ParsedAST::String("hi".to_string())]));
+    }
+    #[test]
+    fn invalid_hello() {
+        let mut p = Deserializer::new();
+        let error = p.parse(&mut vec![2, 0, 0, 0, 4, 0, 0, 0, b'G', b'S', b'X', 0]);
+        assert_eq!(error.err().unwrap().kind, ParseErrorKind::InvalidHello);
+    }
+    #[test]
+    fn parse_styled_text() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let parsed_res = p.parse(&mut vec![5, 0, 0, 0,
+            18, 0, 0, 0,
+            //foreground
+            0xff, 0, 0, 0xff,
+            //background
+            0, 0, 0, 0,
+            //flags: bold and underline
+            0b101, 0, 0, 0,
+            //text length
+            2, 0, 0, 0,
+            b'h', b'i']);
+        assert_eq!(
+            parsed_res,
+            Ok(vec![ParsedAST::StyledText(StyledText::new().span(
+                "hi",
+                Style {
+                    foreground: Color::RED,
+                    bold: true,
+                    underline: true,
+                    ..Style::default()
+                }
+            ))])
+        );
+    }
+    #[test]
+    fn styled_text_span_too_long() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let error = p.parse(&mut vec![5, 0, 0, 0,
+            18, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            3, 0, 0, 0,
+            b'h', b'i']).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidSpan);
+        assert_eq!(error.element, Some(0));
+    }
+    #[test]
+    fn parse_table() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let parsed_res = p.parse(&mut vec![6, 0, 0, 0,
+            49, 0, 0, 0,
+            //column and row count
+            2, 0, 0, 0,
+            1, 0, 0, 0,
+            //string column aligned left
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            1, 0, 0, 0,
+            b'a',
+            //bytes column aligned right
+            3, 0, 0, 0,
+            1, 0, 0, 0,
+            2, 0, 0, 0,
+            b'b', b'c',
+            //row
+            2, 0, 0, 0,
+            b'h', b'i',
+            0, 4, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            parsed_res,
+            Ok(vec![ParsedAST::Table(
+                Table::new()
+                    .column("a", ColumnType::String, Alignment::Left)
+                    .column("bc", ColumnType::Bytes, Alignment::Right)
+                    .row(vec!["hi".into(), Cell::Bytes(1024)])
+            )])
+        );
+    }
+    #[test]
+    fn table_cell_too_short() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let error = p.parse(&mut vec![6, 0, 0, 0,
+            27, 0, 0, 0,
+            1, 0, 0, 0,
+            1, 0, 0, 0,
+            1, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            //integer cell missing a byte
+            1, 0, 0, 0, 0, 0, 0]).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidTable);
+        assert_eq!(error.element, Some(0));
+        assert_eq!(error.offset, 28);
+    }
+    #[test]
+    fn parse_update() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let parsed_res = p.parse(&mut vec![7, 0, 0, 0,
+            14, 0, 0, 0,
+            //id
+            3, 0, 0, 0,
+            //text packet
+            0, 0, 0, 0,
+            2, 0, 0, 0,
+            b'h', b'i',
+            //delete
+            8, 0, 0, 0,
+            4, 0, 0, 0,
+            3, 0, 0, 0]);
+        assert_eq!(
+            parsed_res,
+            Ok(vec![
+                ParsedAST::Update(3, Box::new(ParsedAST::String("hi".to_string()))),
+                ParsedAST::Delete(3)
+            ])
+        );
+    }
+    #[test]
+    fn update_errors() {
+        // control packets can not be updated
+        #[rustfmt::skip]
+        let error = Deserializer::new().parse(&mut vec![7, 0, 0, 0,
+            12, 0, 0, 0,
+            3, 0, 0, 0,
+            3, 0, 0, 0,
+            0, 0, 0, 0]).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidUpdate);
+        // errors in the inner packet point into it
+        #[rustfmt::skip]
+        let error = Deserializer::new().parse(&mut vec![7, 0, 0, 0,
+            13, 0, 0, 0,
+            3, 0, 0, 0,
+            0, 0, 0, 0,
+            1, 0, 0, 0,
+            0xff]).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::StringNotUTF8);
+        assert_eq!(error.offset, 20);
+    }
+    #[test]
+    fn parse_palette() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let parsed_res = p.parse(&mut vec![10, 0, 0, 0,
+            8, 0, 0, 0,
+            0xff, 0x80, 0, 0xff,
+            0, 0, 0x19, 0xff]);
+        assert_eq!(
+            parsed_res,
+            Ok(vec![ParsedAST::Palette(Palette {
+                colors: vec![Color::rgb(0xff, 0x80, 0), Color::rgb(0, 0, 0x19)]
+            })])
+        );
+        #[rustfmt::skip]
+        let parsed_res = Deserializer::new().parse(&mut vec![10, 0, 0, 0,
+            6, 0, 0, 0,
+            0xff, 0x80, 0, 0xff,
+            0, 0]);
+        let error = parsed_res.err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidPalette);
+        assert_eq!(error.offset, 12);
+    }
+    #[test]
+    fn parse_progress() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let parsed_res = p.parse(&mut vec![9, 0, 0, 0,
+            35, 0, 0, 0,
+            //flags: determinate with an eta
+            0b11, 0, 0, 0,
+            //eta
+            65, 0, 0, 0,
+            //current
+            5, 0, 0, 0, 0, 0, 0, 0,
+            //total
+            10, 0, 0, 0, 0, 0, 0, 0,
+            //label
+            2, 0, 0, 0,
+            b'c', b'p',
+            //units
+            1, 0, 0, 0,
+            b'B']);
+        assert_eq!(
+            parsed_res,
+            Ok(vec![ParsedAST::Progress(Progress::new("cp", 5, 10).units("B").eta(65))])
+        );
+        let error = p.parse(&mut vec![9, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0]).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidProgress);
+    }
+    #[test]
+    fn chart_errors() {
+        let chart = Chart::new(ChartKind::Line)
+            .series(Series::new("a", vec![Vector2::new(0.0, 1.0)]))
+            .series(Series::new("b", vec![Vector2::new(1.0, 2.0), Vector2::new(2.0, 3.0)]));
+        let bytes = serializer::build_chart(&chart);
+        let mut valid = bytes.clone();
+        assert_eq!(Deserializer::new().parse(&mut valid), Ok(vec![ParsedAST::Chart(chart)]));
+        let mut broken = bytes.clone();
+        broken[8] = 3;
+        let error = Deserializer::new().parse(&mut broken).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidChart);
+        assert_eq!(error.element, None);
+        // the second series claims a third point past the end of the packet
+        let mut broken = bytes;
+        let count = broken.len() - 2 * 16 - (4 + 1) - 4;
+        broken[count] = 3;
+        let error = Deserializer::new().parse(&mut broken).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidChart);
+        assert_eq!(error.element, Some(1));
+    }
+    #[test]
+    fn parse_rectangle() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let parsed_res = p.parse(&mut vec![1, 0, 0, 0,
+            52, 0, 0, 0,
+            10, 0, 0, 0,
+            10, 0, 0, 0,
+            0, 0, 0, 0,
+            //rectangle element
+            2, 0, 0, 0,
+            24, 0, 0, 0,
+            1, 0, 0, 0,
+            2, 0, 0, 0,
+            //fill, stroke and stroke width
+            0xff, 0, 0, 0xff,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            //width, height and corner radius
+            0, 0, 0x80, 0x3f,
+            0, 0, 0, 0x40,
+            0, 0, 0, 0]);
+        assert_eq!(
+            parsed_res,
+            Ok(vec![ParsedAST::Figure(Figure::new(10, 10).rectangle(
+                Vector2::new(1, 2),
+                Vector2::new(1.0, 2.0),
+                0.0,
+                ShapeStyle::filled(Color::RED)
+            ))])
+        );
+    }
+    #[test]
+    fn ellipse_missing_radius() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let error = p.parse(&mut vec![1, 0, 0, 0,
+            44, 0, 0, 0,
+            10, 0, 0, 0,
+            10, 0, 0, 0,
+            0, 0, 0, 0,
+            3, 0, 0, 0,
+            16, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0x80, 0x3f]).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidShape);
+        assert_eq!(error.element, Some(0));
+    }
+    #[test]
+    fn label_unknown_alignment() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let error = p.parse(&mut vec![1, 0, 0, 0,
+            52, 0, 0, 0,
+            10, 0, 0, 0,
+            10, 0, 0, 0,
+            0, 0, 0, 0,
+            5, 0, 0, 0,
+            24, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            //color, font size and rotation
+            0xff, 0xff, 0xff, 0xff,
+            0, 0, 0x40, 0x41,
+            0, 0, 0, 0,
+            //horizontal alignment 3 does not exist
+            3, 0, 0, 0,
+            0, 0, 0, 0,
+            //empty text
+            0, 0, 0, 0]).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidLabel);
+        assert_eq!(error.element, Some(0));
+    }
+    #[test]
+    fn path_partial_command() {
+        let mut p = Deserializer::new();
+        #[rustfmt::skip]
+        let error = p.parse(&mut vec![1, 0, 0, 0,
+            64, 0, 0, 0,
+            10, 0, 0, 0,
+            10, 0, 0, 0,
+            0, 0, 0, 0,
+            6, 0, 0, 0,
+            36, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            //style
+            0, 0, 0, 0,
+            0xff, 0xff, 0xff, 0xff,
+            0, 0, 0x80, 0x3f,
+            //round join, butt cap, no dashes
+            1, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            //line to with only an x cordinate
+            1, 0, 0, 0,
+            0, 0, 0x80, 0x3f]).err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidPath);
+        assert_eq!(error.element, Some(0));
+    }
+    /// Builds a figure packet out of elements with arbitrary types, length fields and payloads
+    fn figure_packet() -> impl Strategy<Value = Vec<u8>> {
+        let element = (0u32..9, any::<u32>(), any::<bool>(), prop::collection::vec(any::<u8>(), 0..64))
+            .prop_map(|(element_type, length, honest_length, payload)| {
+                let length = if honest_length { payload.len() as u32 } else { length };
+                let mut bytes = element_type.to_le_bytes().to_vec();
+                bytes.extend_from_slice(&length.to_le_bytes());
+                bytes.extend_from_slice(&[0; 8]);
+                bytes.extend(payload);
+                bytes
+            });
+        (any::<[u8; 8]>(), prop::collection::vec(element, 0..4)).prop_map(|(dimensions, elements)| {
+            let mut payload = dimensions.to_vec();
+            payload.extend_from_slice(&[0; 4]);
+            for element in elements {
+                payload.extend(element);
+            }
+            let mut bytes = 1u32.to_le_bytes().to_vec();
+            bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            bytes.extend(payload);
+            bytes
+        })
+    }
+    proptest! {
+        #[test]
+        fn arbitrary_bytes_never_panic(bytes in prop::collection::vec(any::<u8>(), 0..1024)) {
+            let _ = Deserializer::new().parse(&mut bytes.clone());
+        }
+        #[test]
+        fn arbitrary_chunks_never_panic(
+            chunks in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..64), 0..16)
+        ) {
+            let mut p = Deserializer::new();
+            for mut chunk in chunks {
+                let _ = p.parse(&mut chunk);
+            }
+        }
+        #[test]
+        fn skipping_always_makes_progress(bytes in prop::collection::vec(any::<u8>(), 0..1024)) {
+            let mut p = Deserializer::new();
+            let mut stream = bytes.clone();
+            for _ in 0..bytes.len() + 1 {
+                match p.parse(&mut stream) {
+                    Ok(_) if p.header().is_none() => break,
+                    Ok(_) => (),
+                    Err(_) => prop_assert!(p.skip_packet().is_some()),
+                }
+            }
+            prop_assert!(p.parse(&mut vec![]).is_ok());
+        }
+        #[test]
+        fn malformed_figures_never_panic(packet in figure_packet(), split in any::<prop::sample::Index>()) {
+            let mut p = Deserializer::new();
+            let (first, second) = packet.split_at(split.index(packet.len() + 1));
+            let _ = p.parse(&mut first.to_vec());
+            let _ = p.parse(&mut second.to_vec());
+        }
+    }
 }
@@ -0,0 +1,87 @@
+//! Environment variables the frontend sets for the programs it runs, so a program can tell it is
+//! running under GSH instead of a regular terminal.
+//!
+//! A process's environment can not change once it is running, so the sizes are the ones the
+//! terminal had when the program started and go stale if the window is resized.
+use super::PROTOCOL_VERSION;
+/// Protocol version spoken by the frontend, only set when running under GSH
+pub const PROTOCOL_VERSION_VAR: &str = "GSH_PROTOCOL_VERSION";
+/// Width of the terminal in monospace characters when the program started
+pub const COLUMNS_VAR: &str = "GSH_COLUMNS";
+/// Width of the terminal in pixels when the program started, the widest a figure can be
+/// without being scaled down
+pub const PIXEL_WIDTH_VAR: &str = "GSH_PIXEL_WIDTH";
+/// Identifies the terminal session the program is running in
+pub const SESSION_ID_VAR: &str = "GSH_SESSION_ID";
+/// GSH session a program is running in
+#[derive(Debug, PartialEq, Clone)]
+pub struct Environment {
+    pub protocol_version: u32,
+    pub columns: Option<u32>,
+    pub pixel_width: Option<u32>,
+    pub session_id: Option<String>,
+}
+impl Environment {
+    /// Environment for a new session of this version of the protocol
+    pub fn new(columns: u32, pixel_width: u32, session_id: String) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            columns: Some(columns),
+            pixel_width: Some(pixel_width),
+            session_id: Some(session_id),
+        }
+    }
+    /// Reads the environment using `lookup` to get each variable. Returns `None` if
+    /// `GSH_PROTOCOL_VERSION` is missing or not a number.
+    pub fn from_vars<F: Fn(&str) -> Option<String>>(lookup: F) -> Option<Self> {
+        let number = |name| lookup(name).and_then(|value| value.trim().parse().ok());
+        Some(Self {
+            protocol_version: number(PROTOCOL_VERSION_VAR)?,
+            columns: number(COLUMNS_VAR),
+            pixel_width: number(PIXEL_WIDTH_VAR),
+            session_id: lookup(SESSION_ID_VAR),
+        })
+    }
+    /// Variables to set on a child process
+    pub fn to_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![(PROTOCOL_VERSION_VAR, self.protocol_version.to_string())];
+        if let Some(columns) = self.columns {
+            vars.push((COLUMNS_VAR, columns.to_string()));
+        }
+        if let Some(pixel_width) = self.pixel_width {
+            vars.push((PIXEL_WIDTH_VAR, pixel_width.to_string()));
+        }
+        if let Some(session_id) = &self.session_id {
+            vars.push((SESSION_ID_VAR, session_id.clone()));
+        }
+        vars
+    }
+}
+/// Checks whether this program was started by a GSH frontend. Apps should write GSH packets if
+/// this returns `Some` and plain text otherwise.
+pub fn detect() -> Option<Environment> {
+    Environment::from_vars(|name| std::env::var(name).ok())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    #[test]
+    fn round_trip() {
+        let environment = Environment::new(80, 1024, "session".to_string());
+        let vars: HashMap<_, _> = environment.to_vars().into_iter().collect();
+        assert_eq!(
+            Environment::from_vars(|name| vars.get(name).cloned()),
+            Some(environment)
+        );
+    }
+    #[test]
+    fn missing_version() {
+        let vars: HashMap<_, _> = vec![(COLUMNS_VAR, "80".to_string())].into_iter().collect();
+        assert_eq!(Environment::from_vars(|name| vars.get(name).cloned()), None);
+        let vars: HashMap<_, _> = vec![(PROTOCOL_VERSION_VAR, "one".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(Environment::from_vars(|name| vars.get(name).cloned()), None);
+    }
+}
@@ -0,0 +1,175 @@
+use super::PacketHeader;
+use std::fmt;
+/// Error produced when a packet can not be parsed.
+///
+/// Besides the reason the error records where in the stream it happened so that a broken app
+/// can be debugged from a capture of its output.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    /// What went wrong
+    pub kind: ParseErrorKind,
+    /// Offset from the start of the stream of the packet or figure element that failed
+    pub offset: u64,
+    /// Header of the packet that failed, `None` if the stream failed before a header arrived
+    pub packet: Option<PacketHeader>,
+    /// Index of the figure element, styled text span or table row that failed
+    pub element: Option<usize>,
+}
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParseErrorKind {
+    InvalidDatatype(u32),
+    InvalidElementType(u32),
+    InvalidFigure,
+    /// Picture pixels that do not match its dimensions or fail to decode
+    InvalidImage,
+    /// A picture encoding this version does not know
+    InvalidImageEncoding(u32),
+    InvalidLine,
+    /// A rectangle or ellipse missing fields, or a polygon that ends in a partial point
+    InvalidShape,
+    /// A text element missing fields, with an unknown alignment or whose text does not fill the
+    /// rest of the element
+    InvalidLabel,
+    /// A path missing fields, with an unknown join, cap or command, or that ends in a partial
+    /// command
+    InvalidPath,
+    /// A group missing fields, with unknown flags or nested too deep
+    InvalidGroup,
+    /// A heatmap missing fields, with an unknown colormap or whose values do not fill its grid
+    InvalidHeatmap,
+    /// A `Hello` packet without the magic value or with missing fields
+    InvalidHello,
+    /// A `Capabilities` packet that is missing fields
+    InvalidCapabilities,
+    /// A styled text span whose header or text runs past the end of the packet
+    InvalidSpan,
+    /// A table column type that is not a `ColumnType`
+    InvalidColumnType(u32),
+    /// A table whose columns or cells run past the end of the packet, that has an unknown
+    /// alignment, or that has bytes left over after the last row
+    InvalidTable,
+    /// An `Update` or `Delete` missing its id, or an `Update` whose packet is not output or does
+    /// not fill the rest of the payload
+    InvalidUpdate,
+    /// A `Progress` packet missing fields or whose label or units run past the end of the packet
+    InvalidProgress,
+    /// A `Chart` packet missing fields, with an unknown kind or flags, or whose text or points run
+    /// past the end of the packet
+    InvalidChart,
+    /// A `Palette` packet that ends in a partial color
+    InvalidPalette,
+    StringNotUTF8,
+    /// The stream ended in the middle of a packet
+    UnexpectedEof,
+    /// Reading from the stream failed
+    Io(std::io::ErrorKind),
+}
+impl ParseError {
+    /// Error that has not been placed in the stream yet
+    pub(crate) fn new(kind: ParseErrorKind) -> Self {
+        Self {
+            kind,
+            offset: 0,
+            packet: None,
+            element: None,
+        }
+    }
+    /// Places an error found at `offset` inside of a packet's payload in the stream
+    pub(crate) fn in_packet(mut self, packet: PacketHeader, packet_offset: u64) -> Self {
+        self.offset += packet_offset;
+        self.packet = Some(packet);
+        self
+    }
+}
+impl From<ParseErrorKind> for ParseError {
+    fn from(kind: ParseErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::InvalidDatatype(data_type) => {
+                write!(f, "unknown datatype 0x{:x}", data_type)
+            }
+            ParseErrorKind::InvalidElementType(element_type) => {
+                write!(f, "unknown figure element type 0x{:x}", element_type)
+            }
+            ParseErrorKind::InvalidFigure => {
+                write!(f, "figure header or element length runs past the end of the packet")
+            }
+            ParseErrorKind::InvalidImage => {
+                write!(f, "picture pixel data does not decode to an image of its dimensions")
+            }
+            ParseErrorKind::InvalidImageEncoding(encoding) => {
+                write!(f, "unknown picture encoding {}", encoding)
+            }
+            ParseErrorKind::InvalidLine => {
+                write!(f, "line is missing its header or ends in a partial point")
+            }
+            ParseErrorKind::InvalidShape => {
+                write!(f, "shape is missing fields or ends in a partial point")
+            }
+            ParseErrorKind::InvalidLabel => {
+                write!(f, "text element is missing fields or has an unknown alignment")
+            }
+            ParseErrorKind::InvalidPath => {
+                write!(f, "path has an unknown command or ends in a partial command")
+            }
+            ParseErrorKind::InvalidGroup => {
+                write!(f, "group is missing fields, has unknown flags or is nested too deep")
+            }
+            ParseErrorKind::InvalidHeatmap => {
+                write!(f, "heatmap has an unknown colormap or its values do not fill its grid")
+            }
+            ParseErrorKind::InvalidHello => write!(f, "hello packet is missing the GSH magic"),
+            ParseErrorKind::InvalidCapabilities => {
+                write!(f, "capabilities packet is missing fields")
+            }
+            ParseErrorKind::InvalidSpan => {
+                write!(f, "styled text span runs past the end of the packet")
+            }
+            ParseErrorKind::InvalidColumnType(column_type) => {
+                write!(f, "unknown table column type 0x{:x}", column_type)
+            }
+            ParseErrorKind::InvalidTable => {
+                write!(f, "table does not match its column and row counts")
+            }
+            ParseErrorKind::InvalidUpdate => {
+                write!(f, "update or delete is missing its id or does not hold one output packet")
+            }
+            ParseErrorKind::InvalidProgress => {
+                write!(f, "progress is missing fields or its text runs past the end of the packet")
+            }
+            ParseErrorKind::InvalidChart => {
+                write!(f, "chart has an unknown kind or its series run past the end of the packet")
+            }
+            ParseErrorKind::InvalidPalette => write!(f, "palette ends in a partial color"),
+            ParseErrorKind::StringNotUTF8 => write!(f, "text is not valid utf-8"),
+            ParseErrorKind::UnexpectedEof => write!(f, "stream ended in the middle of a packet"),
+            ParseErrorKind::Io(kind) => write!(f, "reading the stream failed: {:?}", kind),
+        }
+    }
+}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(element) = self.element {
+            write!(f, " in element {}", element)?;
+        }
+        if let Some(packet) = self.packet {
+            write!(
+                f,
+                " of packet (type 0x{:x}, {} bytes)",
+                packet.data_type, packet.length
+            )?;
+        }
+        write!(f, " at byte {}", self.offset)
+    }
+}
+impl From<std::io::Error> for ParseError {
+    fn from(error: std::io::Error) -> Self {
+        Self::new(ParseErrorKind::Io(error.kind()))
+    }
+}
+impl std::error::Error for ParseError {}
@@ -0,0 +1,1066 @@
+use super::{Alignment, Color, Colormap};
+use image::RgbaImage;
+use nalgebra::{Affine2, Matrix3, Point2, Vector2};
+/// Drawing made out of pictures, lines, shapes and text.
+///
+/// Built with a chain of calls:
+/// ```
+/// # use parser::{Color, Figure};
+/// # use image::RgbaImage;
+/// # use nalgebra::Vector2;
+/// let figure = Figure::new(200, 100)
+///     .image_at(Vector2::new(10, 10), RgbaImage::new(16, 16))
+///     .polyline(Color::RED, 2.0, vec![Vector2::new(0.0, 0.0), Vector2::new(50.0, 50.0)]);
+/// assert_eq!(figure.contents().len(), 2);
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Figure {
+    pub(crate) dimensions: Vector2<u32>,
+    pub(crate) view_box: Option<ViewBox>,
+    pub(crate) contents: Vec<FigureContents>,
+}
+impl Figure {
+    /// Largest width or height a figure is drawn at, bigger figures are scaled down to fit
+    pub const MAX_SIZE: u32 = 4096;
+    /// Empty figure that is `width` by `height` pixels
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            dimensions: Vector2::new(width, height),
+            view_box: None,
+            contents: vec![],
+        }
+    }
+    /// Places the elements in the logical coordinates of `view_box` instead of pixels, so the
+    /// frontend can draw the figure at whatever size fits. The dimensions of the figure become its
+    /// preferred size and set its aspect ratio.
+    pub fn with_view_box(mut self, view_box: ViewBox) -> Self {
+        self.view_box = Some(view_box);
+        self
+    }
+    /// Adds `image` with its top left corner at `position`
+    pub fn image_at(self, position: Vector2<i32>, image: RgbaImage) -> Self {
+        self.element(FigureContents::new(FigureContentsData::Image(image), position))
+    }
+    /// Adds a line through `points`, the points are relative to the top left of the figure
+    pub fn polyline(self, color: Color, thickness: f32, points: Vec<Vector2<f32>>) -> Self {
+        self.element(FigureContents::new(
+            FigureContentsData::Line(Line {
+                color,
+                thickness,
+                segments: points,
+            }),
+            Vector2::new(0, 0),
+        ))
+    }
+    /// Adds a rectangle with its top left corner at `position`
+    pub fn rectangle(
+        self,
+        position: Vector2<i32>,
+        size: Vector2<f32>,
+        corner_radius: f32,
+        style: ShapeStyle,
+    ) -> Self {
+        self.element(FigureContents::new(
+            FigureContentsData::Rectangle(Rectangle {
+                size,
+                corner_radius,
+                style,
+            }),
+            position,
+        ))
+    }
+    /// Adds an ellipse centered on `center`, use the same radius twice for a circle
+    pub fn ellipse(self, center: Vector2<i32>, radii: Vector2<f32>, style: ShapeStyle) -> Self {
+        self.element(FigureContents::new(
+            FigureContentsData::Ellipse(Ellipse { radii, style }),
+            center,
+        ))
+    }
+    /// Adds a closed polygon through `points`, the points are relative to the top left of the
+    /// figure
+    pub fn polygon(self, points: Vec<Vector2<f32>>, style: ShapeStyle) -> Self {
+        self.element(FigureContents::new(
+            FigureContentsData::Polygon(Polygon { points, style }),
+            Vector2::new(0, 0),
+        ))
+    }
+    /// Adds a path, the points of its commands are relative to the top left of the figure
+    pub fn path(self, path: Path) -> Self {
+        self.element(FigureContents::new(
+            FigureContentsData::Path(path),
+            Vector2::new(0, 0),
+        ))
+    }
+    /// Adds text anchored at `position`, see `Label::align` for which point of the text that is
+    pub fn label(self, position: Vector2<i32>, label: Label) -> Self {
+        self.element(FigureContents::new(
+            FigureContentsData::Text(label),
+            position,
+        ))
+    }
+    /// Adds `heatmap` with its top left corner at `position`
+    pub fn heatmap(self, position: Vector2<i32>, heatmap: Heatmap) -> Self {
+        self.element(FigureContents::new(
+            FigureContentsData::Heatmap(heatmap),
+            position,
+        ))
+    }
+    /// Adds `group` with the origin of its children at `position`
+    pub fn group(self, position: Vector2<i32>, group: Group) -> Self {
+        self.element(FigureContents::new(
+            FigureContentsData::Group(group),
+            position,
+        ))
+    }
+    /// Adds any element, elements are drawn in the order they are added unless a group has a
+    /// z index
+    pub fn element(mut self, element: FigureContents) -> Self {
+        self.contents.push(element);
+        self
+    }
+    /// Size of the figure in pixels
+    pub fn dimensions(&self) -> Vector2<u32> {
+        self.dimensions
+    }
+    pub fn view_box(&self) -> Option<ViewBox> {
+        self.view_box
+    }
+    /// Size in pixels to draw the figure at in a pane `width` pixels wide, see `Fit`. Figures
+    /// without a view box are always drawn at their dimensions. Sizes are scaled down to fit in
+    /// `MAX_SIZE`, keeping the aspect ratio.
+    pub fn size_for(&self, width: u32) -> Vector2<u32> {
+        let fit = match self.view_box {
+            Some(view_box) => view_box.fit,
+            None => return Self::clamp_size(self.dimensions),
+        };
+        let target = match fit {
+            Fit::Contain => width.min(self.dimensions.x),
+            Fit::FillWidth => width,
+            Fit::Fixed => return Self::clamp_size(self.dimensions),
+        };
+        if self.dimensions.x == 0 {
+            return Self::clamp_size(self.dimensions);
+        }
+        let height = target as f64 * self.dimensions.y as f64 / self.dimensions.x as f64;
+        // a tall aspect ratio can ask for more rows than fit in a u32, so it is scaled as a float
+        let scale = (Self::MAX_SIZE as f64 / height.max(target as f64)).min(1.0);
+        // like in `clamp_size` a side scaled below a pixel keeps one
+        Vector2::new(
+            ((target as f64 * scale).round() as u32).max(target.min(1)),
+            ((height * scale).round() as u32).max(u32::from(height > 0.0)),
+        )
+    }
+    /// Scales `size` down to fit in `MAX_SIZE` by `MAX_SIZE` pixels, keeping its aspect ratio
+    pub(crate) fn clamp_size(size: Vector2<u32>) -> Vector2<u32> {
+        let largest = size.x.max(size.y);
+        if largest <= Self::MAX_SIZE {
+            return size;
+        }
+        let scale = Self::MAX_SIZE as f64 / largest as f64;
+        // a side scaled below a pixel keeps one so the figure does not vanish
+        let side = |side: u32| ((side as f64 * scale).round() as u32).max(side.min(1));
+        Vector2::new(side(size.x), side(size.y))
+    }
+    /// Maps the coordinates of the elements to the pixels of an image `size` pixels big. The view
+    /// box, or the dimensions of a figure without one, is scaled as large as fits in the image
+    /// and centered in it.
+    pub fn transform_for(&self, size: Vector2<u32>) -> Affine2<f32> {
+        let (origin, extent) = match self.view_box {
+            Some(view_box) => (view_box.origin, view_box.size),
+            None => (
+                Vector2::new(0.0, 0.0),
+                Vector2::new(self.dimensions.x as f32, self.dimensions.y as f32),
+            ),
+        };
+        let size = Vector2::new(size.x as f32, size.y as f32);
+        let mut scale = (size.x / extent.x).min(size.y / extent.y);
+        // an empty view box has nothing to scale
+        if !scale.is_finite() || scale <= 0.0 {
+            scale = 1.0;
+        }
+        let offset = (size - extent * scale) / 2.0 - origin * scale;
+        Affine2::from_matrix_unchecked(Matrix3::new(
+            scale, 0.0, offset.x, 0.0, scale, offset.y, 0.0, 0.0, 1.0,
+        ))
+    }
+    pub fn contents(&self) -> &[FigureContents] {
+        &self.contents
+    }
+    /// Value of the topmost heatmap cell at `point`, in the coordinates of the figure's elements,
+    /// looking into groups. Lets a frontend show the value under the cursor.
+    pub fn heatmap_value_at(&self, point: Vector2<f32>) -> Option<f32> {
+        heatmap_value_at(&self.contents, point)
+    }
+    /// Colormap of the first heatmap, looking into groups. `None` if there are no heatmaps.
+    pub fn colormap(&self) -> Option<Colormap> {
+        colormap(&self.contents)
+    }
+    /// Colors every heatmap, including the ones in groups, with `colormap`. Lets a viewer pick
+    /// another colormap than the one the app sent.
+    pub fn set_colormap(&mut self, colormap: Colormap) {
+        set_colormap(&mut self.contents, colormap)
+    }
+}
+fn colormap(elements: &[FigureContents]) -> Option<Colormap> {
+    elements.iter().find_map(|element| match &element.data {
+        FigureContentsData::Heatmap(heatmap) => Some(heatmap.colormap),
+        FigureContentsData::Group(group) => colormap(&group.children),
+        _ => None,
+    })
+}
+fn set_colormap(elements: &mut [FigureContents], colormap: Colormap) {
+    for element in elements.iter_mut() {
+        match &mut element.data {
+            FigureContentsData::Heatmap(heatmap) => heatmap.colormap = colormap,
+            FigureContentsData::Group(group) => set_colormap(&mut group.children, colormap),
+            _ => {}
+        }
+    }
+}
+fn heatmap_value_at(elements: &[FigureContents], point: Vector2<f32>) -> Option<f32> {
+    drawing_order(elements)
+        .into_iter()
+        .rev()
+        .find_map(|element| {
+            let local = point - Vector2::new(element.position.x as f32, element.position.y as f32);
+            match &element.data {
+                FigureContentsData::Heatmap(heatmap) => {
+                    heatmap.value_at(local).filter(|value| !value.is_nan())
+                }
+                FigureContentsData::Group(group) => {
+                    if group.opacity.is_nan() || group.opacity <= 0.0 {
+                        return None;
+                    }
+                    let local = group
+                        .transform
+                        .try_inverse()?
+                        .transform_point(&Point2::from(local))
+                        .coords;
+                    if let Some(clip) = group.clip {
+                        let inside = local - clip.position;
+                        if !(inside.x >= 0.0
+                            && inside.y >= 0.0
+                            && inside.x < clip.size.x
+                            && inside.y < clip.size.y)
+                        {
+                            return None;
+                        }
+                    }
+                    heatmap_value_at(&group.children, local)
+                }
+                _ => None,
+            }
+        })
+}
+/// Logical coordinate space of a figure, from `origin` at its top left to `origin + size` at its
+/// bottom right
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ViewBox {
+    pub origin: Vector2<f32>,
+    pub size: Vector2<f32>,
+    pub fit: Fit,
+}
+/// How a figure with a view box is sized to the pane showing it. The height follows from the
+/// width and the aspect ratio of the figure's dimensions.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Fit {
+    /// As wide as the pane, but never wider than the figure's dimensions
+    Contain = 0x0,
+    /// As wide as the pane
+    FillWidth = 0x1,
+    /// The figure's dimensions whatever the size of the pane, only the coordinates are logical
+    Fixed = 0x2,
+}
+impl Fit {
+    pub const ALL: [Fit; 3] = [Fit::Contain, Fit::FillWidth, Fit::Fixed];
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|fit| *fit as u32 == value)
+    }
+}
+#[derive(Debug, PartialEq, Clone)]
+pub struct FigureContents {
+    pub(crate) data: FigureContentsData,
+    pub(crate) position: Vector2<i32>,
+}
+impl FigureContents {
+    pub fn new(data: FigureContentsData, position: Vector2<i32>) -> Self {
+        Self { data, position }
+    }
+    pub fn data(&self) -> &FigureContentsData {
+        &self.data
+    }
+    /// Offset of the element from the top left of the figure in pixels
+    pub fn position(&self) -> Vector2<i32> {
+        self.position
+    }
+    /// Z index of the element, 0 for anything but a group
+    pub fn z_index(&self) -> i32 {
+        match &self.data {
+            FigureContentsData::Group(group) => group.z_index,
+            _ => 0,
+        }
+    }
+}
+/// `elements` in the order they are drawn, by z index and then in the order they were added
+pub(crate) fn drawing_order(elements: &[FigureContents]) -> Vec<&FigureContents> {
+    let mut ordered: Vec<&FigureContents> = elements.iter().collect();
+    ordered.sort_by_key(|element| element.z_index());
+    ordered
+}
+#[derive(Debug, PartialEq, Clone)]
+pub enum FigureContentsData {
+    Image(RgbaImage),
+    Line(Line),
+    Rectangle(Rectangle),
+    Ellipse(Ellipse),
+    Polygon(Polygon),
+    Text(Label),
+    Path(Path),
+    Group(Group),
+    Heatmap(Heatmap),
+}
+impl FigureContentsData {
+    pub fn element_type(&self) -> ElementTypes {
+        match self {
+            FigureContentsData::Image(_) => ElementTypes::Image,
+            FigureContentsData::Line(_) => ElementTypes::Line,
+            FigureContentsData::Rectangle(_) => ElementTypes::Rectangle,
+            FigureContentsData::Ellipse(_) => ElementTypes::Ellipse,
+            FigureContentsData::Polygon(_) => ElementTypes::Polygon,
+            FigureContentsData::Text(_) => ElementTypes::Text,
+            FigureContentsData::Path(_) => ElementTypes::Path,
+            FigureContentsData::Group(_) => ElementTypes::Group,
+            FigureContentsData::Heatmap(_) => ElementTypes::Heatmap,
+        }
+    }
+}
+/// Type of a figure element, sent as the first field of the element header
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ElementTypes {
+    Image = 0x0,
+    Line = 0x1,
+    Rectangle = 0x2,
+    Ellipse = 0x3,
+    Polygon = 0x4,
+    Text = 0x5,
+    Path = 0x6,
+    Group = 0x7,
+    Heatmap = 0x8,
+}
+impl ElementTypes {
+    pub const ALL: [ElementTypes; 9] = [
+        ElementTypes::Image,
+        ElementTypes::Line,
+        ElementTypes::Rectangle,
+        ElementTypes::Ellipse,
+        ElementTypes::Polygon,
+        ElementTypes::Text,
+        ElementTypes::Path,
+        ElementTypes::Group,
+        ElementTypes::Heatmap,
+    ];
+    /// Bit for this element type in a capability bitset
+    pub fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+#[derive(Debug, PartialEq, Clone)]
+pub struct Line {
+    pub color: Color,
+    pub thickness: f32,
+    pub segments: Vec<Vector2<f32>>,
+}
+/// How a shape is filled and outlined. A color with an alpha of 0 is not drawn.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ShapeStyle {
+    pub fill: Color,
+    pub stroke: Color,
+    /// Width of the outline in pixels, centered on the edge of the shape
+    pub stroke_width: f32,
+}
+impl ShapeStyle {
+    /// Filled with `color` and no outline
+    pub fn filled(color: Color) -> Self {
+        Self {
+            fill: color,
+            stroke: Color::TRANSPARENT,
+            stroke_width: 0.0,
+        }
+    }
+    /// Outlined with `color` and not filled
+    pub fn stroked(color: Color, width: f32) -> Self {
+        Self {
+            fill: Color::TRANSPARENT,
+            stroke: color,
+            stroke_width: width,
+        }
+    }
+}
+#[derive(Debug, PartialEq, Clone)]
+pub struct Rectangle {
+    pub size: Vector2<f32>,
+    /// Radius of the rounded corners, 0 for square corners
+    pub corner_radius: f32,
+    pub style: ShapeStyle,
+}
+/// Ellipse centered on the position of its element
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ellipse {
+    pub radii: Vector2<f32>,
+    pub style: ShapeStyle,
+}
+/// Closed polygon, the last point connects back to the first
+#[derive(Debug, PartialEq, Clone)]
+pub struct Polygon {
+    /// Points relative to the position of the element
+    pub points: Vec<Vector2<f32>>,
+    pub style: ShapeStyle,
+}
+/// Outline made of straight and curved segments, filled and stroked like the other shapes.
+///
+/// Built like a figure:
+/// ```
+/// # use parser::{Color, LineCap, Path, ShapeStyle};
+/// # use nalgebra::Vector2;
+/// let arrow = Path::new(ShapeStyle::stroked(Color::WHITE, 2.0))
+///     .move_to(Vector2::new(0.0, 0.0))
+///     .quadratic_to(Vector2::new(50.0, -20.0), Vector2::new(100.0, 0.0))
+///     .cap(LineCap::Round)
+///     .dash(vec![6.0, 3.0], 0.0);
+/// assert_eq!(arrow.commands.len(), 2);
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Path {
+    /// Points of the commands are relative to the position of the element
+    pub commands: Vec<PathCommand>,
+    /// Every subpath is filled as if it was closed
+    pub style: ShapeStyle,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    /// Lengths of the dashes and the gaps between them, alternating and starting with a dash.
+    /// Empty for a solid stroke.
+    pub dashes: Vec<f32>,
+    /// How far into the dash pattern the stroke starts
+    pub dash_offset: f32,
+}
+impl Path {
+    /// Empty path with mitered joins, butt caps and a solid stroke
+    pub fn new(style: ShapeStyle) -> Self {
+        Self {
+            commands: vec![],
+            style,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            dashes: vec![],
+            dash_offset: 0.0,
+        }
+    }
+    pub fn move_to(self, point: Vector2<f32>) -> Self {
+        self.command(PathCommand::MoveTo(point))
+    }
+    pub fn line_to(self, point: Vector2<f32>) -> Self {
+        self.command(PathCommand::LineTo(point))
+    }
+    pub fn quadratic_to(self, control: Vector2<f32>, end: Vector2<f32>) -> Self {
+        self.command(PathCommand::QuadraticTo { control, end })
+    }
+    pub fn cubic_to(
+        self,
+        control_1: Vector2<f32>,
+        control_2: Vector2<f32>,
+        end: Vector2<f32>,
+    ) -> Self {
+        self.command(PathCommand::CubicTo {
+            control_1,
+            control_2,
+            end,
+        })
+    }
+    /// Elliptical arc to `end`, see `PathCommand::ArcTo`
+    pub fn arc_to(
+        self,
+        radii: Vector2<f32>,
+        rotation: f32,
+        large_arc: bool,
+        sweep: bool,
+        end: Vector2<f32>,
+    ) -> Self {
+        self.command(PathCommand::ArcTo {
+            radii,
+            rotation,
+            large_arc,
+            sweep,
+            end,
+        })
+    }
+    pub fn close(self) -> Self {
+        self.command(PathCommand::Close)
+    }
+    pub fn join(mut self, join: LineJoin) -> Self {
+        self.join = join;
+        self
+    }
+    pub fn cap(mut self, cap: LineCap) -> Self {
+        self.cap = cap;
+        self
+    }
+    /// Dashes the stroke with `pattern`, see `Path::dashes`
+    pub fn dash(mut self, pattern: Vec<f32>, offset: f32) -> Self {
+        self.dashes = pattern;
+        self.dash_offset = offset;
+        self
+    }
+    fn command(mut self, command: PathCommand) -> Self {
+        self.commands.push(command);
+        self
+    }
+}
+/// One step of a `Path`. Every command but `MoveTo` starts where the one before it ended.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PathCommand {
+    /// Starts a new subpath at the point
+    MoveTo(Vector2<f32>),
+    LineTo(Vector2<f32>),
+    /// Quadratic bezier curve pulled toward `control`
+    QuadraticTo {
+        control: Vector2<f32>,
+        end: Vector2<f32>,
+    },
+    CubicTo {
+        control_1: Vector2<f32>,
+        control_2: Vector2<f32>,
+        end: Vector2<f32>,
+    },
+    /// Elliptical arc as in SVG. Out of the arcs of an ellipse with `radii`, rotated `rotation`
+    /// degrees clockwise, that join the points, `large_arc` picks one that is more than half of
+    /// the ellipse and `sweep` one that goes clockwise. Radii too small to reach `end` are
+    /// scaled up.
+    ArcTo {
+        radii: Vector2<f32>,
+        rotation: f32,
+        large_arc: bool,
+        sweep: bool,
+        end: Vector2<f32>,
+    },
+    /// Straight line back to the start of the subpath
+    Close,
+}
+impl PathCommand {
+    pub(crate) const MOVE_TO: u32 = 0x0;
+    pub(crate) const LINE_TO: u32 = 0x1;
+    pub(crate) const QUADRATIC_TO: u32 = 0x2;
+    pub(crate) const CUBIC_TO: u32 = 0x3;
+    pub(crate) const ARC_TO: u32 = 0x4;
+    pub(crate) const CLOSE: u32 = 0x5;
+    /// Number the command is sent as
+    pub(crate) fn tag(&self) -> u32 {
+        match self {
+            PathCommand::MoveTo(_) => Self::MOVE_TO,
+            PathCommand::LineTo(_) => Self::LINE_TO,
+            PathCommand::QuadraticTo { .. } => Self::QUADRATIC_TO,
+            PathCommand::CubicTo { .. } => Self::CUBIC_TO,
+            PathCommand::ArcTo { .. } => Self::ARC_TO,
+            PathCommand::Close => Self::CLOSE,
+        }
+    }
+}
+/// Shape of the stroke where two segments meet
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LineJoin {
+    /// Sharp corner, beveled when it would reach more than 4 stroke widths past the corner
+    Miter = 0x0,
+    Round = 0x1,
+    Bevel = 0x2,
+}
+impl LineJoin {
+    pub const ALL: [LineJoin; 3] = [LineJoin::Miter, LineJoin::Round, LineJoin::Bevel];
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|join| *join as u32 == value)
+    }
+}
+/// Shape of the stroke at the ends of open subpaths and dashes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LineCap {
+    /// Ends flat at the end point
+    Butt = 0x0,
+    Round = 0x1,
+    /// Ends flat half a stroke width past the end point
+    Square = 0x2,
+}
+impl LineCap {
+    pub const ALL: [LineCap; 3] = [LineCap::Butt, LineCap::Round, LineCap::Square];
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|cap| *cap as u32 == value)
+    }
+}
+/// How the pixels of a picture element are stored in a packet. Pictures always decode to RGBA.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ImageEncoding {
+    /// Four bytes per pixel, the layout of `RgbaImage`
+    Rgba = 0x0,
+    Png = 0x1,
+    /// The Quite OK Image format, close to PNG in size and much faster to encode
+    Qoi = 0x2,
+    /// One byte of luminance per pixel, decodes to opaque gray
+    Gray = 0x3,
+    /// Three bytes per pixel, decodes to opaque colors
+    Rgb = 0x4,
+}
+impl ImageEncoding {
+    pub const ALL: [ImageEncoding; 5] = [
+        ImageEncoding::Rgba,
+        ImageEncoding::Png,
+        ImageEncoding::Qoi,
+        ImageEncoding::Gray,
+        ImageEncoding::Rgb,
+    ];
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|encoding| *encoding as u32 == value)
+    }
+}
+/// Elements drawn as one, moved, scaled, rotated, faded and clipped together. Lets a drawing
+/// like a legend be built once and placed anywhere.
+///
+/// ```
+/// # use parser::{Color, Figure, Group, ShapeStyle};
+/// # use nalgebra::Vector2;
+/// let marker = Figure::new(0, 0).ellipse(
+///     Vector2::new(0, 0),
+///     Vector2::new(4.0, 4.0),
+///     ShapeStyle::filled(Color::RED),
+/// );
+/// let figure = Figure::new(100, 50)
+///     .group(Vector2::new(20, 25), Group::from_figure(marker.clone()))
+///     .group(
+///         Vector2::new(60, 25),
+///         Group::from_figure(marker).scale(Vector2::new(2.0, 1.0)).rotate(45.0).opacity(0.5),
+///     );
+/// assert_eq!(figure.contents().len(), 2);
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Group {
+    /// Maps the coordinates of the children to the group's, which are then offset by the position
+    /// of the element
+    pub transform: Affine2<f32>,
+    /// 0 is invisible and 1 opaque. The children are drawn together and then faded as one, so
+    /// where they overlap they do not show through each other.
+    pub opacity: f32,
+    /// Only the part of the children inside of this rectangle is drawn
+    pub clip: Option<ClipRectangle>,
+    /// Elements with a higher z index are drawn over their siblings, ones with the same z index in
+    /// the order they were added. Elements other than groups have a z index of 0.
+    pub z_index: i32,
+    pub children: Vec<FigureContents>,
+}
+impl Group {
+    /// Untransformed, opaque and unclipped group of `children`
+    pub fn new(children: Vec<FigureContents>) -> Self {
+        Self {
+            transform: Affine2::identity(),
+            opacity: 1.0,
+            clip: None,
+            z_index: 0,
+            children,
+        }
+    }
+    /// Group of the elements of `figure`, so groups can be built with the figure's builder. The
+    /// size of the figure is ignored.
+    pub fn from_figure(figure: Figure) -> Self {
+        Self::new(figure.contents)
+    }
+    pub fn element(mut self, element: FigureContents) -> Self {
+        self.children.push(element);
+        self
+    }
+    /// Nests `group` with its origin at `position` in the coordinates of this group's children
+    pub fn group(self, position: Vector2<i32>, group: Group) -> Self {
+        self.element(FigureContents::new(
+            FigureContentsData::Group(group),
+            position,
+        ))
+    }
+    /// Applies `transform` to the children before the transforms set so far, so chained calls
+    /// read in the same order as an SVG transform list
+    pub fn transform(mut self, transform: Affine2<f32>) -> Self {
+        self.transform *= transform;
+        self
+    }
+    pub fn translate(self, offset: Vector2<f32>) -> Self {
+        self.transform(Affine2::from_matrix_unchecked(Matrix3::new_translation(
+            &offset,
+        )))
+    }
+    pub fn scale(self, factors: Vector2<f32>) -> Self {
+        self.transform(Affine2::from_matrix_unchecked(
+            Matrix3::new_nonuniform_scaling(&factors),
+        ))
+    }
+    /// Rotates the children `degrees` clockwise around their origin
+    pub fn rotate(self, degrees: f32) -> Self {
+        self.transform(Affine2::from_matrix_unchecked(Matrix3::new_rotation(
+            degrees.to_radians(),
+        )))
+    }
+    pub fn opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
+    /// Clips the children to the rectangle with its top left corner at `position`, in the
+    /// coordinates of the children
+    pub fn clip(mut self, position: Vector2<f32>, size: Vector2<f32>) -> Self {
+        self.clip = Some(ClipRectangle { position, size });
+        self
+    }
+    pub fn z_index(mut self, z_index: i32) -> Self {
+        self.z_index = z_index;
+        self
+    }
+}
+/// Rectangle a group is clipped to, in the coordinates of its children
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ClipRectangle {
+    /// Top left corner
+    pub position: Vector2<f32>,
+    pub size: Vector2<f32>,
+}
+/// Grid of values drawn as cells colored by a colormap, like a correlation matrix or a
+/// spectrogram. Sent as floats so the frontend can show the value under the cursor.
+///
+/// ```
+/// # use parser::{Colormap, Figure, Heatmap};
+/// # use nalgebra::Vector2;
+/// let heatmap = Heatmap::new(Vector2::new(3, 2), vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0], Colormap::Magma)
+///     .size(Vector2::new(60.0, 40.0));
+/// assert_eq!(heatmap.range, (0.0, 5.0));
+/// assert_eq!(heatmap.value_at(Vector2::new(45.0, 30.0)), Some(5.0));
+/// let figure = Figure::new(100, 50).heatmap(Vector2::new(20, 5), heatmap);
+/// assert_eq!(figure.contents().len(), 1);
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Heatmap {
+    /// Number of cells across and down
+    pub grid: Vector2<u32>,
+    /// Size the whole grid is drawn at, in the coordinates of the figure
+    pub size: Vector2<f32>,
+    /// Row by row from the top left, `grid.x * grid.y` of them. NaN cells are not drawn.
+    pub values: Vec<f32>,
+    /// Values mapped to the lowest and highest colors of the colormap, values outside of it are
+    /// drawn in those colors
+    pub range: (f32, f32),
+    pub colormap: Colormap,
+}
+impl Heatmap {
+    /// Heatmap with one unit per cell and its range spanning the values that are not NaN
+    pub fn new(grid: Vector2<u32>, values: Vec<f32>, colormap: Colormap) -> Self {
+        let range = values
+            .iter()
+            .filter(|value| !value.is_nan())
+            .fold(None, |range: Option<(f32, f32)>, &value| match range {
+                Some((low, high)) => Some((low.min(value), high.max(value))),
+                None => Some((value, value)),
+            })
+            .unwrap_or((0.0, 1.0));
+        Self {
+            grid,
+            size: grid.map(|cells| cells as f32),
+            values,
+            range,
+            colormap,
+        }
+    }
+    pub fn size(mut self, size: Vector2<f32>) -> Self {
+        self.size = size;
+        self
+    }
+    /// Sets the values drawn in the lowest and highest colors, so heatmaps can share a scale
+    pub fn range(mut self, low: f32, high: f32) -> Self {
+        self.range = (low, high);
+        self
+    }
+    /// Value of the cell at `point`, relative to the top left of the heatmap, or `None` if the
+    /// point is outside of the grid
+    pub fn value_at(&self, point: Vector2<f32>) -> Option<f32> {
+        let x = (point.x / self.size.x * self.grid.x as f32).floor();
+        let y = (point.y / self.size.y * self.grid.y as f32).floor();
+        // also rejects the NaN of a heatmap with no size
+        if !(x >= 0.0 && y >= 0.0 && x < self.grid.x as f32 && y < self.grid.y as f32) {
+            return None;
+        }
+        self.values
+            .get(y as usize * self.grid.x as usize + x as usize)
+            .copied()
+    }
+    /// Color `value` is drawn in
+    pub fn color(&self, value: f32) -> Color {
+        let (low, high) = self.range;
+        let t = if high == low {
+            if value.is_nan() {
+                f32::NAN
+            } else {
+                0.5
+            }
+        } else {
+            (value - low) / (high - low)
+        };
+        self.colormap.color(t)
+    }
+    /// The grid colored by the colormap, one pixel per cell
+    pub fn to_image(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.grid.x, self.grid.y, |x, y| {
+            let index = y as usize * self.grid.x as usize + x as usize;
+            let value = self.values.get(index).copied().unwrap_or(f32::NAN);
+            self.color(value).into()
+        })
+    }
+}
+/// A line of text drawn by the frontend in its own font
+#[derive(Debug, PartialEq, Clone)]
+pub struct Label {
+    pub text: String,
+    /// Size in points
+    pub font_size: f32,
+    pub color: Color,
+    /// Degrees clockwise around the anchor
+    pub rotation: f32,
+    /// Which side of the text the anchor is on
+    pub horizontal: Alignment,
+    pub vertical: VerticalAlignment,
+}
+impl Label {
+    /// Unrotated text with its top left corner at the anchor
+    pub fn new(text: &str, font_size: f32, color: Color) -> Self {
+        Self {
+            text: text.to_string(),
+            font_size,
+            color,
+            rotation: 0.0,
+            horizontal: Alignment::Left,
+            vertical: VerticalAlignment::Top,
+        }
+    }
+    /// Sets which point of the text is placed at the anchor. `Alignment::Center` with
+    /// `VerticalAlignment::Middle` centers the text on it.
+    pub fn align(mut self, horizontal: Alignment, vertical: VerticalAlignment) -> Self {
+        self.horizontal = horizontal;
+        self.vertical = vertical;
+        self
+    }
+    pub fn rotate(mut self, degrees: f32) -> Self {
+        self.rotation = degrees;
+        self
+    }
+}
+/// Which height of a line of text lines up with its anchor
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum VerticalAlignment {
+    Top = 0x0,
+    Middle = 0x1,
+    /// The line the letters sit on
+    Baseline = 0x2,
+    Bottom = 0x3,
+}
+impl VerticalAlignment {
+    pub const ALL: [VerticalAlignment; 4] = [
+        VerticalAlignment::Top,
+        VerticalAlignment::Middle,
+        VerticalAlignment::Baseline,
+        VerticalAlignment::Bottom,
+    ];
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|alignment| *alignment as u32 == value)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{serializer, Deserializer, ParsedAST};
+    #[test]
+    fn builder_round_trip() {
+        let figure = Figure::new(20, 10)
+            .image_at(Vector2::new(-2, 3), RgbaImage::new(2, 2))
+            .polyline(Color::RED, 1.5, vec![Vector2::new(0.0, 0.0), Vector2::new(4.0, 2.0)]);
+        assert_eq!(figure.dimensions(), Vector2::new(20, 10));
+        assert_eq!(figure.contents()[0].position(), Vector2::new(-2, 3));
+        match figure.contents()[1].data() {
+            FigureContentsData::Line(line) => assert_eq!(line.segments.len(), 2),
+            _ => panic!("expected line"),
+        }
+        let ast = ParsedAST::Figure(figure);
+        let parsed = Deserializer::new().parse(&mut serializer::serialize(&ast));
+        assert_eq!(parsed, Ok(vec![ast]));
+    }
+    #[test]
+    fn shapes_round_trip() {
+        let style = ShapeStyle {
+            fill: Color::GREEN,
+            stroke: Color::WHITE,
+            stroke_width: 2.0,
+        };
+        let figure = Figure::new(100, 100)
+            .rectangle(Vector2::new(10, 20), Vector2::new(30.0, 40.0), 4.0, style)
+            .ellipse(
+                Vector2::new(50, 50),
+                Vector2::new(10.0, 10.0),
+                ShapeStyle::filled(Color::RED),
+            )
+            .label(
+                Vector2::new(50, 90),
+                Label::new("x axis", 10.0, Color::WHITE)
+                    .align(Alignment::Center, VerticalAlignment::Bottom)
+                    .rotate(-90.0),
+            )
+            .polygon(
+                vec![
+                    Vector2::new(0.0, 0.0),
+                    Vector2::new(10.0, 0.0),
+                    Vector2::new(5.0, 8.0),
+                ],
+                ShapeStyle::stroked(Color::BLUE.with_alpha(0), 1.0),
+            );
+        assert_eq!(
+            figure.contents()[1].data().element_type(),
+            ElementTypes::Ellipse
+        );
+        let ast = ParsedAST::Figure(figure);
+        let parsed = Deserializer::new().parse(&mut serializer::serialize(&ast));
+        assert_eq!(parsed, Ok(vec![ast]));
+    }
+    #[test]
+    fn group_round_trip() {
+        let legend = Figure::new(0, 0)
+            .rectangle(
+                Vector2::new(0, 0),
+                Vector2::new(8.0, 8.0),
+                0.0,
+                ShapeStyle::filled(Color::RED),
+            )
+            .label(Vector2::new(12, 0), Label::new("errors", 8.0, Color::WHITE));
+        let inner = Group::from_figure(legend).z_index(-1);
+        let group = Group::new(vec![])
+            .group(Vector2::new(2, 3), inner)
+            .translate(Vector2::new(5.0, 0.0))
+            .rotate(90.0)
+            .scale(Vector2::new(2.0, 0.5))
+            .opacity(0.75)
+            .clip(Vector2::new(0.0, 0.0), Vector2::new(40.0, 10.0));
+        let expected = Matrix3::new(0.0, -0.5, 5.0, 2.0, 0.0, 0.0, 0.0, 0.0, 1.0);
+        assert!((group.transform.matrix() - expected).norm() < 1.0e-6);
+        let figure = Figure::new(50, 50).group(Vector2::new(10, 10), group);
+        match figure.contents()[0].data() {
+            FigureContentsData::Group(group) => assert_eq!(group.children[0].z_index(), -1),
+            _ => panic!("expected group"),
+        }
+        let ast = ParsedAST::Figure(figure);
+        let parsed = Deserializer::new().parse(&mut serializer::serialize(&ast));
+        assert_eq!(parsed, Ok(vec![ast]));
+    }
+    #[test]
+    fn view_box() {
+        let view_box = |fit| ViewBox {
+            origin: Vector2::new(-1.0, -1.0),
+            size: Vector2::new(2.0, 1.0),
+            fit,
+        };
+        let figure = Figure::new(400, 200).with_view_box(view_box(Fit::Contain));
+        assert_eq!(figure.size_for(1000), Vector2::new(400, 200));
+        assert_eq!(figure.size_for(300), Vector2::new(300, 150));
+        let figure = figure.with_view_box(view_box(Fit::FillWidth));
+        assert_eq!(figure.size_for(1000), Vector2::new(1000, 500));
+        let figure = figure.with_view_box(view_box(Fit::Fixed));
+        assert_eq!(figure.size_for(300), Vector2::new(400, 200));
+        assert_eq!(Figure::new(400, 200).size_for(300), Vector2::new(400, 200));
+        // extreme aspect ratios stay within the largest size
+        let tall = Figure::new(1, 65535).with_view_box(view_box(Fit::FillWidth));
+        assert_eq!(tall.size_for(800), Vector2::new(1, Figure::MAX_SIZE));
+        let tall = Figure::new(1, u32::MAX).with_view_box(view_box(Fit::Contain));
+        assert_eq!(tall.size_for(800), Vector2::new(1, Figure::MAX_SIZE));
+        let wide = Figure::new(65535, 1).with_view_box(view_box(Fit::FillWidth));
+        assert_eq!(wide.size_for(100_000), Vector2::new(Figure::MAX_SIZE, 1));
+        let empty = Figure::new(0, u32::MAX).with_view_box(view_box(Fit::FillWidth));
+        assert_eq!(empty.size_for(800), Vector2::new(0, Figure::MAX_SIZE));
+        let big = Figure::new(10_000, 5_000).with_view_box(view_box(Fit::Fixed));
+        assert_eq!(big.size_for(800), Vector2::new(Figure::MAX_SIZE, 2048));
+        // the view box is as large as fits and centered
+        let transform = figure.transform_for(Vector2::new(300, 300));
+        let corner = transform.transform_point(&nalgebra::Point2::new(-1.0, -1.0));
+        assert_eq!(corner.coords, Vector2::new(0.0, 75.0));
+        let corner = transform.transform_point(&nalgebra::Point2::new(1.0, 0.0));
+        assert_eq!(corner.coords, Vector2::new(300.0, 225.0));
+        let ast = ParsedAST::Figure(figure);
+        let parsed = Deserializer::new().parse(&mut serializer::serialize(&ast));
+        assert_eq!(parsed, Ok(vec![ast]));
+    }
+    #[test]
+    fn heatmap_values() {
+        let heatmap = Heatmap::new(Vector2::new(2, 1), vec![-1.0, 3.0], Colormap::Viridis)
+            .size(Vector2::new(4.0, 2.0));
+        let figure = Figure::new(20, 20)
+            .heatmap(Vector2::new(10, 10), heatmap.clone().range(0.0, 1.0))
+            .group(
+                Vector2::new(0, 0),
+                Group::new(vec![])
+                    .scale(Vector2::new(2.0, 2.0))
+                    .clip(Vector2::new(0.0, 0.0), Vector2::new(3.0, 3.0))
+                    .element(FigureContents::new(
+                        FigureContentsData::Heatmap(heatmap),
+                        Vector2::new(0, 0),
+                    )),
+            );
+        assert_eq!(
+            figure.heatmap_value_at(Vector2::new(11.0, 11.0)),
+            Some(-1.0)
+        );
+        assert_eq!(figure.heatmap_value_at(Vector2::new(5.0, 1.0)), Some(3.0));
+        // clipped off
+        assert_eq!(figure.heatmap_value_at(Vector2::new(7.0, 1.0)), None);
+        assert_eq!(figure.heatmap_value_at(Vector2::new(15.0, 11.0)), None);
+        // the viewer's colormap reaches the heatmap in the group as well
+        let mut recolored = figure.clone();
+        recolored.set_colormap(Colormap::Magma);
+        assert_eq!(figure.colormap(), Some(Colormap::Viridis));
+        match recolored.contents()[1].data() {
+            FigureContentsData::Group(group) => match group.children[0].data() {
+                FigureContentsData::Heatmap(heatmap) => {
+                    assert_eq!(heatmap.colormap, Colormap::Magma)
+                }
+                _ => panic!("expected heatmap"),
+            },
+            _ => panic!("expected group"),
+        }
+        assert_eq!(Figure::new(1, 1).colormap(), None);
+        let ast = ParsedAST::Figure(figure);
+        let parsed = Deserializer::new().parse(&mut serializer::serialize(&ast));
+        assert_eq!(parsed, Ok(vec![ast]));
+    }
+    #[test]
+    fn path_round_trip() {
+        let path = Path::new(ShapeStyle::stroked(Color::YELLOW, 3.0))
+            .move_to(Vector2::new(10.0, 10.0))
+            .line_to(Vector2::new(40.0, 10.0))
+            .cubic_to(
+                Vector2::new(50.0, 10.0),
+                Vector2::new(50.0, 30.0),
+                Vector2::new(40.0, 30.0),
+            )
+            .arc_to(
+                Vector2::new(10.0, 5.0),
+                30.0,
+                false,
+                true,
+                Vector2::new(20.0, 30.0),
+            )
+            .close()
+            .join(LineJoin::Round)
+            .cap(LineCap::Square)
+            .dash(vec![4.0, 2.0, 1.0, 2.0], 1.5);
+        let ast = ParsedAST::Figure(Figure::new(60, 40).path(path));
+        let parsed = Deserializer::new().parse(&mut serializer::serialize(&ast));
+        assert_eq!(parsed, Ok(vec![ast]));
+    }
+}
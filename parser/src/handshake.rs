@@ -0,0 +1,246 @@
+use super::{
+    serializer, Datatypes, Deserializer, ElementTypes, FigureContents, FigureContentsData,
+    Palette, ParseError, ParsedAST,
+};
+use nalgebra::Vector2;
+use std::io::{Read, Write};
+/// Bytes at the start of a `Hello` payload, lets a frontend tell a GSH app apart from a program
+/// that happens to print bytes that look like a packet header.
+pub const MAGIC: [u8; 4] = *b"GSH\0";
+/// Version of the protocol implemented by this crate
+pub const PROTOCOL_VERSION: u32 = 3;
+/// First packet an app sends, announcing that the rest of the stream is GSH packets.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Hello {
+    /// Protocol version the sender speaks
+    pub version: u32,
+    /// Bitset of the datatypes the sender can handle, bit `n` is set if datatype `n` is supported
+    pub capabilities: u32,
+}
+impl Default for Hello {
+    /// Hello for this version of the crate, advertising every datatype it can encode
+    fn default() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            capabilities: Datatypes::ALL
+                .iter()
+                .fold(0, |bits, datatype| bits | datatype.bit()),
+        }
+    }
+}
+impl Hello {
+    /// Hello for this version of the crate, see `Default`
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn supports(&self, datatype: Datatypes) -> bool {
+        self.capabilities & datatype.bit() != 0
+    }
+}
+/// What a frontend can display, sent in reply to a `CapabilityQuery`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Capabilities {
+    /// Bitset of supported datatypes, see `Datatypes::bit`
+    pub datatypes: u32,
+    /// Bitset of supported figure elements, see `ElementTypes::bit`
+    pub figure_elements: u32,
+    /// Largest picture element the frontend will show, in pixels
+    pub max_image_size: Vector2<u32>,
+    /// Bits per pixel of the display
+    pub color_depth: u32,
+}
+impl Capabilities {
+    /// Capabilities of a frontend that can only show text, what an app should assume if it gets
+    /// no answer
+    pub fn text_only() -> Self {
+        Self {
+            datatypes: Datatypes::Text.bit(),
+            figure_elements: 0,
+            max_image_size: Vector2::new(0, 0),
+            color_depth: 0,
+        }
+    }
+    pub fn supports(&self, datatype: Datatypes) -> bool {
+        self.datatypes & datatype.bit() != 0
+    }
+    pub fn supports_element(&self, element_type: ElementTypes) -> bool {
+        self.figure_elements & element_type.bit() != 0
+    }
+    /// Gets whether the frontend can display everything in `ast`
+    pub fn can_show(&self, ast: &ParsedAST) -> bool {
+        if !self.supports(ast.datatype()) {
+            return false;
+        }
+        match ast {
+            ParsedAST::Figure(figure) => {
+                let dimensions = figure.dimensions();
+                dimensions.x <= self.max_image_size.x
+                    && dimensions.y <= self.max_image_size.y
+                    && self.can_show_elements(figure.contents())
+            }
+            ParsedAST::Update(_, ast) => self.can_show(ast),
+            _ => true,
+        }
+    }
+    fn can_show_elements(&self, elements: &[FigureContents]) -> bool {
+        elements.iter().all(|content| {
+            self.supports_element(content.data().element_type())
+                && match content.data() {
+                    FigureContentsData::Image(image) => {
+                        image.width() <= self.max_image_size.x
+                            && image.height() <= self.max_image_size.y
+                    }
+                    FigureContentsData::Group(group) => self.can_show_elements(&group.children),
+                    // heatmaps are colored into a picture of one pixel per cell
+                    FigureContentsData::Heatmap(heatmap) => {
+                        heatmap.grid.x <= self.max_image_size.x
+                            && heatmap.grid.y <= self.max_image_size.y
+                    }
+                    _ => true,
+                }
+        })
+    }
+    /// Returns `ast` if the frontend can show it, otherwise a text packet made by `text`. Lets an
+    /// app write rich output with a plain text fallback in one place.
+    pub fn with_text_fallback<F: FnOnce() -> String>(&self, ast: ParsedAST, text: F) -> ParsedAST {
+        if self.can_show(&ast) {
+            ast
+        } else {
+            ParsedAST::String(text())
+        }
+    }
+}
+/// Packets a frontend answers a `CapabilityQuery` with. The palette goes first, so apps can stop
+/// reading at the capabilities, and only to apps whose `Hello` lists it. Older apps do not know
+/// the datatype and would stop parsing before they reach the capabilities.
+pub fn capability_reply(
+    app: Option<&Hello>,
+    capabilities: Capabilities,
+    palette: Palette,
+) -> Vec<ParsedAST> {
+    let mut reply = vec![];
+    let knows_palette = app.map(|hello| hello.supports(Datatypes::Palette)) == Some(true);
+    if knows_palette {
+        reply.push(ParsedAST::Palette(palette));
+    }
+    reply.push(ParsedAST::Capabilities(capabilities));
+    reply
+}
+/// Asks the frontend what it supports. Sends a `CapabilityQuery` to `output` then reads packets
+/// from `input` until the reply arrives, other packets that arrive first are dropped. Returns
+/// `Capabilities::text_only` if `input` ends without a reply.
+///
+/// Only call this when running under GSH, a regular terminal never replies.
+pub fn query_capabilities<R: Read, W: Write>(
+    input: R,
+    output: W,
+) -> Result<Capabilities, ParseError> {
+    query_capabilities_and_palette(input, output).map(|(capabilities, _)| capabilities)
+}
+/// Like `query_capabilities`, but also keeps the `Palette` a frontend with a theme sends just
+/// before its capabilities. The palette is `None` if the frontend did not send one.
+pub fn query_capabilities_and_palette<R: Read, W: Write>(
+    input: R,
+    mut output: W,
+) -> Result<(Capabilities, Option<Palette>), ParseError> {
+    serializer::Serializer::new(&mut output).write(&ParsedAST::CapabilityQuery)?;
+    output.flush()?;
+    let mut palette = None;
+    for node in Deserializer::from_reader(input) {
+        match node? {
+            ParsedAST::Capabilities(capabilities) => return Ok((capabilities, palette)),
+            ParsedAST::Palette(sent) => palette = Some(sent),
+            _ => {}
+        }
+    }
+    Ok((Capabilities::text_only(), palette))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Color, Figure, Group, PaletteIndex};
+    use image::RgbaImage;
+    use std::io::Cursor;
+    fn frontend() -> Capabilities {
+        Capabilities {
+            datatypes: Datatypes::Text.bit() | Datatypes::Figure.bit(),
+            figure_elements: ElementTypes::Image.bit(),
+            max_image_size: Vector2::new(8, 8),
+            color_depth: 32,
+        }
+    }
+    #[test]
+    fn can_show() {
+        let capabilities = frontend();
+        let small = Figure::new(8, 8).image_at(Vector2::new(0, 0), RgbaImage::new(8, 8));
+        let large = Figure::new(8, 8).image_at(Vector2::new(0, 0), RgbaImage::new(9, 8));
+        let line = Figure::new(8, 8).polyline(Color::WHITE, 1.0, vec![]);
+        assert!(capabilities.can_show(&ParsedAST::String("a".to_string())));
+        assert!(capabilities.can_show(&ParsedAST::Figure(small.clone())));
+        assert!(!capabilities.can_show(&ParsedAST::Figure(large.clone())));
+        assert!(!capabilities.can_show(&ParsedAST::Figure(line.clone())));
+        assert!(!capabilities.can_show(&ParsedAST::Figure(Figure::new(100_000, 100_000))));
+        // a group is only shown if its children can be
+        let grouped = Figure::new(8, 8).group(Vector2::new(0, 0), Group::from_figure(small));
+        let mut capabilities = capabilities;
+        capabilities.figure_elements |= ElementTypes::Group.bit();
+        assert!(capabilities.can_show(&ParsedAST::Figure(grouped)));
+        let grouped = Figure::new(8, 8).group(Vector2::new(0, 0), Group::from_figure(large));
+        assert!(!capabilities.can_show(&ParsedAST::Figure(grouped)));
+        assert_eq!(
+            Capabilities::text_only()
+                .with_text_fallback(ParsedAST::Figure(line), || "a line".to_string()),
+            ParsedAST::String("a line".to_string())
+        );
+    }
+    #[test]
+    fn query() {
+        let mut reply = serializer::build_text("typed input".to_string());
+        reply.append(&mut serializer::serialize(&ParsedAST::Capabilities(frontend())));
+        let mut sent = vec![];
+        let capabilities = query_capabilities(Cursor::new(reply), &mut sent);
+        assert_eq!(capabilities, Ok(frontend()));
+        assert_eq!(sent, vec![3, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            query_capabilities(Cursor::new(vec![]), &mut vec![]),
+            Ok(Capabilities::text_only())
+        );
+    }
+    #[test]
+    fn query_palette() {
+        let palette = Palette::default().set(PaletteIndex::Accent, Color::MAGENTA);
+        let mut reply = serializer::serialize(&ParsedAST::Palette(palette.clone()));
+        reply.append(&mut serializer::serialize(&ParsedAST::Capabilities(frontend())));
+        assert_eq!(
+            query_capabilities_and_palette(Cursor::new(reply), &mut vec![]),
+            Ok((frontend(), Some(palette)))
+        );
+        let reply = serializer::serialize(&ParsedAST::Capabilities(frontend()));
+        assert_eq!(
+            query_capabilities_and_palette(Cursor::new(reply), &mut vec![]),
+            Ok((frontend(), None))
+        );
+    }
+    #[test]
+    fn reply_palette() {
+        let palette = Palette::default();
+        assert_eq!(
+            capability_reply(Some(&Hello::new()), frontend(), palette.clone()),
+            vec![
+                ParsedAST::Palette(palette.clone()),
+                ParsedAST::Capabilities(frontend())
+            ]
+        );
+        // an app from before palettes, or one that never said hello
+        let old = Hello {
+            version: 2,
+            capabilities: Datatypes::Text.bit() | Datatypes::Capabilities.bit(),
+        };
+        for app in [Some(&old), None].iter() {
+            assert_eq!(
+                capability_reply(*app, frontend(), palette.clone()),
+                vec![ParsedAST::Capabilities(frontend())]
+            );
+        }
+    }
+}
@@ -1,43 +1,139 @@
-use image::RgbaImage;
-use nalgebra::Vector2;
+mod buffer;
 mod deserializer;
 pub use deserializer::Deserializer;
+mod color;
+pub use color::{Color, Colormap, Palette, PaletteIndex, ParseColorError};
+mod figure;
+pub use figure::{
+    ClipRectangle, ElementTypes, Ellipse, Figure, FigureContents, FigureContentsData, Fit, Group,
+    Heatmap, ImageEncoding, Label, Line, LineCap, LineJoin, Path, PathCommand, Polygon, Rectangle,
+    ShapeStyle, VerticalAlignment, ViewBox,
+};
+mod picture;
+mod raster;
+mod svg;
+mod handshake;
+pub use handshake::{
+    capability_reply, query_capabilities, query_capabilities_and_palette, Capabilities, Hello,
+    MAGIC, PROTOCOL_VERSION,
+};
+pub mod environment;
+pub use environment::detect;
+mod styled_text;
+pub use styled_text::{Span, StyledText, Style};
+mod table;
+pub use table::{Alignment, Cell, Column, ColumnType, Table};
+mod progress;
+pub use progress::Progress;
+mod chart;
+pub use chart::{ticks, Axis, Chart, ChartKind, ChartLayout, ChartRange, Series};
+mod error;
+pub use error::{ParseError, ParseErrorKind};
+mod reader;
+pub use reader::{ReadDeserializer, ReadStatus};
 pub mod serializer;
 /// The parsed result.
 #[derive(Debug, PartialEq, Clone)]
 pub enum ParsedAST {
     String(String),
     Figure(Figure),
+    Hello(Hello),
+    /// Asks the frontend to reply with its `Capabilities`
+    CapabilityQuery,
+    Capabilities(Capabilities),
+    StyledText(StyledText),
+    Table(Table),
+    Progress(Progress),
+    Chart(Chart),
+    /// Replaces the output shown for an id, or adds it at the end if nothing has that id yet.
+    /// Only output can be sent in an update, not control packets.
+    Update(u32, Box<ParsedAST>),
+    /// Removes the output shown for an id
+    Delete(u32),
+    /// The frontend's theme colors
+    Palette(Palette),
 }
-#[derive(Debug, PartialEq, Clone)]
-pub struct Figure {
-    dimensions: Vector2<u32>,
-    contents: Vec<FigureContents>,
+impl ParsedAST {
+    pub fn datatype(&self) -> Datatypes {
+        match self {
+            ParsedAST::String(_) => Datatypes::Text,
+            ParsedAST::Figure(_) => Datatypes::Figure,
+            ParsedAST::Hello(_) => Datatypes::Hello,
+            ParsedAST::CapabilityQuery => Datatypes::CapabilityQuery,
+            ParsedAST::Capabilities(_) => Datatypes::Capabilities,
+            ParsedAST::StyledText(_) => Datatypes::StyledText,
+            ParsedAST::Table(_) => Datatypes::Table,
+            ParsedAST::Progress(_) => Datatypes::Progress,
+            ParsedAST::Chart(_) => Datatypes::Chart,
+            ParsedAST::Update(_, _) => Datatypes::Update,
+            ParsedAST::Delete(_) => Datatypes::Delete,
+            ParsedAST::Palette(_) => Datatypes::Palette,
+        }
+    }
 }
-#[derive(Debug, PartialEq, Clone)]
-pub enum ParseError {
-    InvalidDatatype(u32),
-    InvalidImage,
-    InvalidLine,
-    StringNotUTF8,
+/// Header at the start of every packet
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PacketHeader {
+    pub data_type: u32,
+    /// Length of the payload in bytes, not counting the header
+    pub length: u32,
 }
-enum Datatypes {
+/// Type of a packet, sent as the first field of its header
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Datatypes {
     Text = 0x0,
     Figure = 0x1,
+    Hello = 0x2,
+    CapabilityQuery = 0x3,
+    Capabilities = 0x4,
+    StyledText = 0x5,
+    Table = 0x6,
+    Update = 0x7,
+    Delete = 0x8,
+    Progress = 0x9,
+    Palette = 0xa,
+    Chart = 0xb,
 }
-#[derive(Debug, PartialEq, Clone)]
-pub struct FigureContents {
-    data: FigureContentsData,
-    position: Vector2<i32>,
-}
-#[derive(Debug, PartialEq, Clone)]
-pub enum FigureContentsData {
-    Image(RgbaImage),
-    Line(Line),
-}
-#[derive(Debug, PartialEq, Clone)]
-pub struct Line {
-    pub color: u32,
-    pub thickness: f32,
-    pub segments: Vec<Vector2<f32>>,
+impl Datatypes {
+    pub const ALL: [Datatypes; 12] = [
+        Datatypes::Text,
+        Datatypes::Figure,
+        Datatypes::Hello,
+        Datatypes::CapabilityQuery,
+        Datatypes::Capabilities,
+        Datatypes::StyledText,
+        Datatypes::Table,
+        Datatypes::Update,
+        Datatypes::Delete,
+        Datatypes::Progress,
+        Datatypes::Palette,
+        Datatypes::Chart,
+    ];
+    /// Bit for this datatype in a capability bitset
+    pub fn bit(self) -> u32 {
+        1 << self as u32
+    }
+    /// Whether packets of this type are output that gets drawn, as opposed to control packets
+    pub fn is_output(self) -> bool {
+        match self {
+            Datatypes::Text
+            | Datatypes::Figure
+            | Datatypes::StyledText
+            | Datatypes::Table
+            | Datatypes::Progress
+            | Datatypes::Chart => true,
+            Datatypes::Hello
+            | Datatypes::CapabilityQuery
+            | Datatypes::Capabilities
+            | Datatypes::Update
+            | Datatypes::Delete
+            | Datatypes::Palette => false,
+        }
+    }
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|datatype| *datatype as u32 == value)
+    }
 }
@@ -0,0 +1,367 @@
+use super::ImageEncoding;
+use image::png::{PngDecoder, PngEncoder};
+use image::{ColorType, DynamicImage, GrayImage, ImageDecoder, RgbImage, Rgba, RgbaImage};
+use std::borrow::Cow;
+/// Pictures with at most this many bytes of pixels are sent raw, compressing them saves too
+/// little to be worth it
+const SMALL_IMAGE_SIZE: usize = 4096;
+/// Largest picture a compressed payload may decode to. Raw pictures are already bounded by the
+/// length of the packet, compressed ones could otherwise claim gigabytes from a few bytes.
+pub(crate) const MAX_DECODED_PIXELS: u64 = 1 << 26;
+/// Encodes the pixels of `image`. `Gray` and `Rgb` drop alpha and `Gray` also drops color, the
+/// other encodings are lossless.
+pub(crate) fn encode(image: &RgbaImage, encoding: ImageEncoding) -> Cow<'_, [u8]> {
+    match encoding {
+        ImageEncoding::Rgba => Cow::Borrowed(image.as_raw()),
+        ImageEncoding::Png => Cow::Owned(png(image)),
+        ImageEncoding::Qoi => Cow::Owned(qoi::encode(image)),
+        ImageEncoding::Gray => Cow::Owned(image.pixels().map(luminance).collect()),
+        ImageEncoding::Rgb => Cow::Owned(
+            image
+                .pixels()
+                .flat_map(|pixel| pixel.0[..3].iter().copied())
+                .collect(),
+        ),
+    }
+}
+/// Picks the smallest lossless encoding out of the raw ones and QOI. PNG is only used when asked
+/// for, it is rarely much smaller than QOI and many times slower to encode.
+pub(crate) fn encode_smallest(image: &RgbaImage) -> (ImageEncoding, Cow<'_, [u8]>) {
+    let opaque = image.pixels().all(|pixel| pixel[3] == u8::MAX);
+    let raw = if opaque
+        && image
+            .pixels()
+            .all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2])
+    {
+        ImageEncoding::Gray
+    } else if opaque {
+        ImageEncoding::Rgb
+    } else {
+        ImageEncoding::Rgba
+    };
+    let raw_bytes = encode(image, raw);
+    if raw_bytes.len() <= SMALL_IMAGE_SIZE {
+        return (raw, raw_bytes);
+    }
+    let compressed = qoi::encode(image);
+    if compressed.len() < raw_bytes.len() {
+        (ImageEncoding::Qoi, Cow::Owned(compressed))
+    } else {
+        (raw, raw_bytes)
+    }
+}
+/// Decodes the pixels of a `width` by `height` picture. Fails if `data` is not exactly one
+/// picture of that size.
+pub(crate) fn decode(
+    encoding: ImageEncoding,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> Option<RgbaImage> {
+    let pixels = (width as usize).checked_mul(height as usize)?;
+    match encoding {
+        ImageEncoding::Rgba => {
+            if pixels.checked_mul(4)? != data.len() {
+                return None;
+            }
+            RgbaImage::from_raw(width, height, data.to_vec())
+        }
+        ImageEncoding::Gray => {
+            if pixels != data.len() {
+                return None;
+            }
+            let image = GrayImage::from_raw(width, height, data.to_vec())?;
+            Some(DynamicImage::ImageLuma8(image).into_rgba8())
+        }
+        ImageEncoding::Rgb => {
+            if pixels.checked_mul(3)? != data.len() {
+                return None;
+            }
+            let image = RgbImage::from_raw(width, height, data.to_vec())?;
+            Some(DynamicImage::ImageRgb8(image).into_rgba8())
+        }
+        ImageEncoding::Png | ImageEncoding::Qoi
+            if width as u64 * height as u64 > MAX_DECODED_PIXELS =>
+        {
+            None
+        }
+        ImageEncoding::Png => {
+            let decoder = PngDecoder::new(data).ok()?;
+            if decoder.dimensions() != (width, height) {
+                return None;
+            }
+            Some(DynamicImage::from_decoder(decoder).ok()?.into_rgba8())
+        }
+        ImageEncoding::Qoi => qoi::decode(width, height, data),
+    }
+}
+/// Encodes `image` as a PNG with the fewest channels that keep every pixel
+pub(crate) fn png(image: &RgbaImage) -> Vec<u8> {
+    let opaque = image.pixels().all(|pixel| pixel[3] == u8::MAX);
+    let gray = opaque
+        && image
+            .pixels()
+            .all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2]);
+    let (pixels, color) = if gray {
+        (encode(image, ImageEncoding::Gray), ColorType::L8)
+    } else if opaque {
+        (encode(image, ImageEncoding::Rgb), ColorType::Rgb8)
+    } else {
+        (Cow::Borrowed(image.as_raw().as_slice()), ColorType::Rgba8)
+    };
+    let mut bytes = vec![];
+    // encoding into memory only fails for pixels that do not match the dimensions, which
+    // `RgbaImage` rules out
+    PngEncoder::new(&mut bytes)
+        .encode(&pixels, image.width(), image.height(), color)
+        .expect("failed to encode png");
+    bytes
+}
+/// Rec. 709 luminance, exact for pixels that are already gray
+fn luminance(pixel: &Rgba<u8>) -> u8 {
+    let [red, green, blue, _] = pixel.0;
+    ((2126 * red as u32 + 7152 * green as u32 + 722 * blue as u32 + 5000) / 10000) as u8
+}
+/// The Quite OK Image format, see <https://qoiformat.org/qoi-specification.pdf>. The `image`
+/// crate version this depends on predates it.
+mod qoi {
+    use image::{Rgba, RgbaImage};
+    const MAGIC: &[u8; 4] = b"qoif";
+    const HEADER_SIZE: usize = 14;
+    const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+    const OP_INDEX: u8 = 0x00;
+    const OP_DIFF: u8 = 0x40;
+    const OP_LUMA: u8 = 0x80;
+    const OP_RUN: u8 = 0xc0;
+    const OP_RGB: u8 = 0xfe;
+    const OP_RGBA: u8 = 0xff;
+    const TAG_MASK: u8 = 0xc0;
+    const MAX_RUN: u8 = 62;
+    const START: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    fn hash(pixel: Rgba<u8>) -> usize {
+        let [red, green, blue, alpha] = pixel.0;
+        (red as usize * 3 + green as usize * 5 + blue as usize * 7 + alpha as usize * 11) % 64
+    }
+    pub(super) fn encode(image: &RgbaImage) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + image.as_raw().len() / 2);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&image.width().to_be_bytes());
+        bytes.extend_from_slice(&image.height().to_be_bytes());
+        let opaque = image.pixels().all(|pixel| pixel[3] == u8::MAX);
+        bytes.push(if opaque { 3 } else { 4 });
+        // sRGB with linear alpha
+        bytes.push(0);
+        let mut seen = [Rgba([0; 4]); 64];
+        let mut previous = START;
+        let mut run = 0;
+        for &pixel in image.pixels() {
+            if pixel == previous {
+                run += 1;
+                if run == MAX_RUN {
+                    bytes.push(OP_RUN | (run - 1));
+                    run = 0;
+                }
+                continue;
+            }
+            if run > 0 {
+                bytes.push(OP_RUN | (run - 1));
+                run = 0;
+            }
+            let index = hash(pixel);
+            if seen[index] == pixel {
+                bytes.push(OP_INDEX | index as u8);
+            } else if pixel[3] == previous[3] {
+                seen[index] = pixel;
+                let delta = |channel: usize| pixel[channel].wrapping_sub(previous[channel]) as i8;
+                let (red, green, blue) = (delta(0), delta(1), delta(2));
+                let (red_green, blue_green) = (red.wrapping_sub(green), blue.wrapping_sub(green));
+                let small = |delta: i8| (-2..=1).contains(&delta);
+                let medium = |delta: i8| (-8..=7).contains(&delta);
+                if small(red) && small(green) && small(blue) {
+                    bytes.push(
+                        OP_DIFF
+                            | ((red + 2) as u8) << 4
+                            | ((green + 2) as u8) << 2
+                            | (blue + 2) as u8,
+                    );
+                } else if medium(red_green) && (-32..=31).contains(&green) && medium(blue_green) {
+                    bytes.push(OP_LUMA | (green + 32) as u8);
+                    bytes.push(((red_green + 8) as u8) << 4 | (blue_green + 8) as u8);
+                } else {
+                    bytes.extend_from_slice(&[OP_RGB, pixel[0], pixel[1], pixel[2]]);
+                }
+            } else {
+                seen[index] = pixel;
+                bytes.push(OP_RGBA);
+                bytes.extend_from_slice(&pixel.0);
+            }
+            previous = pixel;
+        }
+        if run > 0 {
+            bytes.push(OP_RUN | (run - 1));
+        }
+        bytes.extend_from_slice(&END_MARKER);
+        bytes
+    }
+    /// Decodes a QOI image, failing if it is not `width` by `height` or if anything other than
+    /// the end marker follows the pixels
+    pub(super) fn decode(width: u32, height: u32, data: &[u8]) -> Option<RgbaImage> {
+        let header = data.get(..HEADER_SIZE)?;
+        if &header[..4] != MAGIC
+            || header[4..8] != width.to_be_bytes()
+            || header[8..12] != height.to_be_bytes()
+            || !(3..=4).contains(&header[12])
+        {
+            return None;
+        }
+        let size = width as usize * height as usize * 4;
+        // a byte decodes to at most a run of pixels, so a short picture claiming to be huge
+        // fails before allocating all of it
+        let decodable = (data.len() - HEADER_SIZE).saturating_mul(MAX_RUN as usize * 4);
+        let mut pixels = Vec::with_capacity(size.min(decodable));
+        let mut ops = data[HEADER_SIZE..].iter().copied();
+        let mut seen = [Rgba([0; 4]); 64];
+        let mut pixel = START;
+        let mut run = 0;
+        while pixels.len() < size {
+            if run > 0 {
+                run -= 1;
+            } else {
+                let op = ops.next()?;
+                match op {
+                    OP_RGB => {
+                        for channel in 0..3 {
+                            pixel[channel] = ops.next()?;
+                        }
+                    }
+                    OP_RGBA => {
+                        for channel in 0..4 {
+                            pixel[channel] = ops.next()?;
+                        }
+                    }
+                    _ => match op & TAG_MASK {
+                        OP_INDEX => pixel = seen[op as usize],
+                        OP_DIFF => {
+                            for (channel, shift) in [4, 2, 0].iter().enumerate() {
+                                let delta = (op >> shift & 0x3).wrapping_sub(2);
+                                pixel[channel] = pixel[channel].wrapping_add(delta);
+                            }
+                        }
+                        OP_LUMA => {
+                            let green = (op & 0x3f).wrapping_sub(32);
+                            let next = ops.next()?;
+                            let red = green.wrapping_add(next >> 4).wrapping_sub(8);
+                            let blue = green.wrapping_add(next & 0xf).wrapping_sub(8);
+                            pixel[0] = pixel[0].wrapping_add(red);
+                            pixel[1] = pixel[1].wrapping_add(green);
+                            pixel[2] = pixel[2].wrapping_add(blue);
+                        }
+                        _ => run = op & 0x3f,
+                    },
+                }
+                seen[hash(pixel)] = pixel;
+            }
+            pixels.extend_from_slice(&pixel.0);
+        }
+        // a run going past the last pixel is as malformed as a missing end marker
+        if run > 0 || !ops.eq(END_MARKER.iter().copied()) {
+            return None;
+        }
+        RgbaImage::from_raw(width, height, pixels)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(70, 40, |x, y| {
+            if x < 10 {
+                Rgba([200, 10, 10, 255])
+            } else {
+                Rgba([x as u8 * 3, y as u8 * 5, (x * y) as u8, 255 - x as u8])
+            }
+        })
+    }
+    #[test]
+    fn round_trip() {
+        let image = gradient();
+        for encoding in [ImageEncoding::Rgba, ImageEncoding::Png, ImageEncoding::Qoi].iter() {
+            let bytes = encode(&image, *encoding);
+            assert_eq!(
+                decode(*encoding, image.width(), image.height(), &bytes).as_ref(),
+                Some(&image),
+                "{:?}",
+                encoding
+            );
+        }
+    }
+    #[test]
+    fn opaque_encodings() {
+        let gray = RgbaImage::from_fn(3, 2, |x, y| {
+            let value = (x * 80 + y) as u8;
+            Rgba([value, value, value, 255])
+        });
+        let bytes = encode(&gray, ImageEncoding::Gray);
+        assert_eq!(bytes.len(), 6);
+        assert_eq!(decode(ImageEncoding::Gray, 3, 2, &bytes), Some(gray));
+        let color = RgbaImage::from_pixel(2, 2, Rgba([1, 2, 3, 4]));
+        let bytes = encode(&color, ImageEncoding::Rgb);
+        assert_eq!(&bytes[..], &[1, 2, 3, 1, 2, 3, 1, 2, 3, 1, 2, 3]);
+        assert_eq!(
+            decode(ImageEncoding::Rgb, 2, 2, &bytes),
+            Some(RgbaImage::from_pixel(2, 2, Rgba([1, 2, 3, 255])))
+        );
+    }
+    #[test]
+    fn smallest() {
+        let (encoding, _) = encode_smallest(&RgbaImage::new(4, 4));
+        assert_eq!(encoding, ImageEncoding::Rgba);
+        let flat = RgbaImage::from_pixel(100, 100, Rgba([9, 9, 9, 255]));
+        let (encoding, bytes) = encode_smallest(&flat);
+        assert_eq!(encoding, ImageEncoding::Qoi);
+        assert_eq!(decode(encoding, 100, 100, &bytes), Some(flat));
+    }
+    #[test]
+    fn mismatched_size() {
+        let image = gradient();
+        for encoding in ImageEncoding::ALL.iter() {
+            let bytes = encode(&image, *encoding);
+            assert_eq!(decode(*encoding, 70, 41, &bytes), None, "{:?}", encoding);
+            assert_eq!(
+                decode(*encoding, 70, 40, &bytes[..bytes.len() - 9]),
+                None,
+                "{:?}",
+                encoding
+            );
+        }
+        assert_eq!(decode(ImageEncoding::Qoi, 1 << 16, 1 << 16, b"qoif"), None);
+    }
+    #[test]
+    fn qoi_framing() {
+        let image = gradient();
+        let bytes = qoi::encode(&image);
+        assert_eq!(decode(ImageEncoding::Qoi, 70, 40, &bytes), Some(image));
+        // pixels without the end marker, or only part of it
+        for cut in [1, 8].iter() {
+            let truncated = &bytes[..bytes.len() - cut];
+            assert_eq!(
+                decode(ImageEncoding::Qoi, 70, 40, truncated),
+                None,
+                "{}",
+                cut
+            );
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(decode(ImageEncoding::Qoi, 70, 40, &trailing), None);
+        // a run of two pixels for a one pixel picture
+        let mut run = qoi::encode(&RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 255])));
+        run[14] = 0xc1;
+        assert_eq!(decode(ImageEncoding::Qoi, 1, 1, &run), None);
+        // the largest picture allowed, but only a header and an end marker
+        let mut header = bytes[..14].to_vec();
+        header[4..12].copy_from_slice(&[0, 0, 0x20, 0, 0, 0, 0x20, 0]);
+        header.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(decode(ImageEncoding::Qoi, 8192, 8192, &header), None);
+    }
+}
@@ -0,0 +1,130 @@
+/// Progress of a long running task, usually sent in an `Update` so the bar fills in place.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Progress {
+    pub label: String,
+    pub current: u64,
+    /// Amount of work in total, `None` if it is not known. Frontends show a spinner instead of
+    /// a bar for work of unknown size.
+    pub total: Option<u64>,
+    /// What `current` and `total` count, such as "files"
+    pub units: String,
+    /// Estimated seconds until the task is done
+    pub eta: Option<u32>,
+}
+impl Progress {
+    const DETERMINATE: u32 = 1 << 0;
+    const HAS_ETA: u32 = 1 << 1;
+    pub fn new(label: &str, current: u64, total: u64) -> Self {
+        Self {
+            label: label.to_string(),
+            current,
+            total: Some(total),
+            units: String::new(),
+            eta: None,
+        }
+    }
+    /// Progress of work with an unknown size
+    pub fn indeterminate(label: &str) -> Self {
+        Self {
+            total: None,
+            ..Self::new(label, 0, 0)
+        }
+    }
+    pub fn units(mut self, units: &str) -> Self {
+        self.units = units.to_string();
+        self
+    }
+    pub fn eta(mut self, seconds: u32) -> Self {
+        self.eta = Some(seconds);
+        self
+    }
+    /// How much of the work is done between 0 and 1, `None` if the total is unknown. Work with
+    /// a total of 0 is done.
+    pub fn fraction(&self) -> Option<f32> {
+        self.total.map(|total| {
+            if total == 0 {
+                1.0
+            } else {
+                (self.current as f64 / total as f64).min(1.0) as f32
+            }
+        })
+    }
+    /// Counts, percentage and time left, for example "5/10 files 50% ETA 0:05"
+    pub fn status(&self) -> String {
+        let mut status = match self.total {
+            Some(total) => format!("{}/{}", self.current, total),
+            None => self.current.to_string(),
+        };
+        if !self.units.is_empty() {
+            status = format!("{} {}", status, self.units);
+        }
+        if let Some(fraction) = self.fraction() {
+            status = format!("{} {}%", status, (fraction * 100.0).floor());
+        }
+        if let Some(eta) = self.eta {
+            let (hours, minutes, seconds) = (eta / 3600, eta / 60 % 60, eta % 60);
+            status = if hours > 0 {
+                format!("{} ETA {}:{:02}:{:02}", status, hours, minutes, seconds)
+            } else {
+                format!("{} ETA {}:{:02}", status, minutes, seconds)
+            };
+        }
+        status
+    }
+    /// Draws the bar with characters, for frontends that can only show plain text
+    pub fn plain_text(&self) -> String {
+        const WIDTH: usize = 20;
+        let bar = match self.fraction() {
+            Some(fraction) => {
+                let filled = (fraction * WIDTH as f32) as usize;
+                format!("[{}{}]", "#".repeat(filled), "-".repeat(WIDTH - filled))
+            }
+            None => "[...]".to_string(),
+        };
+        format!("{} {} {}\n", self.label, bar, self.status())
+    }
+    /// Packs whether the total and ETA are known the way they are sent
+    pub(crate) fn flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.total.is_some() {
+            flags |= Self::DETERMINATE;
+        }
+        if self.eta.is_some() {
+            flags |= Self::HAS_ETA;
+        }
+        flags
+    }
+    pub(crate) fn from_fields(
+        flags: u32,
+        label: String,
+        current: u64,
+        total: u64,
+        units: String,
+        eta: u32,
+    ) -> Self {
+        Self {
+            label,
+            current,
+            total: Some(total).filter(|_| flags & Self::DETERMINATE != 0),
+            units,
+            eta: Some(eta).filter(|_| flags & Self::HAS_ETA != 0),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn status() {
+        let progress = Progress::new("copying", 5, 10).units("files").eta(65);
+        assert_eq!(progress.fraction(), Some(0.5));
+        assert_eq!(
+            progress.plain_text(),
+            "copying [##########----------] 5/10 files 50% ETA 1:05\n"
+        );
+        let indeterminate = Progress::indeterminate("waiting");
+        assert_eq!(indeterminate.fraction(), None);
+        assert_eq!(indeterminate.plain_text(), "waiting [...] 0\n");
+        assert_eq!(Progress::new("", 20, 10).eta(3600).status(), "20/10 100% ETA 1:00:00");
+    }
+}
@@ -0,0 +1,1099 @@
+use super::figure::drawing_order;
+use super::{
+    Color, Figure, FigureContents, FigureContentsData, Group, Heatmap, Label, LineCap, LineJoin,
+    PathCommand, Rectangle, ShapeStyle,
+};
+use image::{Rgba, RgbaImage};
+use nalgebra::{Affine2, Matrix3, Point2, Vector2};
+use std::cmp::Ordering;
+use std::f32::consts::PI;
+/// Each row of pixels is sampled at this many heights, coverage across a row is exact
+const SAMPLES_PER_ROW: usize = 4;
+/// Curves are drawn as straight segments about this many pixels long
+const CURVE_SEGMENT_LENGTH: f32 = 3.0;
+/// Furthest a curve drawn with straight segments may stray from the real curve, in pixels
+const TOLERANCE: f32 = 0.2;
+/// Longest a miter can be relative to the stroke width before the join is beveled
+const MITER_LIMIT: f32 = 4.0;
+/// Dash patterns that would split a stroke into more dashes than this are drawn solid
+const MAX_DASHES: usize = 100_000;
+impl Figure {
+    /// Draws the figure on a transparent image the size of the figure, anything outside of it is
+    /// cut off. Figures bigger than `Figure::MAX_SIZE` are scaled down to fit. Text needs a font
+    /// so it is skipped, see `rasterize_with`.
+    pub fn rasterize(&self) -> RgbaImage {
+        self.rasterize_with(|_, _, _| {})
+    }
+    /// Draws the figure like `rasterize`, calling `draw_label` for every text element in drawing
+    /// order with the anchor of the text in pixels. Text in a transformed group is passed
+    /// rotated and scaled with the group, but never skewed or mirrored.
+    pub fn rasterize_with<F: FnMut(&mut RgbaImage, &Label, Vector2<f32>)>(
+        &self,
+        draw_label: F,
+    ) -> RgbaImage {
+        self.rasterize_at_with(self.dimensions, draw_label)
+    }
+    /// Draws the figure scaled to an image `size` pixels big, see `transform_for`. Sizes bigger
+    /// than `Figure::MAX_SIZE` are scaled down to fit.
+    pub fn rasterize_at(&self, size: Vector2<u32>) -> RgbaImage {
+        self.rasterize_at_with(size, |_, _, _| {})
+    }
+    /// Draws the figure like `rasterize_at`, with text drawn by `draw_label` like in
+    /// `rasterize_with`
+    pub fn rasterize_at_with<F: FnMut(&mut RgbaImage, &Label, Vector2<f32>)>(
+        &self,
+        size: Vector2<u32>,
+        mut draw_label: F,
+    ) -> RgbaImage {
+        let size = Figure::clamp_size(size);
+        let mut image = RgbaImage::new(size.x, size.y);
+        let placement = Placement::new(self.transform_for(size));
+        draw_elements(&mut image, &self.contents, &placement, &mut draw_label);
+        image
+    }
+}
+/// Maps the coordinates of elements to pixels
+struct Placement {
+    transform: Affine2<f32>,
+    /// Most pixels a unit of the elements' coordinates is stretched to, curves are split into
+    /// segments short enough to look smooth at this scale
+    scale: f32,
+}
+impl Placement {
+    fn new(transform: Affine2<f32>) -> Self {
+        let (a, b, c, d) = linear_part(&transform);
+        // the largest singular value of the linear part
+        let sum = a * a + b * b + c * c + d * d;
+        let determinant = a * d - b * c;
+        let spread = (sum * sum - 4.0 * determinant * determinant)
+            .max(0.0)
+            .sqrt();
+        Self {
+            transform,
+            scale: ((sum + spread) / 2.0).sqrt(),
+        }
+    }
+    fn point(&self, point: Vector2<f32>) -> Vector2<f32> {
+        self.transform.transform_point(&Point2::from(point)).coords
+    }
+    fn polygons(&self, mut polygons: Vec<Vec<Vector2<f32>>>) -> Vec<Vec<Vector2<f32>>> {
+        for point in polygons.iter_mut().flatten() {
+            *point = self.point(*point);
+        }
+        polygons
+    }
+    /// `label` scaled by the average scale of the transform and turned with its x axis
+    fn label(&self, label: &Label) -> Label {
+        let (a, b, c, d) = linear_part(&self.transform);
+        let mut label = label.clone();
+        label.font_size *= (a * d - b * c).abs().sqrt();
+        label.rotation += b.atan2(a).to_degrees();
+        label
+    }
+}
+/// The entries of the matrix of `transform` that scale, rotate and skew, in the order of an SVG
+/// matrix(a b c d)
+fn linear_part(transform: &Affine2<f32>) -> (f32, f32, f32, f32) {
+    let matrix = transform.matrix();
+    (
+        matrix[(0, 0)],
+        matrix[(1, 0)],
+        matrix[(0, 1)],
+        matrix[(1, 1)],
+    )
+}
+fn draw_elements<F: FnMut(&mut RgbaImage, &Label, Vector2<f32>)>(
+    image: &mut RgbaImage,
+    elements: &[FigureContents],
+    placement: &Placement,
+    draw_label: &mut F,
+) {
+    for element in drawing_order(elements) {
+        let offset = Vector2::new(element.position.x as f32, element.position.y as f32);
+        match &element.data {
+            FigureContentsData::Image(picture) => draw_image(image, picture, offset, placement),
+            FigureContentsData::Line(line) => {
+                let subpath = Subpath {
+                    points: line.segments.iter().map(|point| point + offset).collect(),
+                    closed: false,
+                };
+                let stroke = Stroke {
+                    width: line.thickness,
+                    join: LineJoin::Miter,
+                    cap: LineCap::Butt,
+                    dashes: &[],
+                    dash_offset: 0.0,
+                };
+                stroke.draw(image, &[subpath], line.color, placement);
+            }
+            FigureContentsData::Rectangle(rectangle) => {
+                let outline = rounded_rectangle(offset, rectangle, placement.scale);
+                draw_shape(image, outline, &rectangle.style, placement);
+            }
+            FigureContentsData::Ellipse(ellipse) => {
+                let outline = ellipse_points(offset, ellipse.radii, placement.scale);
+                draw_shape(image, outline, &ellipse.style, placement);
+            }
+            FigureContentsData::Polygon(polygon) => {
+                let outline = polygon.points.iter().map(|point| point + offset).collect();
+                draw_shape(image, outline, &polygon.style, placement);
+            }
+            FigureContentsData::Text(label) => {
+                draw_label(image, &placement.label(label), placement.point(offset))
+            }
+            FigureContentsData::Path(path) => {
+                let subpaths = flatten(&path.commands, offset, placement.scale);
+                let outlines: Vec<Vec<Vector2<f32>>> = subpaths
+                    .iter()
+                    .map(|subpath| subpath.points.clone())
+                    .collect();
+                fill(image, &placement.polygons(outlines), path.style.fill);
+                let stroke = Stroke {
+                    width: path.style.stroke_width,
+                    join: path.join,
+                    cap: path.cap,
+                    dashes: &path.dashes,
+                    dash_offset: path.dash_offset,
+                };
+                stroke.draw(image, &subpaths, path.style.stroke, placement);
+            }
+            FigureContentsData::Group(group) => {
+                draw_group(image, group, offset, placement, draw_label)
+            }
+            FigureContentsData::Heatmap(heatmap) => draw_heatmap(image, heatmap, offset, placement),
+        }
+    }
+}
+/// Draws the children of `group` with their origin at `offset`. Groups that are faded or
+/// clipped are drawn on a layer of their own first.
+fn draw_group<F: FnMut(&mut RgbaImage, &Label, Vector2<f32>)>(
+    image: &mut RgbaImage,
+    group: &Group,
+    offset: Vector2<f32>,
+    placement: &Placement,
+    draw_label: &mut F,
+) {
+    let translation = Affine2::from_matrix_unchecked(Matrix3::new_translation(&offset));
+    let inner = Placement::new(placement.transform * translation * group.transform);
+    if group.opacity.is_nan() || group.opacity <= 0.0 {
+        return;
+    }
+    if group.opacity >= 1.0 && group.clip.is_none() {
+        draw_elements(image, &group.children, &inner, draw_label);
+        return;
+    }
+    let (width, height) = image.dimensions();
+    let mut layer = RgbaImage::new(width, height);
+    draw_elements(&mut layer, &group.children, &inner, draw_label);
+    let mask = group.clip.map(|clip| {
+        let (near, far) = (clip.position, clip.position + clip.size);
+        let corners = vec![
+            near,
+            Vector2::new(far.x, near.y),
+            far,
+            Vector2::new(near.x, far.y),
+        ];
+        let mut mask = RgbaImage::new(width, height);
+        fill(&mut mask, &inner.polygons(vec![corners]), Color::WHITE);
+        mask
+    });
+    let opacity = group.opacity.min(1.0);
+    for (x, y, pixel) in layer.enumerate_pixels() {
+        let coverage = match &mask {
+            Some(mask) => opacity * mask.get_pixel(x, y)[3] as f32 / 255.0,
+            None => opacity,
+        };
+        blend(image.get_pixel_mut(x, y), pixel.0, coverage);
+    }
+}
+/// Points joined by straight lines
+struct Subpath {
+    points: Vec<Vector2<f32>>,
+    /// Whether the last point connects back to the first
+    closed: bool,
+}
+struct Stroke<'a> {
+    width: f32,
+    join: LineJoin,
+    cap: LineCap,
+    dashes: &'a [f32],
+    dash_offset: f32,
+}
+impl Stroke<'_> {
+    /// Strokes every subpath in `color`. The outline of each segment, join and cap is filled
+    /// together so overlapping parts are not blended twice.
+    fn draw(
+        &self,
+        image: &mut RgbaImage,
+        subpaths: &[Subpath],
+        color: Color,
+        placement: &Placement,
+    ) {
+        if !positive(self.width) {
+            return;
+        }
+        let mut pieces = vec![];
+        for subpath in subpaths {
+            let points = &subpath.points;
+            if points.len() < 2 {
+                continue;
+            }
+            match self.dash_pattern(points, subpath.closed) {
+                Some(pattern) => {
+                    for dash in dash(points, subpath.closed, &pattern, self.dash_offset) {
+                        self.outline(&mut pieces, &dash, false, placement.scale);
+                    }
+                }
+                None => self.outline(&mut pieces, points, subpath.closed, placement.scale),
+            }
+        }
+        // the pieces all wind the same way so the nonzero rule fills their union, which a
+        // transform keeps true even if it mirrors them
+        for piece in pieces.iter_mut() {
+            if signed_area(piece) < 0.0 {
+                piece.reverse();
+            }
+        }
+        fill(image, &placement.polygons(pieces), color);
+    }
+    /// Dashes to split `points` into, `None` for a solid stroke. Patterns with an odd number of
+    /// lengths are repeated to make an even number, like in SVG.
+    fn dash_pattern(&self, points: &[Vector2<f32>], closed: bool) -> Option<Vec<f32>> {
+        let period: f32 = self.dashes.iter().sum();
+        let valid = self
+            .dashes
+            .iter()
+            .all(|dash| *dash >= 0.0 && dash.is_finite());
+        if !valid || !positive(period) || !self.dash_offset.is_finite() {
+            return None;
+        }
+        let mut pattern = self.dashes.to_vec();
+        if pattern.len() % 2 == 1 {
+            pattern.extend_from_slice(self.dashes);
+        }
+        let length: f64 = segments(points, closed).map(|(a, b)| distance(a, b)).sum();
+        let dashes = length / period as f64 * (pattern.len() / 2) as f64;
+        if dashes.is_nan() || dashes > MAX_DASHES as f64 {
+            return None;
+        }
+        Some(pattern)
+    }
+    /// Adds the outline of the stroke along `points` to `pieces`, with round parts split finely
+    /// enough for `scale`
+    fn outline(
+        &self,
+        pieces: &mut Vec<Vec<Vector2<f32>>>,
+        points: &[Vector2<f32>],
+        closed: bool,
+        scale: f32,
+    ) {
+        // repeated points would make segments without a direction
+        let mut points = points.to_vec();
+        points.dedup();
+        if closed && points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        if points.len() < 2 {
+            return;
+        }
+        let half = self.width / 2.0;
+        let segments: Vec<(Vector2<f32>, Vector2<f32>)> = segments(&points, closed).collect();
+        for (a, b) in segments.iter() {
+            let normal = perpendicular((b - a).normalize()) * half;
+            pieces.push(vec![a + normal, b + normal, b - normal, a - normal]);
+        }
+        let corners = if closed {
+            segments.len()
+        } else {
+            segments.len() - 1
+        };
+        for index in 0..corners {
+            let (a, b) = segments[index];
+            let (_, c) = segments[(index + 1) % segments.len()];
+            self.add_join(pieces, b, (b - a).normalize(), (c - b).normalize(), scale);
+        }
+        if !closed {
+            let (first, second) = segments[0];
+            self.add_cap(pieces, first, (first - second).normalize(), scale);
+            let (before, last) = segments[segments.len() - 1];
+            self.add_cap(pieces, last, (last - before).normalize(), scale);
+        }
+    }
+    /// Fills the gap on the outside of the corner at `vertex` between segments going in the
+    /// directions `incoming` and `outgoing`
+    fn add_join(
+        &self,
+        pieces: &mut Vec<Vec<Vector2<f32>>>,
+        vertex: Vector2<f32>,
+        incoming: Vector2<f32>,
+        outgoing: Vector2<f32>,
+        scale: f32,
+    ) {
+        let half = self.width / 2.0;
+        let cross = incoming.x * outgoing.y - incoming.y * outgoing.x;
+        if cross.abs() < 1.0e-6 && incoming.dot(&outgoing) > 0.0 {
+            return;
+        }
+        if self.join == LineJoin::Round {
+            pieces.push(ellipse_points(vertex, Vector2::new(half, half), scale));
+            return;
+        }
+        // the outside of the corner is on the other side from the way the path turns
+        let side = if cross > 0.0 { -1.0 } else { 1.0 };
+        let (n1, n2) = (perpendicular(incoming), perpendicular(outgoing));
+        let (a, b) = (vertex + n1 * half * side, vertex + n2 * half * side);
+        // the sum is 2cos(θ/2) long for a turn of θ and the miter is half/cos(θ/2) long
+        let sum = n1 + n2;
+        let miter = 2.0 / sum.norm();
+        if self.join == LineJoin::Miter && miter <= MITER_LIMIT {
+            let tip = vertex + sum * (side * half * 2.0 / sum.norm_squared());
+            pieces.push(vec![vertex, a, tip, b]);
+        } else {
+            pieces.push(vec![vertex, a, b]);
+        }
+    }
+    /// Adds the cap at the end `point` of a stroke going in `direction`
+    fn add_cap(
+        &self,
+        pieces: &mut Vec<Vec<Vector2<f32>>>,
+        point: Vector2<f32>,
+        direction: Vector2<f32>,
+        scale: f32,
+    ) {
+        let half = self.width / 2.0;
+        match self.cap {
+            LineCap::Butt => {}
+            LineCap::Round => pieces.push(ellipse_points(point, Vector2::new(half, half), scale)),
+            LineCap::Square => {
+                let normal = perpendicular(direction) * half;
+                let end = direction * half;
+                pieces.push(vec![
+                    point + normal,
+                    point + normal + end,
+                    point - normal + end,
+                    point - normal,
+                ]);
+            }
+        }
+    }
+}
+/// Fills and then strokes a closed outline
+fn draw_shape(
+    image: &mut RgbaImage,
+    outline: Vec<Vector2<f32>>,
+    style: &ShapeStyle,
+    placement: &Placement,
+) {
+    fill(
+        image,
+        &placement.polygons(vec![outline.clone()]),
+        style.fill,
+    );
+    let stroke = Stroke {
+        width: style.stroke_width,
+        join: LineJoin::Miter,
+        cap: LineCap::Butt,
+        dashes: &[],
+        dash_offset: 0.0,
+    };
+    let subpath = Subpath {
+        points: outline,
+        closed: true,
+    };
+    stroke.draw(image, &[subpath], style.stroke, placement);
+}
+/// Edge of a polygon from its top to its bottom
+#[derive(Clone, Copy)]
+struct Edge {
+    top: Vector2<f32>,
+    bottom: Vector2<f32>,
+    /// 1 if the edge goes down, -1 if it goes up
+    winding: i32,
+}
+/// Fills the inside of `polygons` by the nonzero rule in `color`, anti-aliased
+fn fill(image: &mut RgbaImage, polygons: &[Vec<Vector2<f32>>], color: Color) {
+    if color.is_transparent() {
+        return;
+    }
+    let mut edges: Vec<Edge> = polygons
+        .iter()
+        .flat_map(|polygon| segments(polygon, true))
+        .filter(|(a, b)| a.y != b.y && a.iter().chain(b.iter()).all(|c| c.is_finite()))
+        .map(|(a, b)| {
+            if a.y < b.y {
+                Edge {
+                    top: a,
+                    bottom: b,
+                    winding: 1,
+                }
+            } else {
+                Edge {
+                    top: b,
+                    bottom: a,
+                    winding: -1,
+                }
+            }
+        })
+        .collect();
+    if edges.is_empty() {
+        return;
+    }
+    edges.sort_by(|a, b| a.top.y.partial_cmp(&b.top.y).unwrap_or(Ordering::Equal));
+    let highest = edges[0].top.y;
+    let lowest = edges
+        .iter()
+        .map(|edge| edge.bottom.y)
+        .fold(highest, f32::max);
+    let first_row = highest.floor().max(0.0) as u32;
+    let last_row = (lowest.ceil().max(0.0) as u32).min(image.height());
+    let mut coverage = vec![0.0f32; image.width() as usize];
+    let mut active: Vec<Edge> = vec![];
+    let mut crossings: Vec<(f32, i32)> = vec![];
+    let mut next = 0;
+    for row in first_row..last_row {
+        let (row_top, row_bottom) = (row as f32, row as f32 + 1.0);
+        while next < edges.len() && edges[next].top.y < row_bottom {
+            active.push(edges[next]);
+            next += 1;
+        }
+        active.retain(|edge| edge.bottom.y > row_top);
+        coverage.iter_mut().for_each(|pixel| *pixel = 0.0);
+        for sample in 0..SAMPLES_PER_ROW {
+            let y = row_top + (sample as f32 + 0.5) / SAMPLES_PER_ROW as f32;
+            crossings.clear();
+            for edge in active.iter() {
+                if y >= edge.top.y && y < edge.bottom.y {
+                    let t = (y - edge.top.y) / (edge.bottom.y - edge.top.y);
+                    let x = edge.top.x + t * (edge.bottom.x - edge.top.x);
+                    crossings.push((x, edge.winding));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                if winding != 0 {
+                    add_span(
+                        &mut coverage,
+                        pair[0].0,
+                        pair[1].0,
+                        1.0 / SAMPLES_PER_ROW as f32,
+                    );
+                }
+            }
+        }
+        let color = color.to_bytes();
+        for (x, amount) in coverage.iter().enumerate() {
+            if *amount > 0.0 {
+                blend(image.get_pixel_mut(x as u32, row), color, amount.min(1.0));
+            }
+        }
+    }
+}
+/// Adds `weight` times how much of each pixel lies between `start` and `end`
+fn add_span(coverage: &mut [f32], start: f32, end: f32, weight: f32) {
+    let (start, end) = (start.max(0.0), end.min(coverage.len() as f32));
+    if start >= end {
+        return;
+    }
+    for x in start as usize..(end.ceil() as usize).min(coverage.len()) {
+        let overlap = end.min(x as f32 + 1.0) - start.max(x as f32);
+        coverage[x] += overlap * weight;
+    }
+}
+/// Blends `color`, packed as bytes in the order red, green, blue, alpha, over `pixel`
+fn blend(pixel: &mut Rgba<u8>, color: [u8; 4], coverage: f32) {
+    let source = color[3] as f32 / 255.0 * coverage;
+    if source <= 0.0 {
+        return;
+    }
+    let destination = pixel[3] as f32 / 255.0 * (1.0 - source);
+    let alpha = source + destination;
+    for channel in 0..3 {
+        let mixed = color[channel] as f32 * source + pixel[channel] as f32 * destination;
+        pixel[channel] = (mixed / alpha).round() as u8;
+    }
+    pixel[3] = (alpha * 255.0).round() as u8;
+}
+/// Blends `picture` over `image` with its top left corner at `position`. Each pixel of `image`
+/// takes the pixel of the picture its center lands on, so transformed pictures stay sharp.
+fn draw_image(
+    image: &mut RgbaImage,
+    picture: &RgbaImage,
+    position: Vector2<f32>,
+    placement: &Placement,
+) {
+    let inverse = match placement.transform.try_inverse() {
+        Some(inverse) => inverse,
+        None => return,
+    };
+    let size = Vector2::new(picture.width() as f32, picture.height() as f32);
+    let corners: Vec<Vector2<f32>> = [
+        Vector2::new(0.0, 0.0),
+        Vector2::new(size.x, 0.0),
+        size,
+        Vector2::new(0.0, size.y),
+    ]
+    .iter()
+    .map(|corner| placement.point(position + corner))
+    .collect();
+    if !corners
+        .iter()
+        .flat_map(|corner| corner.iter())
+        .all(|c| c.is_finite())
+    {
+        return;
+    }
+    let (mut low, mut high) = (corners[0], corners[0]);
+    for corner in corners.iter() {
+        low = low.inf(corner);
+        high = high.sup(corner);
+    }
+    let columns = low.x.floor().max(0.0) as u32..(high.x.ceil().max(0.0) as u32).min(image.width());
+    let rows = low.y.floor().max(0.0) as u32..(high.y.ceil().max(0.0) as u32).min(image.height());
+    for y in rows {
+        for x in columns.clone() {
+            let center = Point2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let source = inverse.transform_point(&center).coords - position;
+            let (column, row) = (source.x.floor(), source.y.floor());
+            if column >= 0.0 && row >= 0.0 && column < size.x && row < size.y {
+                let pixel = picture.get_pixel(column as u32, row as u32);
+                blend(image.get_pixel_mut(x, y), pixel.0, 1.0);
+            }
+        }
+    }
+}
+/// Draws the cells of `heatmap` as a picture of one pixel per cell stretched to its size, so the
+/// edges between cells stay sharp
+fn draw_heatmap(
+    image: &mut RgbaImage,
+    heatmap: &Heatmap,
+    offset: Vector2<f32>,
+    placement: &Placement,
+) {
+    let grid = Vector2::new(heatmap.grid.x as f32, heatmap.grid.y as f32);
+    let cell = heatmap.size.component_div(&grid);
+    let translation = Affine2::from_matrix_unchecked(Matrix3::new_translation(&offset));
+    let scaling = Affine2::from_matrix_unchecked(Matrix3::new_nonuniform_scaling(&cell));
+    let cells = Placement::new(placement.transform * translation * scaling);
+    draw_image(image, &heatmap.to_image(), Vector2::new(0.0, 0.0), &cells);
+}
+/// Splits the commands of a path into subpaths of straight lines, short enough to look smooth
+/// when stretched by `scale`
+fn flatten(commands: &[PathCommand], offset: Vector2<f32>, scale: f32) -> Vec<Subpath> {
+    let mut subpaths = vec![];
+    let mut points: Vec<Vector2<f32>> = vec![];
+    let mut start = offset;
+    for command in commands {
+        // drawing without a move first starts where the last subpath did
+        if points.is_empty() && !matches!(command, PathCommand::MoveTo(_)) {
+            points.push(start);
+        }
+        let pen = points.last().copied().unwrap_or(start);
+        match *command {
+            PathCommand::MoveTo(point) => {
+                if points.len() > 1 {
+                    subpaths.push(Subpath {
+                        points: std::mem::take(&mut points),
+                        closed: false,
+                    });
+                }
+                start = point + offset;
+                points = vec![start];
+            }
+            PathCommand::LineTo(point) => points.push(point + offset),
+            PathCommand::QuadraticTo { control, end } => {
+                let (control, end) = (control + offset, end + offset);
+                let length = (control - pen).norm() + (end - control).norm();
+                let steps = curve_steps(length * scale);
+                points.extend((1..=steps).map(|step| {
+                    let t = step as f32 / steps as f32;
+                    pen * (1.0 - t).powi(2) + control * 2.0 * t * (1.0 - t) + end * t * t
+                }));
+            }
+            PathCommand::CubicTo {
+                control_1,
+                control_2,
+                end,
+            } => {
+                let (control_1, control_2, end) =
+                    (control_1 + offset, control_2 + offset, end + offset);
+                let length = (control_1 - pen).norm()
+                    + (control_2 - control_1).norm()
+                    + (end - control_2).norm();
+                let steps = curve_steps(length * scale);
+                points.extend((1..=steps).map(|step| {
+                    let t = step as f32 / steps as f32;
+                    let u = 1.0 - t;
+                    pen * u * u * u
+                        + control_1 * 3.0 * u * u * t
+                        + control_2 * 3.0 * u * t * t
+                        + end * t * t * t
+                }));
+            }
+            PathCommand::ArcTo {
+                radii,
+                rotation,
+                large_arc,
+                sweep,
+                end,
+            } => arc(
+                &mut points,
+                pen,
+                radii,
+                rotation,
+                large_arc,
+                sweep,
+                end + offset,
+                scale,
+            ),
+            PathCommand::Close => {
+                subpaths.push(Subpath {
+                    points: std::mem::take(&mut points),
+                    closed: true,
+                });
+            }
+        }
+    }
+    if points.len() > 1 {
+        subpaths.push(Subpath {
+            points,
+            closed: false,
+        });
+    }
+    subpaths
+}
+/// Number of straight segments to draw a curve with, from the length of its control polygon
+fn curve_steps(length: f32) -> usize {
+    if length.is_finite() {
+        (length / CURVE_SEGMENT_LENGTH).ceil().clamp(1.0, 256.0) as usize
+    } else {
+        1
+    }
+}
+/// Adds the points of an SVG arc from `from` to `end` to `points`, following the conversion
+/// from endpoints to a center in the SVG implementation notes
+#[allow(clippy::too_many_arguments)]
+fn arc(
+    points: &mut Vec<Vector2<f32>>,
+    from: Vector2<f32>,
+    radii: Vector2<f32>,
+    rotation: f32,
+    large_arc: bool,
+    sweep: bool,
+    end: Vector2<f32>,
+    scale: f32,
+) {
+    let (mut rx, mut ry) = (radii.x.abs(), radii.y.abs());
+    if from == end {
+        return;
+    }
+    if !positive(rx) || !positive(ry) {
+        points.push(end);
+        return;
+    }
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let half = (from - end) / 2.0;
+    let p = Vector2::new(cos * half.x + sin * half.y, -sin * half.x + cos * half.y);
+    // radii too small to reach the end are scaled up until they just do
+    let reach = (p.x / rx).powi(2) + (p.y / ry).powi(2);
+    if reach > 1.0 {
+        rx *= reach.sqrt();
+        ry *= reach.sqrt();
+    }
+    let numerator = (rx * ry).powi(2) - (rx * p.y).powi(2) - (ry * p.x).powi(2);
+    let denominator = (rx * p.y).powi(2) + (ry * p.x).powi(2);
+    let mut factor = (numerator / denominator).max(0.0).sqrt();
+    if large_arc == sweep {
+        factor = -factor;
+    }
+    let center_p = Vector2::new(factor * rx * p.y / ry, -factor * ry * p.x / rx);
+    let center = Vector2::new(
+        cos * center_p.x - sin * center_p.y,
+        sin * center_p.x + cos * center_p.y,
+    ) + (from + end) / 2.0;
+    let angle = |x: f32, y: f32| y.atan2(x);
+    let start_angle = angle((p.x - center_p.x) / rx, (p.y - center_p.y) / ry);
+    let mut sweep_angle = angle((-p.x - center_p.x) / rx, (-p.y - center_p.y) / ry) - start_angle;
+    if sweep && sweep_angle < 0.0 {
+        sweep_angle += 2.0 * PI;
+    } else if !sweep && sweep_angle > 0.0 {
+        sweep_angle -= 2.0 * PI;
+    }
+    let steps = curve_steps(rx.max(ry) * sweep_angle.abs() * scale);
+    // the last point is `end` itself so the arc ends exactly where the next command starts
+    points.extend((1..steps).map(|step| {
+        let (sin_t, cos_t) = (start_angle + sweep_angle * step as f32 / steps as f32).sin_cos();
+        let (x, y) = (rx * cos_t, ry * sin_t);
+        Vector2::new(cos * x - sin * y, sin * x + cos * y) + center
+    }));
+    points.push(end);
+}
+/// Outline of an ellipse around `center`, with enough points that no edge strays further
+/// than `TOLERANCE` from the ellipse when stretched by `scale`
+fn ellipse_points(center: Vector2<f32>, radii: Vector2<f32>, scale: f32) -> Vec<Vector2<f32>> {
+    let (rx, ry) = (radii.x.abs(), radii.y.abs());
+    let radius = rx.max(ry) * scale;
+    let steps = if radius > TOLERANCE && radius.is_finite() {
+        let step = 2.0 * (1.0 - TOLERANCE / radius).acos();
+        (2.0 * PI / step).ceil().clamp(8.0, 1024.0) as usize
+    } else {
+        8
+    };
+    (0..steps)
+        .map(|step| {
+            let (sin, cos) = (2.0 * PI * step as f32 / steps as f32).sin_cos();
+            center + Vector2::new(rx * cos, ry * sin)
+        })
+        .collect()
+}
+/// Outline of a rectangle with its top left corner at `position`, with the corners split finely
+/// enough for `scale`
+fn rounded_rectangle(
+    position: Vector2<f32>,
+    rectangle: &Rectangle,
+    scale: f32,
+) -> Vec<Vector2<f32>> {
+    let far = position + rectangle.size;
+    let (left, right) = (position.x.min(far.x), position.x.max(far.x));
+    let (top, bottom) = (position.y.min(far.y), position.y.max(far.y));
+    let limit = (right - left).min(bottom - top) / 2.0;
+    let radius = rectangle.corner_radius.max(0.0).min(limit);
+    if !positive(radius) {
+        return vec![
+            Vector2::new(left, top),
+            Vector2::new(right, top),
+            Vector2::new(right, bottom),
+            Vector2::new(left, bottom),
+        ];
+    }
+    let steps = curve_steps(radius * PI / 2.0 * scale).max(2);
+    // centers of the corners clockwise from the top right, with the angle their arc starts at
+    let corners = [
+        (Vector2::new(right - radius, top + radius), -PI / 2.0),
+        (Vector2::new(right - radius, bottom - radius), 0.0),
+        (Vector2::new(left + radius, bottom - radius), PI / 2.0),
+        (Vector2::new(left + radius, top + radius), PI),
+    ];
+    let mut points = vec![];
+    for (center, start) in corners.iter() {
+        points.extend((0..=steps).map(|step| {
+            let (sin, cos) = (start + PI / 2.0 * step as f32 / steps as f32).sin_cos();
+            center + Vector2::new(cos, sin) * radius
+        }));
+    }
+    points
+}
+/// Consecutive pairs of points, with the last point paired with the first if `closed`
+fn segments(
+    points: &[Vector2<f32>],
+    closed: bool,
+) -> impl Iterator<Item = (Vector2<f32>, Vector2<f32>)> + '_ {
+    let wrap = if closed && points.len() > 1 {
+        Some((points[points.len() - 1], points[0]))
+    } else {
+        None
+    };
+    points.windows(2).map(|pair| (pair[0], pair[1])).chain(wrap)
+}
+/// Splits a line through `points` into the dashes of `pattern`, which alternates between
+/// dash and gap lengths. Stops after `MAX_DASHES` dashes.
+fn dash(
+    points: &[Vector2<f32>],
+    closed: bool,
+    pattern: &[f32],
+    offset: f32,
+) -> Vec<Vec<Vector2<f32>>> {
+    // distances are added up in f64, in f32 a long line stops moving past about 1.6e7
+    let period: f64 = pattern.iter().map(|length| *length as f64).sum();
+    let mut index = 0;
+    let mut skipped = (offset as f64).rem_euclid(period);
+    while skipped >= pattern[index] as f64 {
+        skipped -= pattern[index] as f64;
+        index = (index + 1) % pattern.len();
+    }
+    let mut remaining = pattern[index] as f64 - skipped;
+    let mut dashes = vec![];
+    let mut current = vec![];
+    // every dash takes two steps through the pattern, empty entries are steps too
+    let mut steps = 0;
+    if index % 2 == 0 {
+        current.push(points[0]);
+    }
+    for (a, b) in segments(points, closed) {
+        let length = distance(a, b);
+        let mut travelled = 0.0;
+        while length - travelled > remaining {
+            steps += 1;
+            if steps > 2 * MAX_DASHES {
+                return dashes;
+            }
+            travelled += remaining;
+            let point = a + (b - a) * (travelled / length) as f32;
+            if index % 2 == 0 {
+                current.push(point);
+                dashes.push(std::mem::take(&mut current));
+            } else {
+                current = vec![point];
+            }
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index] as f64;
+        }
+        remaining -= length - travelled;
+        if index % 2 == 0 {
+            current.push(b);
+        }
+    }
+    if index % 2 == 0 && current.len() > 1 {
+        dashes.push(current);
+    }
+    dashes
+}
+/// Distance between `a` and `b`, in f64 so the lengths of long lines add up without drifting
+fn distance(a: Vector2<f32>, b: Vector2<f32>) -> f64 {
+    (b.x as f64 - a.x as f64).hypot(b.y as f64 - a.y as f64)
+}
+/// Whether `value` is a number above 0 that is not infinite
+fn positive(value: f32) -> bool {
+    value.is_finite() && value > 0.0
+}
+/// `direction` turned a quarter turn clockwise
+fn perpendicular(direction: Vector2<f32>) -> Vector2<f32> {
+    Vector2::new(-direction.y, direction.x)
+}
+/// Area inside of `polygon`, positive if it goes clockwise on the screen
+fn signed_area(polygon: &[Vector2<f32>]) -> f32 {
+    segments(polygon, true)
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum::<f32>()
+        / 2.0
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colormap, Fit, Path, ShapeStyle, ViewBox};
+    /// Alpha of the pixel at `x`,`y`
+    fn alpha(image: &RgbaImage, x: u32, y: u32) -> u8 {
+        image.get_pixel(x, y)[3]
+    }
+    #[test]
+    fn oversized() {
+        // scaled down instead of allocating a picture of the full size
+        let figure = Figure::new(1_000_000, 2_000).polyline(
+            Color::WHITE,
+            2_000.0,
+            vec![
+                Vector2::new(0.0, 1_000.0),
+                Vector2::new(1_000_000.0, 1_000.0),
+            ],
+        );
+        let image = figure.rasterize();
+        assert_eq!(image.dimensions(), (Figure::MAX_SIZE, 8));
+        // the line is scaled along with the figure and still fills its height
+        assert!(
+            alpha(&image, Figure::MAX_SIZE / 2, 0) > 0
+                && alpha(&image, Figure::MAX_SIZE / 2, 7) > 0
+        );
+        assert_eq!(figure.size_for(800), Vector2::new(Figure::MAX_SIZE, 8));
+        assert_eq!(
+            Figure::new(3, u32::MAX)
+                .rasterize_at(Vector2::new(3, u32::MAX))
+                .dimensions(),
+            (1, Figure::MAX_SIZE)
+        );
+    }
+    #[test]
+    fn image_clipped() {
+        let picture = RgbaImage::from_pixel(3, 3, Rgba([0, 0, 255, 255]));
+        let image = Figure::new(4, 4)
+            .image_at(Vector2::new(-1, 2), picture)
+            .rasterize();
+        assert_eq!(image.dimensions(), (4, 4));
+        assert_eq!(image.get_pixel(1, 3), &Rgba([0, 0, 255, 255]));
+        assert_eq!(alpha(&image, 2, 3), 0);
+        assert_eq!(alpha(&image, 0, 1), 0);
+    }
+    #[test]
+    fn thick_line() {
+        let line = vec![Vector2::new(1.0, 5.0), Vector2::new(9.0, 5.0)];
+        let image = Figure::new(10, 10)
+            .polyline(Color::RED, 2.0, line)
+            .rasterize();
+        assert_eq!(image.get_pixel(5, 4), &Rgba([255, 0, 0, 255]));
+        assert_eq!(alpha(&image, 5, 5), 255);
+        assert_eq!(alpha(&image, 5, 6), 0);
+        assert_eq!(alpha(&image, 0, 5), 0);
+        // a line between pixel rows covers half of each
+        let line = vec![Vector2::new(0.0, 5.0), Vector2::new(10.0, 5.0)];
+        let image = Figure::new(10, 10)
+            .polyline(Color::RED, 1.0, line)
+            .rasterize();
+        assert!((alpha(&image, 5, 4) as i32 - 128).abs() <= 1);
+    }
+    #[test]
+    fn shapes() {
+        let style = ShapeStyle {
+            fill: Color::RED,
+            stroke: Color::BLUE,
+            stroke_width: 2.0,
+        };
+        let image = Figure::new(40, 20)
+            .rectangle(Vector2::new(2, 2), Vector2::new(16.0, 16.0), 0.0, style)
+            .ellipse(
+                Vector2::new(30, 10),
+                Vector2::new(8.0, 8.0),
+                ShapeStyle::filled(Color::RED),
+            )
+            .rasterize();
+        assert_eq!(image.get_pixel(10, 10), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(10, 2), &Rgba([0, 0, 255, 255]));
+        assert_eq!(alpha(&image, 0, 0), 0);
+        assert_eq!(alpha(&image, 30, 10), 255);
+        // the edge of the circle is partly covered
+        let edge = alpha(&image, 37, 12);
+        assert!(edge > 0 && edge < 255);
+        assert_eq!(alpha(&image, 37, 17), 0);
+    }
+    #[test]
+    fn groups() {
+        let square = |color| {
+            Figure::new(0, 0).rectangle(
+                Vector2::new(0, 0),
+                Vector2::new(4.0, 4.0),
+                0.0,
+                ShapeStyle::filled(color),
+            )
+        };
+        let image = Figure::new(20, 20)
+            // turned a quarter clockwise around its corner at 10,0
+            .group(
+                Vector2::new(10, 0),
+                Group::from_figure(square(Color::RED)).rotate(90.0),
+            )
+            // drawn under the red square even though it comes after it
+            .group(
+                Vector2::new(7, 0),
+                Group::from_figure(square(Color::BLUE)).z_index(-1),
+            )
+            .group(
+                Vector2::new(0, 10),
+                Group::from_figure(square(Color::GREEN))
+                    .scale(Vector2::new(2.0, 2.0))
+                    .clip(Vector2::new(0.0, 0.0), Vector2::new(2.0, 4.0)),
+            )
+            .group(
+                Vector2::new(12, 10),
+                Group::from_figure(square(Color::RED))
+                    .element(square(Color::RED).contents()[0].clone())
+                    .opacity(0.5),
+            )
+            .group(
+                Vector2::new(12, 16),
+                Group::new(vec![])
+                    .element(FigureContents::new(
+                        FigureContentsData::Image(RgbaImage::from_pixel(
+                            1,
+                            1,
+                            Rgba([0, 0, 255, 255]),
+                        )),
+                        Vector2::new(0, 0),
+                    ))
+                    .scale(Vector2::new(2.0, 2.0)),
+            )
+            .rasterize();
+        assert_eq!(image.get_pixel(7, 1), &Rgba([255, 0, 0, 255]));
+        assert_eq!(alpha(&image, 11, 1), 0);
+        // the blue square sticks out from under the red one
+        assert_eq!(image.get_pixel(6, 1), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(10, 3), &Rgba([0, 0, 255, 255]));
+        // clipped to the left half in the group's coordinates, which are scaled twice
+        assert_eq!(image.get_pixel(3, 17), &Rgba([0, 255, 0, 255]));
+        assert_eq!(alpha(&image, 5, 17), 0);
+        // the overlapping squares fade as one
+        assert!((alpha(&image, 13, 11) as i32 - 128).abs() <= 1);
+        assert_eq!(image.get_pixel(13, 17), &Rgba([0, 0, 255, 255]));
+        assert_eq!(alpha(&image, 14, 17), 0);
+    }
+    #[test]
+    fn view_box() {
+        let figure = Figure::new(10, 10)
+            .with_view_box(ViewBox {
+                origin: Vector2::new(0.0, 0.0),
+                size: Vector2::new(1.0, 1.0),
+                fit: Fit::FillWidth,
+            })
+            .rectangle(
+                Vector2::new(0, 0),
+                Vector2::new(0.5, 0.5),
+                0.0,
+                ShapeStyle::filled(Color::RED),
+            );
+        let image = figure.rasterize();
+        assert_eq!(alpha(&image, 4, 4), 255);
+        assert_eq!(alpha(&image, 5, 5), 0);
+        let image = figure.rasterize_at(figure.size_for(40));
+        assert_eq!(image.dimensions(), (40, 40));
+        assert_eq!(alpha(&image, 19, 19), 255);
+        assert_eq!(alpha(&image, 20, 20), 0);
+    }
+    #[test]
+    fn heatmap() {
+        let heatmap = Heatmap::new(
+            Vector2::new(2, 2),
+            vec![0.0, 1.0, f32::NAN, 0.5],
+            Colormap::Grayscale,
+        )
+        .size(Vector2::new(8.0, 4.0));
+        let image = Figure::new(10, 10)
+            .heatmap(Vector2::new(1, 1), heatmap)
+            .rasterize();
+        assert_eq!(image.get_pixel(1, 1), &Rgba([0, 0, 0, 255]));
+        assert_eq!(image.get_pixel(8, 2), &Rgba([255, 255, 255, 255]));
+        assert_eq!(alpha(&image, 2, 4), 0);
+        assert_eq!(image.get_pixel(5, 4), &Rgba([128, 128, 128, 255]));
+        assert_eq!(alpha(&image, 9, 1), 0);
+    }
+    #[test]
+    fn dashed_arc() {
+        let path = Path::new(ShapeStyle::stroked(Color::RED, 2.0))
+            .move_to(Vector2::new(0.0, 10.0))
+            .arc_to(
+                Vector2::new(10.0, 10.0),
+                0.0,
+                false,
+                true,
+                Vector2::new(20.0, 10.0),
+            );
+        let image = Figure::new(20, 20).path(path).rasterize();
+        // clockwise from the left goes over the top
+        assert!(alpha(&image, 10, 0) > 200);
+        assert_eq!(alpha(&image, 10, 10), 0);
+        assert_eq!(alpha(&image, 10, 19), 0);
+        let dashed = Path::new(ShapeStyle::stroked(Color::RED, 2.0))
+            .move_to(Vector2::new(0.0, 5.0))
+            .line_to(Vector2::new(20.0, 5.0))
+            .dash(vec![4.0], 2.0);
+        let image = Figure::new(20, 10).path(dashed).rasterize();
+        let row: Vec<bool> = (0..20).map(|x| alpha(&image, x, 5) == 255).collect();
+        assert_eq!(
+            row[..8],
+            [true, true, false, false, false, false, true, true]
+        );
+    }
+    #[test]
+    fn long_dashed_line() {
+        // too many dashes to draw one by one so the line is drawn solid
+        let path = Path::new(ShapeStyle::stroked(Color::RED, 2.0))
+            .move_to(Vector2::new(0.0, 5.0))
+            .line_to(Vector2::new(1e9, 5.0))
+            .dash(vec![1.0; 10_000], 0.0);
+        let image = Figure::new(20, 10).path(path).rasterize();
+        assert!((0..20).all(|x| alpha(&image, x, 5) == 255));
+        // past 1.6e7 an f32 distance stops growing, the dashes still move along and stop at the
+        // limit
+        let points = [Vector2::new(0.0, 0.0), Vector2::new(1e9, 0.0)];
+        let dashes = dash(&points, false, &[1.0, 1.0], 0.0);
+        assert_eq!(dashes.len(), MAX_DASHES);
+        assert_eq!(dashes[1], [Vector2::new(2.0, 0.0), Vector2::new(3.0, 0.0)]);
+    }
+}
@@ -0,0 +1,213 @@
+use super::{Deserializer, ParseError, ParsedAST};
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read};
+/// Outcome of `ReadDeserializer::poll_next`
+#[derive(Debug, PartialEq, Clone)]
+pub enum ReadStatus {
+    /// A complete packet was parsed
+    Ready(ParsedAST),
+    /// The reader has no more data right now. Any partial packet stays buffered until the rest
+    /// of it arrives.
+    WouldBlock,
+    /// The stream has ended
+    Finished,
+}
+/// Deserializer that reads its own input, made by `Deserializer::from_reader`.
+///
+/// Iterating yields every packet in the stream, blocking on the reader when it needs more data.
+/// A broken packet is yielded as an error and then skipped so iteration can keep going. For
+/// readers in non-blocking mode use `poll_next`, which returns `ReadStatus::WouldBlock` instead
+/// of waiting.
+pub struct ReadDeserializer<R: Read> {
+    reader: R,
+    deserializer: Deserializer,
+    parsed: VecDeque<ParsedAST>,
+    chunk: Vec<u8>,
+    /// Set after a broken packet is skipped, the bytes behind it still need to be parsed
+    reparse: bool,
+    finished: bool,
+}
+impl<R: Read> ReadDeserializer<R> {
+    /// Size of a single read from the reader
+    const CHUNK_SIZE: usize = 1 << 16;
+    pub(crate) fn new(deserializer: Deserializer, reader: R) -> Self {
+        Self {
+            reader,
+            deserializer,
+            parsed: VecDeque::new(),
+            chunk: vec![],
+            reparse: false,
+            finished: false,
+        }
+    }
+    /// Gets the next packet without waiting on the reader. Returns `ReadStatus::WouldBlock` once
+    /// the reader reports `ErrorKind::WouldBlock` before a packet is complete.
+    pub fn poll_next(&mut self) -> Result<ReadStatus, ParseError> {
+        loop {
+            if let Some(node) = self.parsed.pop_front() {
+                return Ok(ReadStatus::Ready(node));
+            }
+            if self.reparse {
+                self.reparse = false;
+                self.parse_chunk()?;
+                continue;
+            }
+            if self.finished {
+                return Ok(ReadStatus::Finished);
+            }
+            self.chunk.resize(Self::CHUNK_SIZE, 0);
+            let read = self.reader.read(&mut self.chunk);
+            match read {
+                Ok(0) => {
+                    self.chunk.clear();
+                    self.finished = true;
+                    let rest = self.deserializer.finish()?;
+                    self.parsed.extend(rest);
+                }
+                Ok(length) => {
+                    self.chunk.truncate(length);
+                    self.parse_chunk()?;
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => (),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    self.chunk.clear();
+                    return Ok(ReadStatus::WouldBlock);
+                }
+                Err(error) => {
+                    self.chunk.clear();
+                    self.finished = true;
+                    return Err(error.into());
+                }
+            }
+        }
+    }
+    /// Gives back the reader, dropping any bytes that have not been parsed yet
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+    /// Parses the bytes in `chunk`, skipping any broken packets after reporting them
+    fn parse_chunk(&mut self) -> Result<(), ParseError> {
+        loop {
+            match self.deserializer.parse(&mut self.chunk) {
+                Ok(parsed) => {
+                    let done = parsed.is_empty();
+                    self.parsed.extend(parsed);
+                    if done {
+                        return Ok(());
+                    }
+                }
+                Err(error) => {
+                    self.deserializer.skip_packet();
+                    self.reparse = true;
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+impl<R: Read> Iterator for ReadDeserializer<R> {
+    type Item = Result<ParsedAST, ParseError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.poll_next() {
+            Ok(ReadStatus::Ready(node)) => Some(Ok(node)),
+            Ok(ReadStatus::WouldBlock) => {
+                Some(Err(std::io::Error::from(ErrorKind::WouldBlock).into()))
+            }
+            Ok(ReadStatus::Finished) => None,
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParseErrorKind, PacketHeader};
+    use std::io::Cursor;
+    #[test]
+    fn read_all_packets() {
+        #[rustfmt::skip]
+        let stream = Cursor::new(vec![0, 0, 0, 0,
+            1, 0, 0, 0,
+            b'a',
+            0, 0, 0, 0,
+            1, 0, 0, 0,
+            b'b']);
+        let parsed: Result<Vec<_>, _> = Deserializer::from_reader(stream).collect();
+        assert_eq!(
+            parsed,
+            Ok(vec![
+                ParsedAST::String("a".to_string()),
+                ParsedAST::String("b".to_string())
+            ])
+        );
+    }
+    #[test]
+    fn continue_after_error() {
+        #[rustfmt::skip]
+        let stream = Cursor::new(vec![0x20, 0, 0, 0,
+            1, 0, 0, 0,
+            0,
+            0, 0, 0, 0,
+            1, 0, 0, 0,
+            b'b']);
+        let parsed: Vec<_> = Deserializer::from_reader(stream).collect();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(
+            parsed[0].clone().err().unwrap().kind,
+            ParseErrorKind::InvalidDatatype(0x20)
+        );
+        assert_eq!(parsed[1], Ok(ParsedAST::String("b".to_string())));
+    }
+    #[test]
+    fn truncated_stream() {
+        let stream = Cursor::new(vec![0, 0, 0, 0, 3, 0, 0, 0, b'a']);
+        let parsed: Vec<_> = Deserializer::from_reader(stream).collect();
+        let error = parsed[0].clone().err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::UnexpectedEof);
+        assert_eq!(
+            error.packet,
+            Some(PacketHeader {
+                data_type: 0,
+                length: 3
+            })
+        );
+        assert_eq!(parsed.len(), 1);
+    }
+    /// Reader that hands out one byte per call and would block between them
+    struct Trickle {
+        data: Vec<u8>,
+        position: usize,
+        blocked: bool,
+    }
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.blocked = !self.blocked;
+            if self.blocked {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            if self.position >= self.data.len() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.data[self.position];
+            self.position += 1;
+            Ok(1)
+        }
+    }
+    #[test]
+    fn non_blocking() {
+        let mut reader = Deserializer::from_reader(Trickle {
+            data: vec![0, 0, 0, 0, 1, 0, 0, 0, b'a'],
+            position: 0,
+            blocked: false,
+        });
+        for _ in 0..9 {
+            assert_eq!(reader.poll_next(), Ok(ReadStatus::WouldBlock));
+        }
+        assert_eq!(
+            reader.poll_next(),
+            Ok(ReadStatus::Ready(ParsedAST::String("a".to_string())))
+        );
+        assert_eq!(reader.poll_next(), Ok(ReadStatus::WouldBlock));
+        assert_eq!(reader.poll_next(), Ok(ReadStatus::Finished));
+    }
+}
@@ -1,102 +1,809 @@
-use super::{Figure, FigureContentsData, Line};
+use super::{
+    picture, Capabilities, Cell, Chart, Color, Datatypes, Figure, FigureContents, FigureContentsData,
+    Group, Heatmap, Hello, ImageEncoding, Line, Palette, ParsedAST, Path, PathCommand, Progress, Series,
+    ShapeStyle, StyledText, Table, MAGIC,
+};
+use image::RgbaImage;
 use nalgebra::Vector2;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Write};
+const HEADER_SIZE: usize = 8;
+const HELLO_SIZE: usize = 12;
+const CAPABILITIES_SIZE: usize = 20;
+/// Dimensions and flags of a figure
+const FIGURE_HEADER_SIZE: usize = 12;
+/// Origin, size and fit of a figure's view box
+const VIEW_BOX_SIZE: usize = 20;
+/// Flag set when a figure has a view box
+const FIGURE_VIEW_BOX: u32 = 0x1;
+const ELEMENT_HEADER_SIZE: usize = 16;
+/// Dimensions and encoding of a picture
+const PICTURE_HEADER_SIZE: usize = 12;
+const LINE_HEADER_SIZE: usize = 8;
+const SPAN_HEADER_SIZE: usize = 16;
+const TABLE_HEADER_SIZE: usize = 8;
+/// Type and alignment of a column, its name follows
+const COLUMN_HEADER_SIZE: usize = 8;
+const STRING_CELL_HEADER_SIZE: usize = 4;
+const NUMBER_CELL_SIZE: usize = 8;
+const ID_SIZE: usize = 4;
+const PROGRESS_HEADER_SIZE: usize = 24;
+const STRING_LENGTH_SIZE: usize = 4;
+const POINT_SIZE: usize = 8;
+const SHAPE_STYLE_SIZE: usize = 12;
+const RECTANGLE_SIZE: usize = 12;
+const ELLIPSE_SIZE: usize = 8;
+const LABEL_HEADER_SIZE: usize = 20;
+/// Join, cap, dash offset and dash count of a path
+const PATH_HEADER_SIZE: usize = 16;
+const PATH_COMMAND_TAG_SIZE: usize = 4;
+const COLOR_SIZE: usize = 4;
+/// Transform, opacity, z index, flags and clip rectangle of a group
+const GROUP_HEADER_SIZE: usize = 52;
+/// Flag set when a group has a clip rectangle
+const GROUP_CLIP: u32 = 0x1;
+/// Grid, size, range and colormap of a heatmap
+const HEATMAP_HEADER_SIZE: usize = 28;
+const HEATMAP_VALUE_SIZE: usize = 4;
+/// Kind, flags, axis ranges and series count of a chart
+const CHART_HEADER_SIZE: usize = 44;
+/// Flags, color and point count of a chart series
+const SERIES_HEADER_SIZE: usize = 12;
+/// An x and a y f64
+const CHART_POINT_SIZE: usize = 16;
+/// Encodes any `ParsedAST` as a packet. Parsing the result with `Deserializer` gives back `ast`.
+pub fn serialize(ast: &ParsedAST) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(packet_size(ast));
+    Serializer::new(&mut bytes)
+        .write(ast)
+        .expect("packets are limited to u32::MAX bytes");
+    bytes
+}
+/// Builds the `Hello` packet an app sends before anything else
+pub fn build_hello() -> Vec<u8> {
+    serialize(&ParsedAST::Hello(Hello::new()))
+}
 #[allow(dead_code)]
 pub fn build_text(text: String) -> Vec<u8> {
-    return build_packet(0, &mut text.as_bytes().to_vec());
+    let mut bytes = Vec::with_capacity(text_size(&text));
+    Serializer::new(&mut bytes)
+        .write_text(&text)
+        .expect("packets are limited to u32::MAX bytes");
+    bytes
 }
 #[allow(dead_code)]
 pub fn build_figure(figure: Figure) -> Vec<u8> {
-    let x_dim = figure.dimensions.x.to_le_bytes();
-    let y_dim = figure.dimensions.y.to_le_bytes();
-    let mut figure_contents = vec![
-        x_dim[0], x_dim[1], x_dim[2], x_dim[3], y_dim[0], y_dim[1], y_dim[2], y_dim[3],
-    ];
-    for content in figure.contents.iter() {
-        figure_contents.append(&mut build_figure_content(&content.data, &content.position));
-    }
-    return build_packet(0x1, &mut figure_contents);
-}
-fn build_figure_content(content: &FigureContentsData, position: &Vector2<i32>) -> Vec<u8> {
-    let (element_type, mut payload) = match content {
-        FigureContentsData::Image(image) => (0u32, image.as_raw().clone()),
-        FigureContentsData::Line(line) => (1u32, build_line(line)),
-    };
-    let x_chord = position.x.to_le_bytes();
-    let y_chord = position.y.to_le_bytes();
-    let elm_type_bytes = element_type.to_le_bytes();
-    let length_bytes = (payload.len() as u32).to_le_bytes();
-    let mut data = vec![
-        elm_type_bytes[0],
-        elm_type_bytes[1],
-        elm_type_bytes[2],
-        elm_type_bytes[3],
-        length_bytes[0],
-        length_bytes[1],
-        length_bytes[2],
-        length_bytes[3],
-        x_chord[0],
-        x_chord[1],
-        x_chord[2],
-        x_chord[3],
-        y_chord[0],
-        y_chord[1],
-        y_chord[2],
-        y_chord[3],
-    ];
-    data.append(&mut payload);
-    return data;
-}
-fn build_line(line: &Line) -> Vec<u8> {
-    let thickness_bytes = line.thickness.to_le_bytes();
-    let color_bytes = line.color.to_le_bytes();
-    let mut data = vec![
-        color_bytes[0],
-        color_bytes[1],
-        color_bytes[2],
-        color_bytes[3],
-        thickness_bytes[0],
-        thickness_bytes[1],
-        thickness_bytes[2],
-        thickness_bytes[3],
-    ];
-    for segment in line.segments.iter() {
-        let x_bytes = segment.x.to_le_bytes();
-        let y_bytes = segment.y.to_le_bytes();
-        data.append(&mut x_bytes.to_vec());
-        data.append(&mut y_bytes.to_vec());
-    }
-    return data;
-}
-fn build_packet(data_type: u32, data: &mut Vec<u8>) -> Vec<u8> {
-    let type_bytes = data_type.to_le_bytes();
-    let length_bytes = (data.len() as u32).to_le_bytes();
-    let mut bytes = vec![
-        type_bytes[0],
-        type_bytes[1],
-        type_bytes[2],
-        type_bytes[3],
-        length_bytes[0],
-        length_bytes[1],
-        length_bytes[2],
-        length_bytes[3],
-    ];
-    bytes.append(data);
-    return bytes;
+    let mut bytes = Vec::with_capacity(figure_size(&figure));
+    Serializer::new(&mut bytes)
+        .write_figure(&figure)
+        .expect("packets are limited to u32::MAX bytes");
+    bytes
+}
+/// Builds a progress packet. To move an existing bar instead of adding a new one wrap it in a
+/// `ParsedAST::Update` and use `serialize`.
+pub fn build_progress(progress: &Progress) -> Vec<u8> {
+    serialize(&ParsedAST::Progress(progress.clone()))
+}
+pub fn build_chart(chart: &Chart) -> Vec<u8> {
+    serialize(&ParsedAST::Chart(chart.clone()))
+}
+/// Number of bytes in the packet for `ast`
+pub fn packet_size(ast: &ParsedAST) -> usize {
+    match ast {
+        ParsedAST::String(text) => text_size(text),
+        ParsedAST::Figure(figure) => figure_size(figure),
+        ParsedAST::Hello(_) => HEADER_SIZE + HELLO_SIZE,
+        ParsedAST::CapabilityQuery => HEADER_SIZE,
+        ParsedAST::Capabilities(_) => HEADER_SIZE + CAPABILITIES_SIZE,
+        ParsedAST::StyledText(text) => HEADER_SIZE + styled_text_payload_size(text),
+        ParsedAST::Table(table) => HEADER_SIZE + table_payload_size(table),
+        ParsedAST::Update(_, ast) => HEADER_SIZE + ID_SIZE + packet_size(ast),
+        ParsedAST::Delete(_) => HEADER_SIZE + ID_SIZE,
+        ParsedAST::Progress(progress) => HEADER_SIZE + progress_payload_size(progress),
+        ParsedAST::Chart(chart) => HEADER_SIZE + chart_payload_size(chart),
+        ParsedAST::Palette(palette) => HEADER_SIZE + COLOR_SIZE * palette.colors.len(),
+    }
+}
+/// Number of bytes in the packet for `text`
+pub fn text_size(text: &str) -> usize {
+    HEADER_SIZE + text.len()
+}
+/// Number of bytes in the packet for `figure`
+pub fn figure_size(figure: &Figure) -> usize {
+    HEADER_SIZE + figure_payload_size(figure)
+}
+/// Encodes `ast` into the start of `buffer` and returns the number of bytes used. Nothing is
+/// written if the packet does not fit.
+pub fn encode_into(ast: &ParsedAST, buffer: &mut [u8]) -> Result<usize, EncodeError> {
+    let size = packet_size(ast);
+    let slot = fit(buffer, size)?;
+    Serializer::new(slot).write(ast)?;
+    Ok(size)
+}
+/// Encodes a text packet into the start of `buffer` and returns the number of bytes used.
+/// Nothing is written if the packet does not fit.
+pub fn encode_text_into(text: &str, buffer: &mut [u8]) -> Result<usize, EncodeError> {
+    let size = text_size(text);
+    let slot = fit(buffer, size)?;
+    Serializer::new(slot).write_text(text)?;
+    Ok(size)
+}
+/// Encodes a figure packet into the start of `buffer` and returns the number of bytes used.
+/// Nothing is written if the packet does not fit.
+pub fn encode_figure_into(figure: &Figure, buffer: &mut [u8]) -> Result<usize, EncodeError> {
+    let size = figure_size(figure);
+    let slot = fit(buffer, size)?;
+    Serializer::new(slot).write_figure(figure)?;
+    Ok(size)
+}
+/// Gets the first `size` bytes of `buffer`
+fn fit(buffer: &mut [u8], size: usize) -> Result<&mut [u8], EncodeError> {
+    let available = buffer.len();
+    buffer
+        .get_mut(..size)
+        .ok_or(EncodeError::BufferTooSmall {
+            needed: size,
+            available,
+        })
+}
+/// Error from encoding into a fixed size buffer
+#[derive(Debug, PartialEq, Clone)]
+pub enum EncodeError {
+    BufferTooSmall { needed: usize, available: usize },
+    /// The packet's payload is longer than `u32::MAX` bytes
+    PacketTooLarge,
+}
+impl From<io::Error> for EncodeError {
+    fn from(_: io::Error) -> Self {
+        // writing into a slice that was checked to fit can only fail on an oversized packet
+        EncodeError::PacketTooLarge
+    }
+}
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::BufferTooSmall { needed, available } => write!(
+                f,
+                "packet needs {} bytes but the buffer only has {}",
+                needed, available
+            ),
+            EncodeError::PacketTooLarge => write!(f, "packet is larger than u32::MAX bytes"),
+        }
+    }
+}
+impl std::error::Error for EncodeError {}
+/// Writes packets directly into a `Write` sink.
+///
+/// Headers are computed up front from the sizes of the contents, so payloads such as image data
+/// are written straight from the caller's memory without being copied into an intermediate
+/// buffer. The output is byte for byte the same as `build_text` and `build_figure`. Each field
+/// is a separate write, so unbuffered sinks should be wrapped in a `std::io::BufWriter`.
+///
+/// Pictures are written as raw RGBA unless `image_encoding` picks another encoding. Compressed
+/// pictures are encoded before their packet's header is written, and the size functions such as
+/// `packet_size` assume raw RGBA.
+pub struct Serializer<W: Write> {
+    writer: W,
+    /// `None` picks the smallest lossless encoding for each picture
+    image_encoding: Option<ImageEncoding>,
+}
+/// Encoding and encoded pixels of a picture
+type EncodedPicture<'a> = (ImageEncoding, Cow<'a, [u8]>);
+/// A figure element with its pictures encoded, which its size depends on
+struct EncodedElement<'a> {
+    content: &'a FigureContents,
+    /// Size of the element without its header
+    size: usize,
+    /// Encoded pixels if the element is a picture
+    picture: Option<EncodedPicture<'a>>,
+    /// Children if the element is a group
+    children: Vec<EncodedElement<'a>>,
+}
+impl<W: Write> Serializer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            image_encoding: Some(ImageEncoding::Rgba),
+        }
+    }
+    /// Encodes every picture with `encoding`, or with whichever lossless encoding is smallest for
+    /// the picture if it is `None`. `ImageEncoding::Gray` and `ImageEncoding::Rgb` drop alpha
+    /// and color from pictures that have them.
+    pub fn image_encoding(mut self, encoding: Option<ImageEncoding>) -> Self {
+        self.image_encoding = encoding;
+        self
+    }
+    pub fn write(&mut self, ast: &ParsedAST) -> io::Result<()> {
+        match ast {
+            ParsedAST::String(text) => self.write_text(text),
+            ParsedAST::Figure(figure) => self.write_figure(figure),
+            ParsedAST::Hello(hello) => self.write_hello(hello),
+            ParsedAST::CapabilityQuery => self.write_header(Datatypes::CapabilityQuery as u32, 0),
+            ParsedAST::Capabilities(capabilities) => self.write_capabilities(capabilities),
+            ParsedAST::StyledText(text) => self.write_styled_text(text),
+            ParsedAST::Table(table) => self.write_table(table),
+            ParsedAST::Update(id, ast) => self.write_update(*id, ast),
+            ParsedAST::Delete(id) => self.write_delete(*id),
+            ParsedAST::Progress(progress) => self.write_progress(progress),
+            ParsedAST::Chart(chart) => self.write_chart(chart),
+            ParsedAST::Palette(palette) => self.write_palette(palette),
+        }
+    }
+    pub fn write_palette(&mut self, palette: &Palette) -> io::Result<()> {
+        self.write_header(
+            Datatypes::Palette as u32,
+            COLOR_SIZE * palette.colors.len(),
+        )?;
+        for color in palette.colors.iter() {
+            self.write_color(*color)?;
+        }
+        Ok(())
+    }
+    pub fn write_progress(&mut self, progress: &Progress) -> io::Result<()> {
+        self.write_header(Datatypes::Progress as u32, progress_payload_size(progress))?;
+        self.writer.write_all(&progress.flags().to_le_bytes())?;
+        self.writer
+            .write_all(&progress.eta.unwrap_or(0).to_le_bytes())?;
+        self.writer.write_all(&progress.current.to_le_bytes())?;
+        self.writer
+            .write_all(&progress.total.unwrap_or(0).to_le_bytes())?;
+        self.writer.write_all(&length_field(progress.label.len())?)?;
+        self.writer.write_all(progress.label.as_bytes())?;
+        self.writer.write_all(&length_field(progress.units.len())?)?;
+        self.writer.write_all(progress.units.as_bytes())
+    }
+    pub fn write_chart(&mut self, chart: &Chart) -> io::Result<()> {
+        self.write_header(Datatypes::Chart as u32, chart_payload_size(chart))?;
+        self.writer.write_all(&(chart.kind as u32).to_le_bytes())?;
+        self.writer.write_all(&chart.flags().to_le_bytes())?;
+        for axis in [&chart.x_axis, &chart.y_axis].iter() {
+            let (low, high) = axis.range.unwrap_or((0.0, 0.0));
+            self.writer.write_all(&low.to_le_bytes())?;
+            self.writer.write_all(&high.to_le_bytes())?;
+        }
+        self.writer.write_all(&length_field(chart.series.len())?)?;
+        for text in [&chart.title, &chart.x_axis.label, &chart.y_axis.label].iter() {
+            self.writer.write_all(&length_field(text.len())?)?;
+            self.writer.write_all(text.as_bytes())?;
+        }
+        for series in chart.series.iter() {
+            let flags = if series.color.is_some() { Series::HAS_COLOR } else { 0 };
+            self.writer.write_all(&flags.to_le_bytes())?;
+            self.write_color(series.color.unwrap_or(Color::TRANSPARENT))?;
+            self.writer.write_all(&length_field(series.points.len())?)?;
+            self.writer.write_all(&length_field(series.name.len())?)?;
+            self.writer.write_all(series.name.as_bytes())?;
+            for point in series.points.iter() {
+                self.writer.write_all(&point.x.to_le_bytes())?;
+                self.writer.write_all(&point.y.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+    /// Writes an update replacing the output with `id`. `ast` has to be output, the deserializer
+    /// rejects updates holding control packets.
+    pub fn write_update(&mut self, id: u32, ast: &ParsedAST) -> io::Result<()> {
+        if let ParsedAST::Figure(_) = ast {
+            if self.image_encoding != Some(ImageEncoding::Rgba) {
+                // the size of compressed pictures is only known once they are encoded
+                let mut figure = Serializer::new(vec![]).image_encoding(self.image_encoding);
+                figure.write(ast)?;
+                let figure = figure.into_inner();
+                self.write_header(Datatypes::Update as u32, ID_SIZE + figure.len())?;
+                self.writer.write_all(&id.to_le_bytes())?;
+                return self.writer.write_all(&figure);
+            }
+        }
+        self.write_header(Datatypes::Update as u32, ID_SIZE + packet_size(ast))?;
+        self.writer.write_all(&id.to_le_bytes())?;
+        self.write(ast)
+    }
+    pub fn write_delete(&mut self, id: u32) -> io::Result<()> {
+        self.write_header(Datatypes::Delete as u32, ID_SIZE)?;
+        self.writer.write_all(&id.to_le_bytes())
+    }
+    pub fn write_table(&mut self, table: &Table) -> io::Result<()> {
+        self.write_header(Datatypes::Table as u32, table_payload_size(table))?;
+        self.writer.write_all(&length_field(table.columns.len())?)?;
+        self.writer.write_all(&length_field(table.rows.len())?)?;
+        for column in table.columns.iter() {
+            self.writer
+                .write_all(&(column.column_type as u32).to_le_bytes())?;
+            self.writer.write_all(&(column.alignment as u32).to_le_bytes())?;
+            self.writer.write_all(&length_field(column.name.len())?)?;
+            self.writer.write_all(column.name.as_bytes())?;
+        }
+        for cell in table.rows.iter().flatten() {
+            match cell {
+                Cell::String(string) => {
+                    self.writer.write_all(&length_field(string.len())?)?;
+                    self.writer.write_all(string.as_bytes())
+                }
+                Cell::Integer(value) => self.writer.write_all(&value.to_le_bytes()),
+                Cell::Float(value) => self.writer.write_all(&value.to_le_bytes()),
+                Cell::Bytes(value) => self.writer.write_all(&value.to_le_bytes()),
+                Cell::Date(value) => self.writer.write_all(&value.to_le_bytes()),
+            }?;
+        }
+        Ok(())
+    }
+    pub fn write_styled_text(&mut self, text: &StyledText) -> io::Result<()> {
+        self.write_header(Datatypes::StyledText as u32, styled_text_payload_size(text))?;
+        for span in text.spans.iter() {
+            self.write_color(span.style.foreground)?;
+            self.write_color(span.style.background)?;
+            self.writer.write_all(&span.style.flags().to_le_bytes())?;
+            self.writer.write_all(&length_field(span.text.len())?)?;
+            self.writer.write_all(span.text.as_bytes())?;
+        }
+        Ok(())
+    }
+    pub fn write_hello(&mut self, hello: &Hello) -> io::Result<()> {
+        self.write_header(Datatypes::Hello as u32, HELLO_SIZE)?;
+        self.writer.write_all(&MAGIC)?;
+        self.writer.write_all(&hello.version.to_le_bytes())?;
+        self.writer.write_all(&hello.capabilities.to_le_bytes())
+    }
+    pub fn write_capabilities(&mut self, capabilities: &Capabilities) -> io::Result<()> {
+        self.write_header(Datatypes::Capabilities as u32, CAPABILITIES_SIZE)?;
+        self.writer.write_all(&capabilities.datatypes.to_le_bytes())?;
+        self.writer
+            .write_all(&capabilities.figure_elements.to_le_bytes())?;
+        self.writer
+            .write_all(&capabilities.max_image_size.x.to_le_bytes())?;
+        self.writer
+            .write_all(&capabilities.max_image_size.y.to_le_bytes())?;
+        self.writer.write_all(&capabilities.color_depth.to_le_bytes())
+    }
+    pub fn write_text(&mut self, text: &str) -> io::Result<()> {
+        self.write_header(Datatypes::Text as u32, text.len())?;
+        self.writer.write_all(text.as_bytes())
+    }
+    pub fn write_figure(&mut self, figure: &Figure) -> io::Result<()> {
+        let elements: Vec<EncodedElement> = figure
+            .contents
+            .iter()
+            .map(|content| self.encode_element(content))
+            .collect();
+        let size: usize = elements
+            .iter()
+            .map(|element| ELEMENT_HEADER_SIZE + element.size)
+            .sum();
+        self.write_header(Datatypes::Figure as u32, figure_header_size(figure) + size)?;
+        self.writer.write_all(&figure.dimensions.x.to_le_bytes())?;
+        self.writer.write_all(&figure.dimensions.y.to_le_bytes())?;
+        match &figure.view_box {
+            Some(view_box) => {
+                self.writer.write_all(&FIGURE_VIEW_BOX.to_le_bytes())?;
+                self.write_points(&[view_box.origin, view_box.size])?;
+                self.writer.write_all(&(view_box.fit as u32).to_le_bytes())?;
+            }
+            None => self.writer.write_all(&0u32.to_le_bytes())?,
+        }
+        for element in elements.iter() {
+            self.write_figure_content(element)?;
+        }
+        Ok(())
+    }
+    /// Flushes the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+    fn encode_picture<'a>(&self, image: &'a RgbaImage) -> EncodedPicture<'a> {
+        match self.image_encoding {
+            // PNG has no way to store an image without pixels
+            Some(ImageEncoding::Png) if image.width() == 0 || image.height() == 0 => {
+                (ImageEncoding::Rgba, Cow::Borrowed(image.as_raw()))
+            }
+            Some(encoding) => (encoding, picture::encode(image, encoding)),
+            None => picture::encode_smallest(image),
+        }
+    }
+    /// Encodes the pictures of `content` and works out its size
+    fn encode_element<'a>(&self, content: &'a FigureContents) -> EncodedElement<'a> {
+        let mut element = EncodedElement {
+            content,
+            size: 0,
+            picture: None,
+            children: vec![],
+        };
+        match &content.data {
+            FigureContentsData::Image(image) => {
+                let picture = self.encode_picture(image);
+                element.size = PICTURE_HEADER_SIZE + picture.1.len();
+                element.picture = Some(picture);
+            }
+            FigureContentsData::Group(group) => {
+                element.children = group
+                    .children
+                    .iter()
+                    .map(|child| self.encode_element(child))
+                    .collect();
+                element.size = GROUP_HEADER_SIZE
+                    + element
+                        .children
+                        .iter()
+                        .map(|child| ELEMENT_HEADER_SIZE + child.size)
+                        .sum::<usize>();
+            }
+            data => element.size = element_payload_size(data),
+        }
+        element
+    }
+    fn write_figure_content(&mut self, element: &EncodedElement) -> io::Result<()> {
+        let (content, position) = (&element.content.data, element.content.position);
+        self.writer
+            .write_all(&(content.element_type() as u32).to_le_bytes())?;
+        self.writer.write_all(&length_field(element.size)?)?;
+        self.writer.write_all(&position.x.to_le_bytes())?;
+        self.writer.write_all(&position.y.to_le_bytes())?;
+        match content {
+            FigureContentsData::Image(image) => {
+                let (encoding, pixels) = element
+                    .picture
+                    .as_ref()
+                    .expect("pictures are encoded before writing them");
+                self.writer.write_all(&image.width().to_le_bytes())?;
+                self.writer.write_all(&image.height().to_le_bytes())?;
+                self.writer.write_all(&(*encoding as u32).to_le_bytes())?;
+                self.writer.write_all(pixels)
+            }
+            FigureContentsData::Line(line) => self.write_line(line),
+            FigureContentsData::Rectangle(rectangle) => {
+                self.write_shape_style(&rectangle.style)?;
+                self.writer.write_all(&rectangle.size.x.to_le_bytes())?;
+                self.writer.write_all(&rectangle.size.y.to_le_bytes())?;
+                self.writer.write_all(&rectangle.corner_radius.to_le_bytes())
+            }
+            FigureContentsData::Ellipse(ellipse) => {
+                self.write_shape_style(&ellipse.style)?;
+                self.writer.write_all(&ellipse.radii.x.to_le_bytes())?;
+                self.writer.write_all(&ellipse.radii.y.to_le_bytes())
+            }
+            FigureContentsData::Polygon(polygon) => {
+                self.write_shape_style(&polygon.style)?;
+                self.write_points(&polygon.points)
+            }
+            FigureContentsData::Text(label) => {
+                self.write_color(label.color)?;
+                self.writer.write_all(&label.font_size.to_le_bytes())?;
+                self.writer.write_all(&label.rotation.to_le_bytes())?;
+                self.writer
+                    .write_all(&(label.horizontal as u32).to_le_bytes())?;
+                self.writer
+                    .write_all(&(label.vertical as u32).to_le_bytes())?;
+                self.writer.write_all(&length_field(label.text.len())?)?;
+                self.writer.write_all(label.text.as_bytes())
+            }
+            FigureContentsData::Path(path) => self.write_path(path),
+            FigureContentsData::Group(group) => {
+                self.write_group_header(group)?;
+                for child in element.children.iter() {
+                    self.write_figure_content(child)?;
+                }
+                Ok(())
+            }
+            FigureContentsData::Heatmap(heatmap) => self.write_heatmap(heatmap),
+        }
+    }
+    fn write_heatmap(&mut self, heatmap: &Heatmap) -> io::Result<()> {
+        self.writer.write_all(&heatmap.grid.x.to_le_bytes())?;
+        self.writer.write_all(&heatmap.grid.y.to_le_bytes())?;
+        self.write_points(&[heatmap.size])?;
+        self.writer.write_all(&heatmap.range.0.to_le_bytes())?;
+        self.writer.write_all(&heatmap.range.1.to_le_bytes())?;
+        self.writer
+            .write_all(&(heatmap.colormap as u32).to_le_bytes())?;
+        for value in heatmap.values.iter() {
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+    fn write_group_header(&mut self, group: &Group) -> io::Result<()> {
+        let matrix = group.transform.matrix();
+        // in the order of an SVG matrix(a b c d e f)
+        for (row, column) in [(0, 0), (1, 0), (0, 1), (1, 1), (0, 2), (1, 2)].iter() {
+            self.writer.write_all(&matrix[(*row, *column)].to_le_bytes())?;
+        }
+        self.writer.write_all(&group.opacity.to_le_bytes())?;
+        self.writer.write_all(&group.z_index.to_le_bytes())?;
+        let flags = if group.clip.is_some() { GROUP_CLIP } else { 0 };
+        self.writer.write_all(&flags.to_le_bytes())?;
+        match &group.clip {
+            Some(clip) => self.write_points(&[clip.position, clip.size]),
+            None => self.writer.write_all(&[0; 2 * POINT_SIZE]),
+        }
+    }
+    fn write_path(&mut self, path: &Path) -> io::Result<()> {
+        self.write_shape_style(&path.style)?;
+        self.writer.write_all(&(path.join as u32).to_le_bytes())?;
+        self.writer.write_all(&(path.cap as u32).to_le_bytes())?;
+        self.writer.write_all(&path.dash_offset.to_le_bytes())?;
+        self.writer.write_all(&length_field(path.dashes.len())?)?;
+        for dash in path.dashes.iter() {
+            self.writer.write_all(&dash.to_le_bytes())?;
+        }
+        for command in path.commands.iter() {
+            self.writer.write_all(&command.tag().to_le_bytes())?;
+            match command {
+                PathCommand::MoveTo(point) | PathCommand::LineTo(point) => {
+                    self.write_points(&[*point])?
+                }
+                PathCommand::QuadraticTo { control, end } => self.write_points(&[*control, *end])?,
+                PathCommand::CubicTo {
+                    control_1,
+                    control_2,
+                    end,
+                } => self.write_points(&[*control_1, *control_2, *end])?,
+                PathCommand::ArcTo {
+                    radii,
+                    rotation,
+                    large_arc,
+                    sweep,
+                    end,
+                } => {
+                    self.write_points(&[*radii])?;
+                    self.writer.write_all(&rotation.to_le_bytes())?;
+                    let flags = *large_arc as u32 | (*sweep as u32) << 1;
+                    self.writer.write_all(&flags.to_le_bytes())?;
+                    self.write_points(&[*end])?;
+                }
+                PathCommand::Close => {}
+            }
+        }
+        Ok(())
+    }
+    fn write_shape_style(&mut self, style: &ShapeStyle) -> io::Result<()> {
+        self.write_color(style.fill)?;
+        self.write_color(style.stroke)?;
+        self.writer.write_all(&style.stroke_width.to_le_bytes())
+    }
+    fn write_line(&mut self, line: &Line) -> io::Result<()> {
+        self.write_color(line.color)?;
+        self.writer.write_all(&line.thickness.to_le_bytes())?;
+        self.write_points(&line.segments)
+    }
+    fn write_points(&mut self, points: &[Vector2<f32>]) -> io::Result<()> {
+        for segment in points.iter() {
+            let x_bytes = segment.x.to_le_bytes();
+            let y_bytes = segment.y.to_le_bytes();
+            self.writer.write_all(&[
+                x_bytes[0], x_bytes[1], x_bytes[2], x_bytes[3], y_bytes[0], y_bytes[1],
+                y_bytes[2], y_bytes[3],
+            ])?;
+        }
+        Ok(())
+    }
+    fn write_color(&mut self, color: Color) -> io::Result<()> {
+        self.writer.write_all(&color.to_bytes())
+    }
+    fn write_header(&mut self, data_type: u32, length: usize) -> io::Result<()> {
+        self.writer.write_all(&data_type.to_le_bytes())?;
+        self.writer.write_all(&length_field(length)?)
+    }
+}
+/// Encodes a payload length, failing if it does not fit in the packet's length field
+fn length_field(length: usize) -> io::Result<[u8; 4]> {
+    if length > u32::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            EncodeError::PacketTooLarge,
+        ));
+    }
+    Ok((length as u32).to_le_bytes())
+}
+fn styled_text_payload_size(text: &StyledText) -> usize {
+    text.spans
+        .iter()
+        .map(|span| SPAN_HEADER_SIZE + span.text.len())
+        .sum()
+}
+fn progress_payload_size(progress: &Progress) -> usize {
+    PROGRESS_HEADER_SIZE
+        + STRING_LENGTH_SIZE
+        + progress.label.len()
+        + STRING_LENGTH_SIZE
+        + progress.units.len()
+}
+fn chart_payload_size(chart: &Chart) -> usize {
+    let texts = [&chart.title, &chart.x_axis.label, &chart.y_axis.label]
+        .iter()
+        .map(|text| STRING_LENGTH_SIZE + text.len())
+        .sum::<usize>();
+    let series = chart
+        .series
+        .iter()
+        .map(|series| {
+            SERIES_HEADER_SIZE
+                + STRING_LENGTH_SIZE
+                + series.name.len()
+                + CHART_POINT_SIZE * series.points.len()
+        })
+        .sum::<usize>();
+    CHART_HEADER_SIZE + texts + series
+}
+fn table_payload_size(table: &Table) -> usize {
+    let columns: usize = table
+        .columns
+        .iter()
+        .map(|column| COLUMN_HEADER_SIZE + STRING_LENGTH_SIZE + column.name.len())
+        .sum();
+    let cells: usize = table
+        .rows
+        .iter()
+        .flatten()
+        .map(|cell| match cell {
+            Cell::String(string) => STRING_CELL_HEADER_SIZE + string.len(),
+            _ => NUMBER_CELL_SIZE,
+        })
+        .sum();
+    TABLE_HEADER_SIZE + columns + cells
+}
+fn figure_header_size(figure: &Figure) -> usize {
+    match figure.view_box {
+        Some(_) => FIGURE_HEADER_SIZE + VIEW_BOX_SIZE,
+        None => FIGURE_HEADER_SIZE,
+    }
+}
+fn figure_payload_size(figure: &Figure) -> usize {
+    figure_header_size(figure)
+        + figure
+            .contents
+            .iter()
+            .map(|content| ELEMENT_HEADER_SIZE + element_payload_size(&content.data))
+            .sum::<usize>()
+}
+/// Size of an element with pictures in raw RGBA
+fn element_payload_size(content: &FigureContentsData) -> usize {
+    match content {
+        FigureContentsData::Image(image) => PICTURE_HEADER_SIZE + image.as_raw().len(),
+        FigureContentsData::Line(line) => LINE_HEADER_SIZE + POINT_SIZE * line.segments.len(),
+        FigureContentsData::Rectangle(_) => SHAPE_STYLE_SIZE + RECTANGLE_SIZE,
+        FigureContentsData::Ellipse(_) => SHAPE_STYLE_SIZE + ELLIPSE_SIZE,
+        FigureContentsData::Polygon(polygon) => {
+            SHAPE_STYLE_SIZE + POINT_SIZE * polygon.points.len()
+        }
+        FigureContentsData::Text(label) => {
+            LABEL_HEADER_SIZE + STRING_LENGTH_SIZE + label.text.len()
+        }
+        FigureContentsData::Path(path) => {
+            let commands: usize = path
+                .commands
+                .iter()
+                .map(|command| {
+                    PATH_COMMAND_TAG_SIZE
+                        + match command {
+                            PathCommand::MoveTo(_) | PathCommand::LineTo(_) => POINT_SIZE,
+                            PathCommand::QuadraticTo { .. } => 2 * POINT_SIZE,
+                            // the rotation and flags of an arc take up as much as a point
+                            PathCommand::CubicTo { .. } | PathCommand::ArcTo { .. } => {
+                                3 * POINT_SIZE
+                            }
+                            PathCommand::Close => 0,
+                        }
+                })
+                .sum();
+            SHAPE_STYLE_SIZE + PATH_HEADER_SIZE + 4 * path.dashes.len() + commands
+        }
+        FigureContentsData::Group(group) => {
+            GROUP_HEADER_SIZE
+                + group
+                    .children
+                    .iter()
+                    .map(|child| ELEMENT_HEADER_SIZE + element_payload_size(&child.data))
+                    .sum::<usize>()
+        }
+        FigureContentsData::Heatmap(heatmap) => {
+            HEATMAP_HEADER_SIZE + HEATMAP_VALUE_SIZE * heatmap.values.len()
+        }
+    }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::FigureContents;
+    use crate::{
+        Alignment, Axis, ChartKind, ClipRectangle, Colormap, ColumnType, Deserializer, Ellipse, Fit,
+        Label, LineCap, LineJoin, Polygon, Rectangle, Span, Style, VerticalAlignment, ViewBox,
+    };
     use image::RgbaImage;
+    use nalgebra::{Affine2, Matrix3};
+    use proptest::prelude::*;
 
     #[test]
     fn text() {
         build_text("hello world".to_string());
     }
     #[test]
+    fn text_bytes() {
+        assert_eq!(
+            build_text("hi".to_string()),
+            vec![0, 0, 0, 0, 2, 0, 0, 0, b'h', b'i']
+        );
+    }
+    #[test]
+    fn figure_bytes() {
+        let fig = Figure {
+            dimensions: Vector2::new(5, 6),
+            view_box: None,
+            contents: vec![
+                FigureContents {
+                    data: FigureContentsData::Image(RgbaImage::from_pixel(
+                        1,
+                        1,
+                        image::Rgba([1, 2, 3, 4]),
+                    )),
+                    position: Vector2::new(-1, 2),
+                },
+                FigureContents {
+                    data: FigureContentsData::Line(Line {
+                        color: Color::rgba(0xff, 0, 0, 0),
+                        thickness: 1.0,
+                        segments: vec![Vector2::new(0.0, 1.0)],
+                    }),
+                    position: Vector2::new(0, 0),
+                },
+            ],
+        };
+        #[rustfmt::skip]
+        let expected = vec![
+            1, 0, 0, 0,
+            76, 0, 0, 0,
+            5, 0, 0, 0,
+            6, 0, 0, 0,
+            //flags
+            0, 0, 0, 0,
+            //picture element
+            0, 0, 0, 0,
+            16, 0, 0, 0,
+            0xff, 0xff, 0xff, 0xff,
+            2, 0, 0, 0,
+            1, 0, 0, 0,
+            1, 0, 0, 0,
+            0, 0, 0, 0,
+            1, 2, 3, 4,
+            //line element
+            1, 0, 0, 0,
+            16, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            0xff, 0, 0, 0,
+            0, 0, 0x80, 0x3f,
+            0, 0, 0, 0,
+            0, 0, 0x80, 0x3f,
+        ];
+        assert_eq!(figure_size(&fig), expected.len());
+        let mut streamed = vec![];
+        Serializer::new(&mut streamed).write_figure(&fig).unwrap();
+        assert_eq!(streamed, expected);
+        let mut fixed = [0; 128];
+        assert_eq!(encode_figure_into(&fig, &mut fixed), Ok(expected.len()));
+        assert_eq!(&fixed[..expected.len()], &expected[..]);
+        assert_eq!(build_figure(fig), expected);
+    }
+    #[test]
+    fn encode_into_small_buffer() {
+        let mut fixed = [0xaa; 4];
+        assert_eq!(
+            encode_text_into("hello", &mut fixed),
+            Err(EncodeError::BufferTooSmall {
+                needed: 13,
+                available: 4
+            })
+        );
+        assert_eq!(fixed, [0xaa; 4]);
+    }
+    #[test]
     fn empty_fig() {
         let fig = Figure {
             dimensions: Vector2::new(200, 200),
+            view_box: None,
             contents: vec![],
         };
         build_figure(fig);
@@ -105,6 +812,7 @@ mod tests {
     fn image() {
         let fig = Figure {
             dimensions: Vector2::new(200, 200),
+            view_box: None,
             contents: vec![FigureContents {
                 data: FigureContentsData::Image(RgbaImage::new(10, 10)),
                 position: Vector2::new(0, 0),
@@ -113,12 +821,32 @@ mod tests {
         build_figure(fig);
     }
     #[test]
+    fn compressed_update() {
+        let image = RgbaImage::from_fn(100, 100, |x, _| image::Rgba([x as u8, 0, 0, 255]));
+        let update = ParsedAST::Update(
+            3,
+            Box::new(ParsedAST::Figure(
+                Figure::new(100, 100).image_at(Vector2::new(0, 0), image),
+            )),
+        );
+        let mut bytes = vec![];
+        Serializer::new(&mut bytes)
+            .image_encoding(None)
+            .write(&update)
+            .unwrap();
+        assert!(bytes.len() < 100 * 100 * 3);
+        // update header, id, figure header, dimensions and flags, element header and dimensions
+        assert_eq!(&bytes[56..60], &(ImageEncoding::Qoi as u32).to_le_bytes());
+        assert_eq!(Deserializer::new().parse(&mut bytes), Ok(vec![update]));
+    }
+    #[test]
     fn lines() {
         let fig = Figure {
             dimensions: Vector2::new(200, 200),
+            view_box: None,
             contents: vec![FigureContents {
                 data: FigureContentsData::Line(Line {
-                    color: 0x00_00_00_ff,
+                    color: Color::BLACK,
                     thickness: 1.0,
                     segments: vec![Vector2::new(0.0, 0.0), Vector2::new(1.0, 1.0)],
                 }),
@@ -127,4 +855,369 @@ mod tests {
         };
         build_figure(fig);
     }
+    fn color() -> impl Strategy<Value = Color> {
+        any::<[u8; 4]>().prop_map(Color::from_bytes)
+    }
+    fn coordinate() -> impl Strategy<Value = f32> {
+        -1.0e6f32..1.0e6f32
+    }
+    fn figure_contents() -> impl Strategy<Value = FigureContents> {
+        let point = || (coordinate(), coordinate()).prop_map(|(x, y)| Vector2::new(x, y));
+        let image = (0u32..5, 0u32..5)
+            .prop_flat_map(|(width, height)| {
+                prop::collection::vec(any::<u8>(), (width * height * 4) as usize)
+                    .prop_map(move |pixels| RgbaImage::from_raw(width, height, pixels).unwrap())
+            })
+            .prop_map(FigureContentsData::Image);
+        let line = (
+            color(),
+            coordinate(),
+            prop::collection::vec((coordinate(), coordinate()), 0..8),
+        )
+            .prop_map(|(color, thickness, points)| {
+                FigureContentsData::Line(Line {
+                    color,
+                    thickness,
+                    segments: points.iter().map(|(x, y)| Vector2::new(*x, *y)).collect(),
+                })
+            });
+        let style = (color(), color(), coordinate()).prop_map(|(fill, stroke, stroke_width)| {
+            ShapeStyle {
+                fill,
+                stroke,
+                stroke_width,
+            }
+        })
+        .boxed();
+        let rectangle = (style.clone(), coordinate(), coordinate(), coordinate()).prop_map(
+            |(style, width, height, corner_radius)| {
+                FigureContentsData::Rectangle(Rectangle {
+                    size: Vector2::new(width, height),
+                    corner_radius,
+                    style,
+                })
+            },
+        );
+        let ellipse = (style.clone(), coordinate(), coordinate()).prop_map(|(style, x, y)| {
+            FigureContentsData::Ellipse(Ellipse {
+                radii: Vector2::new(x, y),
+                style,
+            })
+        });
+        let polygon = (style.clone(), prop::collection::vec((coordinate(), coordinate()), 0..8)).prop_map(
+            |(style, points)| {
+                FigureContentsData::Polygon(Polygon {
+                    points: points.iter().map(|(x, y)| Vector2::new(*x, *y)).collect(),
+                    style,
+                })
+            },
+        );
+        let command = prop_oneof![
+            point().prop_map(PathCommand::MoveTo),
+            point().prop_map(PathCommand::LineTo),
+            (point(), point()).prop_map(|(control, end)| PathCommand::QuadraticTo { control, end }),
+            (point(), point(), point()).prop_map(|(control_1, control_2, end)| {
+                PathCommand::CubicTo {
+                    control_1,
+                    control_2,
+                    end,
+                }
+            }),
+            (point(), coordinate(), any::<(bool, bool)>(), point()).prop_map(
+                |(radii, rotation, (large_arc, sweep), end)| PathCommand::ArcTo {
+                    radii,
+                    rotation,
+                    large_arc,
+                    sweep,
+                    end,
+                }
+            ),
+            Just(PathCommand::Close),
+        ];
+        let path = (
+            style.clone(),
+            prop::collection::vec(command, 0..8),
+            prop::sample::select(&LineJoin::ALL[..]),
+            prop::sample::select(&LineCap::ALL[..]),
+            prop::collection::vec(coordinate(), 0..4),
+            coordinate(),
+        )
+            .prop_map(|(style, commands, join, cap, dashes, dash_offset)| {
+                FigureContentsData::Path(Path {
+                    commands,
+                    style,
+                    join,
+                    cap,
+                    dashes,
+                    dash_offset,
+                })
+            });
+        let label = (
+            any::<String>(),
+            color(),
+            coordinate(),
+            coordinate(),
+            prop::sample::select(&Alignment::ALL[..]),
+            prop::sample::select(&VerticalAlignment::ALL[..]),
+        )
+            .prop_map(
+                |(text, color, font_size, rotation, horizontal, vertical)| {
+                    FigureContentsData::Text(Label {
+                        text,
+                        font_size,
+                        color,
+                        rotation,
+                        horizontal,
+                        vertical,
+                    })
+                },
+            );
+        let heatmap = (0u32..5, 0u32..5)
+            .prop_flat_map(move |(columns, rows)| {
+                (
+                    prop::collection::vec(coordinate(), (columns * rows) as usize),
+                    point(),
+                    coordinate(),
+                    coordinate(),
+                    prop::sample::select(&Colormap::ALL[..]),
+                )
+                    .prop_map(move |(values, size, low, high, colormap)| {
+                        FigureContentsData::Heatmap(Heatmap {
+                            grid: Vector2::new(columns, rows),
+                            size,
+                            values,
+                            range: (low, high),
+                            colormap,
+                        })
+                    })
+            });
+        let leaf = (
+            prop_oneof![image, line, rectangle, ellipse, polygon, label, path, heatmap],
+            any::<(i32, i32)>(),
+        ).prop_map(|(data, (x, y))| {
+            FigureContents {
+                data,
+                position: Vector2::new(x, y),
+            }
+        });
+        leaf.prop_recursive(3, 16, 4, move |element| {
+            let clip =
+                (point(), point()).prop_map(|(position, size)| ClipRectangle { position, size });
+            (
+                prop::collection::vec(coordinate(), 6),
+                coordinate(),
+                any::<i32>(),
+                prop::option::of(clip),
+                prop::collection::vec(element, 0..4),
+                any::<(i32, i32)>(),
+            )
+                .prop_map(|(matrix, opacity, z_index, clip, children, (x, y))| {
+                    // in the order of an SVG matrix(a b c d e f)
+                    let transform = Affine2::from_matrix_unchecked(Matrix3::new(
+                        matrix[0], matrix[2], matrix[4], matrix[1], matrix[3], matrix[5], 0.0, 0.0,
+                        1.0,
+                    ));
+                    FigureContents {
+                        data: FigureContentsData::Group(Group {
+                            transform,
+                            opacity,
+                            clip,
+                            z_index,
+                            children,
+                        }),
+                        position: Vector2::new(x, y),
+                    }
+                })
+        })
+    }
+    fn cell(column_type: ColumnType) -> BoxedStrategy<Cell> {
+        match column_type {
+            ColumnType::String => any::<String>().prop_map(Cell::String).boxed(),
+            ColumnType::Integer => any::<i64>().prop_map(Cell::Integer).boxed(),
+            ColumnType::Float => (-1.0e12f64..1.0e12f64).prop_map(Cell::Float).boxed(),
+            ColumnType::Bytes => any::<u64>().prop_map(Cell::Bytes).boxed(),
+            ColumnType::Date => any::<i64>().prop_map(Cell::Date).boxed(),
+        }
+    }
+    fn table() -> impl Strategy<Value = ParsedAST> {
+        let column = (
+            prop::sample::select(&ColumnType::ALL[..]),
+            prop::sample::select(&Alignment::ALL[..]),
+            any::<String>(),
+        );
+        prop::collection::vec(column, 1..4).prop_flat_map(|columns| {
+            let row: Vec<_> = columns.iter().map(|column| cell(column.0)).collect();
+            prop::collection::vec(row, 0..4).prop_map(move |rows| {
+                let table = columns
+                    .iter()
+                    .fold(Table::new(), |table, (column_type, alignment, name)| {
+                        table.column(name, *column_type, *alignment)
+                    });
+                ParsedAST::Table(rows.into_iter().fold(table, Table::row))
+            })
+        })
+    }
+    /// Generates every kind of packet the protocol supports
+    fn parsed_ast() -> impl Strategy<Value = ParsedAST> {
+        let view_box = (
+            coordinate(),
+            coordinate(),
+            coordinate(),
+            coordinate(),
+            prop::sample::select(&Fit::ALL[..]),
+        )
+            .prop_map(|(x, y, width, height, fit)| ViewBox {
+                origin: Vector2::new(x, y),
+                size: Vector2::new(width, height),
+                fit,
+            });
+        let figure = (
+            any::<(u32, u32)>(),
+            prop::option::of(view_box),
+            prop::collection::vec(figure_contents(), 0..4),
+        )
+            .prop_map(|((x, y), view_box, contents)| {
+                ParsedAST::Figure(Figure {
+                    dimensions: Vector2::new(x, y),
+                    view_box,
+                    contents,
+                })
+            });
+        let hello = any::<(u32, u32)>().prop_map(|(version, capabilities)| {
+            ParsedAST::Hello(Hello {
+                version,
+                capabilities,
+            })
+        });
+        let capabilities = any::<[u32; 5]>().prop_map(|fields| {
+            ParsedAST::Capabilities(Capabilities {
+                datatypes: fields[0],
+                figure_elements: fields[1],
+                max_image_size: Vector2::new(fields[2], fields[3]),
+                color_depth: fields[4],
+            })
+        });
+        let span = (any::<String>(), (color(), color()), 0u32..32).prop_map(
+            |(text, (foreground, background), flags)| Span {
+                text,
+                style: Style::from_flags(foreground, background, flags),
+            },
+        );
+        let styled_text = prop::collection::vec(span, 0..4)
+            .prop_map(|spans| ParsedAST::StyledText(StyledText { spans }));
+        let progress = (
+            any::<(String, String)>(),
+            any::<u64>(),
+            any::<Option<u64>>(),
+            any::<Option<u32>>(),
+        )
+            .prop_map(|((label, units), current, total, eta)| {
+                ParsedAST::Progress(Progress {
+                    label,
+                    current,
+                    total,
+                    units,
+                    eta,
+                })
+            });
+        let range = prop::option::of((-1e6f64..1e6, -1e6f64..1e6));
+        let axis = (any::<String>(), range).prop_map(|(label, range)| Axis { label, range });
+        let series = (
+            any::<String>(),
+            prop::option::of(color()),
+            prop::collection::vec((-1e6f64..1e6, -1e6f64..1e6), 0..8),
+        )
+            .prop_map(|(name, color, points)| Series {
+                name,
+                color,
+                points: points.into_iter().map(|(x, y)| Vector2::new(x, y)).collect(),
+            });
+        let chart = (
+            prop::sample::select(&ChartKind::ALL[..]),
+            any::<String>(),
+            (axis.clone(), axis),
+            prop::collection::vec(series, 0..4),
+            any::<bool>(),
+        )
+            .prop_map(|(kind, title, (x_axis, y_axis), series, legend)| {
+                ParsedAST::Chart(Chart {
+                    kind,
+                    title,
+                    x_axis,
+                    y_axis,
+                    series,
+                    legend,
+                })
+            });
+        let output = prop_oneof![
+            any::<String>().prop_map(ParsedAST::String),
+            styled_text,
+            table(),
+            figure,
+            progress,
+            chart,
+        ]
+        .boxed();
+        let update = (any::<u32>(), output.clone())
+            .prop_map(|(id, ast)| ParsedAST::Update(id, Box::new(ast)));
+        prop_oneof![
+            output,
+            update,
+            any::<u32>().prop_map(ParsedAST::Delete),
+            hello,
+            Just(ParsedAST::CapabilityQuery),
+            capabilities,
+            prop::collection::vec(color(), 0..10)
+                .prop_map(|colors| ParsedAST::Palette(Palette { colors })),
+        ]
+    }
+    proptest! {
+        #[test]
+        fn round_trip(ast in parsed_ast()) {
+            let bytes = serialize(&ast);
+            prop_assert_eq!(bytes.len(), packet_size(&ast));
+            prop_assert_eq!(Deserializer::new().parse(&mut bytes.clone()), Ok(vec![ast.clone()]));
+            let mut fixed = vec![0; bytes.len()];
+            prop_assert_eq!(encode_into(&ast, &mut fixed), Ok(bytes.len()));
+            prop_assert_eq!(fixed, bytes);
+        }
+        #[test]
+        fn round_trip_stream(
+            asts in prop::collection::vec(parsed_ast(), 0..6),
+            split in any::<prop::sample::Index>()
+        ) {
+            let mut stream = vec![];
+            let mut serializer = Serializer::new(&mut stream);
+            for ast in asts.iter() {
+                serializer.write(ast).unwrap();
+            }
+            let (first, second) = stream.split_at(split.index(stream.len() + 1));
+            let mut p = Deserializer::new();
+            let mut parsed = p.parse(&mut first.to_vec()).unwrap();
+            parsed.append(&mut p.parse(&mut second.to_vec()).unwrap());
+            prop_assert_eq!(parsed, asts);
+        }
+        #[test]
+        fn round_trip_encoded(
+            ast in parsed_ast(),
+            encoding in prop_oneof![
+                Just(None),
+                Just(Some(ImageEncoding::Png)),
+                Just(Some(ImageEncoding::Qoi)),
+            ]
+        ) {
+            let mut bytes = vec![];
+            Serializer::new(&mut bytes).image_encoding(encoding).write(&ast).unwrap();
+            prop_assert_eq!(Deserializer::new().parse(&mut bytes), Ok(vec![ast]));
+        }
+        #[test]
+        fn rasterize_never_panics(contents in prop::collection::vec(figure_contents(), 0..4)) {
+            let figure = Figure {
+                dimensions: Vector2::new(32, 32),
+                view_box: None,
+                contents,
+            };
+            prop_assert_eq!(figure.rasterize().dimensions(), (32, 32));
+        }
+    }
 }
@@ -0,0 +1,84 @@
+use super::Color;
+/// Text made out of runs that each have their own colors and font style.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct StyledText {
+    pub spans: Vec<Span>,
+}
+impl StyledText {
+    pub fn new() -> Self {
+        Self { spans: vec![] }
+    }
+    /// Adds `text` drawn with `style` to the end
+    pub fn span(mut self, text: &str, style: Style) -> Self {
+        self.spans.push(Span {
+            text: text.to_string(),
+            style,
+        });
+        self
+    }
+    /// Text with the styling removed, for frontends that can only show plain text
+    pub fn plain_text(&self) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+}
+#[derive(Debug, PartialEq, Clone)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Style {
+    pub foreground: Color,
+    pub background: Color,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    /// Draw with the monospace font, otherwise a proportional font is used
+    pub monospace: bool,
+}
+impl Style {
+    const BOLD: u32 = 1 << 0;
+    const ITALIC: u32 = 1 << 1;
+    const UNDERLINE: u32 = 1 << 2;
+    const STRIKETHROUGH: u32 = 1 << 3;
+    const PROPORTIONAL: u32 = 1 << 4;
+    /// Packs the font flags the way they are sent
+    pub(crate) fn flags(&self) -> u32 {
+        [
+            (self.bold, Self::BOLD),
+            (self.italic, Self::ITALIC),
+            (self.underline, Self::UNDERLINE),
+            (self.strikethrough, Self::STRIKETHROUGH),
+            (!self.monospace, Self::PROPORTIONAL),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |flags, (_, bit)| flags | bit)
+    }
+    pub(crate) fn from_flags(foreground: Color, background: Color, flags: u32) -> Self {
+        Self {
+            foreground,
+            background,
+            bold: flags & Self::BOLD != 0,
+            italic: flags & Self::ITALIC != 0,
+            underline: flags & Self::UNDERLINE != 0,
+            strikethrough: flags & Self::STRIKETHROUGH != 0,
+            monospace: flags & Self::PROPORTIONAL == 0,
+        }
+    }
+}
+impl Default for Style {
+    /// Opaque white monospace text on a transparent background
+    fn default() -> Self {
+        Self {
+            foreground: Color::WHITE,
+            background: Color::TRANSPARENT,
+            bold: false,
+            italic: false,
+            underline: false,
+            strikethrough: false,
+            monospace: true,
+        }
+    }
+}
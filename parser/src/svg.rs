@@ -0,0 +1,430 @@
+use super::figure::drawing_order;
+use super::picture::png;
+use super::{
+    Alignment, Color, Figure, FigureContents, FigureContentsData, Group, Label, LineCap, LineJoin,
+    Path, PathCommand, ShapeStyle, VerticalAlignment,
+};
+use nalgebra::Vector2;
+use std::fmt::Write;
+impl Figure {
+    /// Writes the figure as an SVG document the size of the figure, with the figure's view box as
+    /// the SVG's `viewBox` if it has one. Images and heatmaps are embedded as PNGs and every
+    /// other element stays a vector shape, text is set in the viewer's sans-serif font. Groups
+    /// become `<g>` elements in the order of their z index.
+    pub fn to_svg(&self) -> String {
+        let mut svg = String::new();
+        // writing to a String can not fail
+        let _ = self.write_svg(&mut svg);
+        svg
+    }
+    fn write_svg(&self, svg: &mut String) -> std::fmt::Result {
+        let (width, height) = (self.dimensions.x, self.dimensions.y);
+        let view_box = match &self.view_box {
+            Some(view_box) => format!(
+                "{} {}",
+                point_text(view_box.origin),
+                point_text(view_box.size)
+            ),
+            None => format!("0 0 {} {}", width, height),
+        };
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{}">"#,
+            width, height, view_box
+        )?;
+        write_elements(svg, &self.contents, &mut 0)?;
+        writeln!(svg, "</svg>")
+    }
+}
+/// Writes `elements` in drawing order. `clips` counts the clip paths written so far, to give
+/// each one its own id.
+fn write_elements(
+    svg: &mut String,
+    elements: &[FigureContents],
+    clips: &mut usize,
+) -> std::fmt::Result {
+    for element in drawing_order(elements) {
+        let offset = Vector2::new(element.position.x as f32, element.position.y as f32);
+        match &element.data {
+            FigureContentsData::Image(image) => {
+                if image.width() == 0 || image.height() == 0 {
+                    continue;
+                }
+                writeln!(
+                    svg,
+                    r#"<image x="{}" y="{}" width="{}" height="{}" style="image-rendering:pixelated" href="data:image/png;base64,{}"/>"#,
+                    element.position.x,
+                    element.position.y,
+                    image.width(),
+                    image.height(),
+                    base64(&png(image))
+                )?;
+            }
+            FigureContentsData::Line(line) => {
+                let mut data = String::new();
+                for (index, point) in line.segments.iter().enumerate() {
+                    let command = if index == 0 { 'M' } else { 'L' };
+                    write!(data, "{}{} ", command, point_text(point + offset))?;
+                }
+                writeln!(
+                    svg,
+                    r#"<path d="{}" fill="none"{} stroke-width="{}"/>"#,
+                    data.trim_end(),
+                    paint("stroke", line.color),
+                    number(line.thickness)
+                )?;
+            }
+            FigureContentsData::Rectangle(rectangle) => {
+                let far = offset + rectangle.size;
+                writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{}"{}/>"#,
+                    number(offset.x.min(far.x)),
+                    number(offset.y.min(far.y)),
+                    number(rectangle.size.x.abs()),
+                    number(rectangle.size.y.abs()),
+                    number(rectangle.corner_radius.max(0.0)),
+                    shape_style(&rectangle.style)
+                )?;
+            }
+            FigureContentsData::Ellipse(ellipse) => {
+                writeln!(
+                    svg,
+                    r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}"{}/>"#,
+                    element.position.x,
+                    element.position.y,
+                    number(ellipse.radii.x.abs()),
+                    number(ellipse.radii.y.abs()),
+                    shape_style(&ellipse.style)
+                )?;
+            }
+            FigureContentsData::Polygon(polygon) => {
+                let points: Vec<String> = polygon
+                    .points
+                    .iter()
+                    .map(|point| point_text(point + offset))
+                    .collect();
+                writeln!(
+                    svg,
+                    r#"<polygon points="{}"{}/>"#,
+                    points.join(" "),
+                    shape_style(&polygon.style)
+                )?;
+            }
+            FigureContentsData::Text(label) => write_label(svg, label, offset)?,
+            FigureContentsData::Path(path) => write_path(svg, path, offset)?,
+            FigureContentsData::Group(group) => write_group(svg, group, offset, clips)?,
+            FigureContentsData::Heatmap(heatmap) => {
+                let size = heatmap.size;
+                if heatmap.grid.x == 0 || heatmap.grid.y == 0 || !(size.x > 0.0 && size.y > 0.0) {
+                    continue;
+                }
+                // one pixel per cell, stretched to the size of the heatmap
+                writeln!(
+                    svg,
+                    r#"<image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="none" style="image-rendering:pixelated" href="data:image/png;base64,{}"/>"#,
+                    element.position.x,
+                    element.position.y,
+                    number(size.x),
+                    number(size.y),
+                    base64(&png(&heatmap.to_image()))
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+fn write_group(
+    svg: &mut String,
+    group: &Group,
+    offset: Vector2<f32>,
+    clips: &mut usize,
+) -> std::fmt::Result {
+    let matrix = group.transform.matrix();
+    let entries: Vec<String> = [(0, 0), (1, 0), (0, 1), (1, 1), (0, 2), (1, 2)]
+        .iter()
+        .map(|(row, column)| number(matrix[(*row, *column)]))
+        .collect();
+    write!(
+        svg,
+        r#"<g transform="translate({}) matrix({})""#,
+        point_text(offset),
+        entries.join(" ")
+    )?;
+    if group.opacity < 1.0 || group.opacity.is_nan() {
+        write!(svg, r#" opacity="{}""#, number(group.opacity.max(0.0)))?;
+    }
+    match &group.clip {
+        Some(clip) => {
+            let id = *clips;
+            *clips += 1;
+            let far = clip.position + clip.size;
+            // the clip path is in the coordinates of the group, the same as its children
+            writeln!(svg, r#" clip-path="url(#clip{})">"#, id)?;
+            writeln!(
+                svg,
+                r#"<clipPath id="clip{}"><rect x="{}" y="{}" width="{}" height="{}"/></clipPath>"#,
+                id,
+                number(clip.position.x.min(far.x)),
+                number(clip.position.y.min(far.y)),
+                number(clip.size.x.abs()),
+                number(clip.size.y.abs())
+            )?;
+        }
+        None => writeln!(svg, ">")?,
+    }
+    write_elements(svg, &group.children, clips)?;
+    writeln!(svg, "</g>")
+}
+fn write_label(svg: &mut String, label: &Label, anchor: Vector2<f32>) -> std::fmt::Result {
+    let horizontal = match label.horizontal {
+        Alignment::Left => "start",
+        Alignment::Center => "middle",
+        Alignment::Right => "end",
+    };
+    let vertical = match label.vertical {
+        VerticalAlignment::Top => "text-before-edge",
+        VerticalAlignment::Middle => "central",
+        VerticalAlignment::Baseline => "alphabetic",
+        VerticalAlignment::Bottom => "text-after-edge",
+    };
+    let (x, y) = (number(anchor.x), number(anchor.y));
+    writeln!(
+        svg,
+        r#"<text x="{}" y="{}" font-family="sans-serif" font-size="{}"{} text-anchor="{}" dominant-baseline="{}" transform="rotate({} {} {})">{}</text>"#,
+        x,
+        y,
+        number(label.font_size),
+        paint("fill", label.color),
+        horizontal,
+        vertical,
+        number(label.rotation),
+        x,
+        y,
+        escape(&label.text)
+    )
+}
+fn write_path(svg: &mut String, path: &Path, offset: Vector2<f32>) -> std::fmt::Result {
+    let mut data = String::new();
+    for command in path.commands.iter() {
+        match *command {
+            PathCommand::MoveTo(point) => write!(data, "M{} ", point_text(point + offset))?,
+            PathCommand::LineTo(point) => write!(data, "L{} ", point_text(point + offset))?,
+            PathCommand::QuadraticTo { control, end } => write!(
+                data,
+                "Q{} {} ",
+                point_text(control + offset),
+                point_text(end + offset)
+            )?,
+            PathCommand::CubicTo {
+                control_1,
+                control_2,
+                end,
+            } => write!(
+                data,
+                "C{} {} {} ",
+                point_text(control_1 + offset),
+                point_text(control_2 + offset),
+                point_text(end + offset)
+            )?,
+            PathCommand::ArcTo {
+                radii,
+                rotation,
+                large_arc,
+                sweep,
+                end,
+            } => write!(
+                data,
+                "A{} {} {} {} {} ",
+                point_text(radii),
+                number(rotation),
+                large_arc as u8,
+                sweep as u8,
+                point_text(end + offset)
+            )?,
+            PathCommand::Close => data.push_str("Z "),
+        }
+    }
+    let join = match path.join {
+        LineJoin::Miter => "miter",
+        LineJoin::Round => "round",
+        LineJoin::Bevel => "bevel",
+    };
+    let cap = match path.cap {
+        LineCap::Butt => "butt",
+        LineCap::Round => "round",
+        LineCap::Square => "square",
+    };
+    let mut dashes = String::new();
+    if !path.dashes.is_empty() {
+        let pattern: Vec<String> = path.dashes.iter().map(|dash| number(*dash)).collect();
+        write!(
+            dashes,
+            r#" stroke-dasharray="{}" stroke-dashoffset="{}""#,
+            pattern.join(" "),
+            number(path.dash_offset)
+        )?;
+    }
+    writeln!(
+        svg,
+        r#"<path d="{}"{} stroke-linejoin="{}" stroke-linecap="{}" stroke-miterlimit="4"{}/>"#,
+        data.trim_end(),
+        shape_style(&path.style),
+        join,
+        cap,
+        dashes
+    )
+}
+/// Fill and stroke attributes of a shape
+fn shape_style(style: &ShapeStyle) -> String {
+    format!(
+        r#"{}{} stroke-width="{}""#,
+        paint("fill", style.fill),
+        paint("stroke", style.stroke),
+        number(style.stroke_width)
+    )
+}
+/// `attribute` set to `color`, with an opacity if it is not opaque
+fn paint(attribute: &str, color: Color) -> String {
+    match color.a {
+        0 => format!(r#" {}="none""#, attribute),
+        255 => format!(r#" {}="{}""#, attribute, color),
+        _ => format!(
+            r#" {}="{}" {}-opacity="{}""#,
+            attribute,
+            color.with_alpha(255),
+            attribute,
+            number(color.a as f32 / 255.0)
+        ),
+    }
+}
+fn point_text(point: Vector2<f32>) -> String {
+    format!("{} {}", number(point.x), number(point.y))
+}
+/// Formats `value` as an SVG number, which has no way to write infinity or NaN
+fn number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "0".to_string()
+    }
+}
+/// Escapes the characters that have a meaning in XML text and attributes
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+/// Standard base64 with padding
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len() / 3 * 4 + 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, byte)| {
+            group | (*byte as u32) << (16 - 8 * index)
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colormap, Heatmap};
+    use image::RgbaImage;
+    #[test]
+    fn elements() {
+        let path = Path::new(ShapeStyle::stroked(Color::RED.with_alpha(0x80), 2.0))
+            .move_to(Vector2::new(0.0, 0.0))
+            .quadratic_to(Vector2::new(5.0, 10.0), Vector2::new(10.0, 0.0))
+            .dash(vec![3.0, 1.5], 0.0);
+        let svg = Figure::new(40, 30)
+            .image_at(Vector2::new(1, 2), RgbaImage::new(1, 1))
+            .polyline(
+                Color::GREEN,
+                1.0,
+                vec![Vector2::new(0.0, 0.0), Vector2::new(4.0, 4.0)],
+            )
+            .path(path)
+            .label(Vector2::new(20, 25), Label::new("a < b", 8.0, Color::WHITE))
+            .to_svg();
+        assert!(
+            svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="30""#)
+        );
+        assert!(svg.contains(r#"<image x="1" y="2" width="1" height="1""#));
+        assert!(svg.contains("href=\"data:image/png;base64,iVBORw0KGgo"));
+        assert!(svg
+            .contains(r##"<path d="M0 0 L4 4" fill="none" stroke="#00ff00" stroke-width="1"/>"##));
+        assert!(svg.contains(
+            r##"d="M0 0 Q5 10 10 0" fill="none" stroke="#ff0000" stroke-opacity="0.5019608""##
+        ));
+        assert!(svg.contains(r#"stroke-dasharray="3 1.5""#));
+        assert!(svg.contains(">a &lt; b</text>"));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+    #[test]
+    fn groups() {
+        let marker = Figure::new(0, 0).ellipse(
+            Vector2::new(0, 0),
+            Vector2::new(2.0, 2.0),
+            ShapeStyle::filled(Color::RED),
+        );
+        let svg = Figure::new(40, 30)
+            .group(
+                Vector2::new(10, 5),
+                Group::from_figure(marker.clone())
+                    .scale(Vector2::new(2.0, 3.0))
+                    .opacity(0.5)
+                    .clip(Vector2::new(-1.0, -1.0), Vector2::new(2.0, 2.0)),
+            )
+            .group(Vector2::new(0, 0), Group::from_figure(marker).z_index(-1))
+            .to_svg();
+        let lines: Vec<&str> = svg.lines().collect();
+        assert_eq!(
+            lines[1],
+            r#"<g transform="translate(0 0) matrix(1 0 0 1 0 0)">"#
+        );
+        assert_eq!(lines[3], "</g>");
+        assert_eq!(
+            lines[4],
+            r#"<g transform="translate(10 5) matrix(2 0 0 3 0 0)" opacity="0.5" clip-path="url(#clip0)">"#
+        );
+        assert_eq!(
+            lines[5],
+            r#"<clipPath id="clip0"><rect x="-1" y="-1" width="2" height="2"/></clipPath>"#
+        );
+        assert!(lines[6].starts_with("<ellipse"));
+        assert_eq!(lines[7], "</g>");
+    }
+    #[test]
+    fn heatmap_size() {
+        let heatmap = Heatmap::new(Vector2::new(1, 1), vec![0.0], Colormap::Magma)
+            .size(Vector2::new(f32::INFINITY, 2.5));
+        let svg = Figure::new(8, 8)
+            .heatmap(Vector2::new(0, 0), heatmap)
+            .to_svg();
+        assert!(svg.contains(r#"<image x="0" y="0" width="0" height="2.5""#));
+    }
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(base64(b""), "");
+    }
+}
@@ -0,0 +1,327 @@
+use std::cmp::Ordering;
+use std::fmt;
+/// Rows of typed values under named columns.
+///
+/// Apps send values unformatted, the frontend formats them and uses the column types to sort
+/// and align. Every row has one cell per column and the type of each cell matches its column,
+/// the builder panics on anything else so a `Table` can always be sent.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Table {
+    pub(crate) columns: Vec<Column>,
+    pub(crate) rows: Vec<Vec<Cell>>,
+}
+impl Table {
+    pub fn new() -> Self {
+        Self {
+            columns: vec![],
+            rows: vec![],
+        }
+    }
+    /// Adds a column on the right.
+    ///
+    /// # Panics
+    /// If rows were already added.
+    pub fn column(mut self, name: &str, column_type: ColumnType, alignment: Alignment) -> Self {
+        assert!(self.rows.is_empty(), "columns have to be added before rows");
+        self.columns.push(Column {
+            name: name.to_string(),
+            column_type,
+            alignment,
+        });
+        self
+    }
+    /// Adds a row at the bottom.
+    ///
+    /// # Panics
+    /// If the table has no columns, there is not one cell per column or a cell's type does not
+    /// match its column.
+    pub fn row(mut self, cells: Vec<Cell>) -> Self {
+        assert!(!self.columns.is_empty(), "rows need at least one column");
+        assert_eq!(cells.len(), self.columns.len(), "row needs one cell per column");
+        for (cell, column) in cells.iter().zip(self.columns.iter()) {
+            assert_eq!(
+                cell.column_type(),
+                column.column_type,
+                "cell does not match the type of column {}",
+                column.name
+            );
+        }
+        self.rows.push(cells);
+        self
+    }
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+    pub fn rows(&self) -> &[Vec<Cell>] {
+        &self.rows
+    }
+    /// Sorts the rows by the values in `column`, rows with equal values keep their order
+    pub fn sort_by(&mut self, column: usize, ascending: bool) {
+        self.rows.sort_by(|a, b| {
+            let order = a[column].compare(&b[column]);
+            if ascending {
+                order
+            } else {
+                order.reverse()
+            }
+        });
+    }
+    /// Comma separated values with a header line, fields are quoted as in RFC 4180
+    pub fn to_csv(&self) -> String {
+        self.to_delimited(|field| {
+            if field.contains(&[',', '"', '\n', '\r'][..]) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        }, ",")
+    }
+    /// Tab separated values with a header line. The format has no quoting so tabs and line
+    /// breaks inside of fields are replaced with spaces.
+    pub fn to_tsv(&self) -> String {
+        self.to_delimited(|field| field.replace(&['\t', '\n', '\r'][..], " "), "\t")
+    }
+    /// Lays the table out as fixed width text, for frontends that can only show plain text
+    pub fn plain_text(&self) -> String {
+        let header: Vec<String> = self.columns.iter().map(|column| column.name.clone()).collect();
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect();
+        let widths: Vec<usize> = (0..self.columns.len())
+            .map(|i| {
+                std::iter::once(&header)
+                    .chain(rows.iter())
+                    .map(|row| row[i].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let mut text = String::new();
+        for row in std::iter::once(&header).chain(rows.iter()) {
+            let line: Vec<String> = row
+                .iter()
+                .zip(self.columns.iter().zip(widths.iter()))
+                .map(|(field, (column, width))| match column.alignment {
+                    Alignment::Left => format!("{:<1$}", field, width),
+                    Alignment::Right => format!("{:>1$}", field, width),
+                    Alignment::Center => format!("{:^1$}", field, width),
+                })
+                .collect();
+            text.push_str(line.join("  ").trim_end());
+            text.push('\n');
+        }
+        text
+    }
+    fn to_delimited<F: Fn(String) -> String>(&self, escape: F, delimiter: &str) -> String {
+        let header = self.columns.iter().map(|column| column.name.clone());
+        let mut lines = vec![header.map(&escape).collect::<Vec<_>>().join(delimiter)];
+        for row in self.rows.iter() {
+            let fields = row.iter().map(|cell| escape(cell.field()));
+            lines.push(fields.collect::<Vec<_>>().join(delimiter));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+#[derive(Debug, PartialEq, Clone)]
+pub struct Column {
+    pub name: String,
+    pub column_type: ColumnType,
+    pub alignment: Alignment,
+}
+/// Type of the values in a column
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColumnType {
+    String = 0x0,
+    Integer = 0x1,
+    Float = 0x2,
+    Bytes = 0x3,
+    Date = 0x4,
+}
+impl ColumnType {
+    pub const ALL: [ColumnType; 5] = [
+        ColumnType::String,
+        ColumnType::Integer,
+        ColumnType::Float,
+        ColumnType::Bytes,
+        ColumnType::Date,
+    ];
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|column_type| *column_type as u32 == value)
+    }
+}
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Alignment {
+    Left = 0x0,
+    Right = 0x1,
+    Center = 0x2,
+}
+impl Alignment {
+    pub const ALL: [Alignment; 3] = [Alignment::Left, Alignment::Right, Alignment::Center];
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|alignment| *alignment as u32 == value)
+    }
+}
+/// A value in a table. `Display` formats it for people, the CSV and TSV exports keep full
+/// precision instead.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Cell {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    /// Size in bytes
+    Bytes(u64),
+    /// Seconds since the unix epoch in UTC
+    Date(i64),
+}
+impl Cell {
+    pub fn column_type(&self) -> ColumnType {
+        match self {
+            Cell::String(_) => ColumnType::String,
+            Cell::Integer(_) => ColumnType::Integer,
+            Cell::Float(_) => ColumnType::Float,
+            Cell::Bytes(_) => ColumnType::Bytes,
+            Cell::Date(_) => ColumnType::Date,
+        }
+    }
+    /// Orders cells of the same type, NaN goes after every other float. Cells of different
+    /// types compare by type.
+    pub fn compare(&self, other: &Cell) -> Ordering {
+        match (self, other) {
+            (Cell::String(a), Cell::String(b)) => a.cmp(b),
+            (Cell::Integer(a), Cell::Integer(b)) => a.cmp(b),
+            (Cell::Float(a), Cell::Float(b)) => a
+                .partial_cmp(b)
+                .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan())),
+            (Cell::Bytes(a), Cell::Bytes(b)) => a.cmp(b),
+            (Cell::Date(a), Cell::Date(b)) => a.cmp(b),
+            (a, b) => (a.column_type() as u32).cmp(&(b.column_type() as u32)),
+        }
+    }
+    /// Value as written to CSV and TSV, sizes are exact and dates are ISO 8601
+    fn field(&self) -> String {
+        match self {
+            Cell::Bytes(bytes) => bytes.to_string(),
+            Cell::Date(seconds) => {
+                let (year, month, day, hour, minute, second) = civil_time(*seconds);
+                format!(
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+                    year, month, day, hour, minute, second
+                )
+            }
+            cell => cell.to_string(),
+        }
+    }
+}
+impl From<&str> for Cell {
+    fn from(value: &str) -> Self {
+        Cell::String(value.to_string())
+    }
+}
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cell::String(value) => write!(f, "{}", value),
+            Cell::Integer(value) => write!(f, "{}", value),
+            Cell::Float(value) => write!(f, "{}", value),
+            Cell::Bytes(bytes) => {
+                const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+                if *bytes < 1024 {
+                    return write!(f, "{} B", bytes);
+                }
+                let mut size = *bytes as f64 / 1024.0;
+                let mut unit = 0;
+                while size >= 1024.0 && unit + 1 < UNITS.len() {
+                    size /= 1024.0;
+                    unit += 1;
+                }
+                write!(f, "{:.1} {}", size, UNITS[unit])
+            }
+            Cell::Date(seconds) => {
+                let (year, month, day, hour, minute, second) = civil_time(*seconds);
+                write!(
+                    f,
+                    "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                    year, month, day, hour, minute, second
+                )
+            }
+        }
+    }
+}
+/// Splits seconds since the unix epoch into year, month, day, hour, minute and second of the
+/// proleptic Gregorian calendar
+fn civil_time(seconds: i64) -> (i64, u32, u32, u32, u32, u32) {
+    let days = seconds.div_euclid(86400);
+    let time = seconds.rem_euclid(86400) as u32;
+    // days to civil date from http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn processes() -> Table {
+        Table::new()
+            .column("name", ColumnType::String, Alignment::Left)
+            .column("pid", ColumnType::Integer, Alignment::Right)
+            .column("memory", ColumnType::Bytes, Alignment::Right)
+            .row(vec!["init".into(), Cell::Integer(1), Cell::Bytes(2048)])
+            .row(vec!["sh, \"login\"".into(), Cell::Integer(30), Cell::Bytes(100)])
+    }
+    #[test]
+    fn sort() {
+        let mut table = processes();
+        table.sort_by(2, true);
+        assert_eq!(table.rows()[0][1], Cell::Integer(30));
+        table.sort_by(1, true);
+        assert_eq!(table.rows()[0][1], Cell::Integer(1));
+        table.sort_by(1, false);
+        assert_eq!(table.rows()[0][1], Cell::Integer(30));
+    }
+    #[test]
+    fn export() {
+        let table = processes();
+        assert_eq!(
+            table.to_csv(),
+            "name,pid,memory\ninit,1,2048\n\"sh, \"\"login\"\"\",30,100\n"
+        );
+        assert_eq!(
+            table.to_tsv(),
+            "name\tpid\tmemory\ninit\t1\t2048\nsh, \"login\"\t30\t100\n"
+        );
+        assert_eq!(
+            table.plain_text(),
+            "name         pid   memory\ninit           1  2.0 KiB\nsh, \"login\"   30    100 B\n"
+        );
+    }
+    #[test]
+    fn dates() {
+        assert_eq!(Cell::Date(0).to_string(), "1970-01-01 00:00:00");
+        assert_eq!(Cell::Date(951782400 + 3661).field(), "2000-02-29T01:01:01Z");
+        assert_eq!(Cell::Date(-1).to_string(), "1969-12-31 23:59:59");
+    }
+    #[test]
+    #[should_panic]
+    fn mismatched_cell() {
+        processes().row(vec![Cell::Integer(2), Cell::Integer(2), Cell::Bytes(0)]);
+    }
+}